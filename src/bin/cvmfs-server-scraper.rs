@@ -0,0 +1,400 @@
+//! Command line interface for scraping CVMFS servers.
+//!
+//! Servers are given with `--server` or in an inventory file (`--config`), and the results
+//! are printed in one of the output formats. The exit code reports whether any server or
+//! repository failed, see the `EXIT_*` constants.
+//!
+//! This binary requires the `cli` feature.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use chrono::DateTime;
+use clap::{Parser, ValueEnum};
+
+use cvmfs_server_scraper::{
+    Hostname, Inventory, RecordingFetcher, ReplayFetcher, ScrapeReport, ScrapedServer,
+    ScraperCommon, Server, ServerBackendType, ServerType,
+};
+
+/// Every server and repository was scraped.
+const EXIT_OK: u8 = 0;
+/// At least one server failed to scrape.
+const EXIT_SERVER_FAILED: u8 = 1;
+/// The arguments or the configuration file were invalid.
+const EXIT_USAGE: u8 = 2;
+/// Every server was scraped, but at least one repository failed.
+const EXIT_REPOSITORY_FAILED: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    /// The output() of every server
+    Human,
+    /// A ScrapeReport as JSON
+    Json,
+    /// One row per repository
+    Table,
+}
+
+/// Scrape CVMFS servers for metadata about the servers and their repositories.
+///
+/// Exit codes: 0 if everything was scraped, 1 if any server failed, 2 on invalid arguments or
+/// configuration, and 3 if all servers were scraped but any repository failed.
+#[derive(Debug, Parser)]
+#[command(name = "cvmfs-server-scraper", version)]
+struct Args {
+    /// Address (hostname, IP address or hostname@IP to pin the hostname) or base URL (e.g.
+    /// https://cdn.example.com:8443/cvmfs) of a server to scrape, may be repeated
+    #[arg(short, long = "server", value_name = "ADDRESS|URL")]
+    servers: Vec<String>,
+
+    /// Server type of the servers given with --server (stratum0, stratum1 or sync-server)
+    #[arg(long, default_value = "stratum1")]
+    server_type: ServerType,
+
+    /// Backend type of the servers given with --server (cvmfs, s3 or auto-detect)
+    #[arg(long, default_value = "auto-detect")]
+    backend: ServerBackendType,
+
+    /// Inventory file (TOML, YAML or JSON) with servers and scrape options
+    #[arg(short, long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Repository to scrape even if not listed in repositories.json, may be repeated
+    #[arg(short = 'r', long = "repository", value_name = "REPOSITORY")]
+    repositories: Vec<String>,
+
+    /// Repository to never scrape, may be repeated
+    #[arg(short, long = "ignore-repository", value_name = "REPOSITORY")]
+    ignored_repositories: Vec<String>,
+
+    /// Only scrape the repositories given with --repository
+    #[arg(long)]
+    only_forced_repositories: bool,
+
+    /// GeoAPI server to query with, may be repeated (defaults to the EESSI Stratum1 servers)
+    #[arg(short, long = "geoapi-server", value_name = "HOSTNAME")]
+    geoapi_servers: Vec<String>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Human)]
+    output: OutputFormat,
+
+    /// Record every request and response of the scrape to a cassette file
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Replay the responses of a cassette file recorded with --record instead of contacting the
+    /// servers (give the same servers and options as when recording)
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+}
+
+/// Merge the arguments into the inventory.
+///
+/// Servers and repositories from the arguments are added to those of the inventory, GeoAPI
+/// servers from the arguments replace those of the inventory.
+fn merge_args(mut inventory: Inventory, args: &Args) -> Result<Inventory, String> {
+    for server in &args.servers {
+        let server = if server.contains("://") {
+            Server::from_url(args.server_type, args.backend, server)
+                .map_err(|e| format!("Invalid server {}: {}", server, e))?
+        } else {
+            let address = server
+                .parse()
+                .map_err(|e| format!("Invalid server {}: {}", server, e))?;
+            Server::from_address(args.server_type, args.backend, address)
+        };
+        inventory.servers.push(server);
+    }
+    inventory
+        .forced_repositories
+        .extend(args.repositories.iter().cloned());
+    inventory
+        .ignored_repositories
+        .extend(args.ignored_repositories.iter().cloned());
+    inventory.only_scrape_forced_repositories |= args.only_forced_repositories;
+    if !args.geoapi_servers.is_empty() {
+        let geoapi_servers = args
+            .geoapi_servers
+            .iter()
+            .map(|h| h.parse())
+            .collect::<Result<Vec<Hostname>, _>>()
+            .map_err(|e| format!("Invalid GeoAPI server: {}", e))?;
+        inventory.geoapi_servers = Some(geoapi_servers);
+    }
+    Ok(inventory)
+}
+
+async fn run(args: &Args) -> Result<Vec<ScrapedServer>, String> {
+    let inventory = match &args.config {
+        Some(path) => Inventory::load(path).map_err(|e| e.to_string())?,
+        None => Inventory::default(),
+    };
+    let inventory = merge_args(inventory, args)?;
+
+    if inventory.servers.is_empty() {
+        return Err("No servers given, use --server or --config".to_string());
+    }
+
+    let mut scraper = inventory.scraper();
+    let mut recorder = None;
+    if args.record.is_some() {
+        let fetcher = scraper.http_fetcher().map_err(|e| e.to_string())?;
+        let recording = RecordingFetcher::new(fetcher);
+        scraper = scraper.fetcher(recording.clone());
+        recorder = Some(recording);
+    }
+    if let Some(path) = &args.replay {
+        scraper = scraper.fetcher(ReplayFetcher::from_file(path).map_err(|e| e.to_string())?);
+    }
+    let scraper = scraper.validate().map_err(|e| e.to_string())?;
+
+    let servers = scraper.scrape().await;
+    if let (Some(recorder), Some(path)) = (recorder, &args.record) {
+        recorder.save(path).map_err(|e| e.to_string())?;
+    }
+    Ok(servers)
+}
+
+fn exit_code(servers: &[ScrapedServer]) -> u8 {
+    let mut code = EXIT_OK;
+    for server in servers {
+        match server {
+            ScrapedServer::Failed(_) => return EXIT_SERVER_FAILED,
+            ScrapedServer::Populated(populated) => {
+                if populated.has_failed_repositories() {
+                    code = EXIT_REPOSITORY_FAILED;
+                }
+            }
+        }
+    }
+    code
+}
+
+fn render_table(servers: &[ScrapedServer]) -> String {
+    let mut rows = vec![[
+        "SERVER".to_string(),
+        "TYPE".to_string(),
+        "REPOSITORY".to_string(),
+        "REVISION".to_string(),
+        "PUBLISHED".to_string(),
+        "STATUS".to_string(),
+    ]];
+
+    for server in servers {
+        match server {
+            ScrapedServer::Populated(populated) => {
+                let server_type = format!("{:?}", populated.server_type);
+                if populated.repositories.is_empty() && populated.failed_repositories.is_empty() {
+                    rows.push([
+                        populated.key(),
+                        server_type.clone(),
+                        "-".to_string(),
+                        "-".to_string(),
+                        "-".to_string(),
+                        "no repositories".to_string(),
+                    ]);
+                }
+                for repo in &populated.repositories {
+                    let published = DateTime::from_timestamp(repo.manifest.t, 0)
+                        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                        .unwrap_or_else(|| repo.manifest.t.to_string());
+                    rows.push([
+                        populated.key(),
+                        server_type.clone(),
+                        repo.name.clone(),
+                        repo.revision().to_string(),
+                        published,
+                        "ok".to_string(),
+                    ]);
+                }
+                for repo in &populated.failed_repositories {
+                    rows.push([
+                        populated.key(),
+                        server_type.clone(),
+                        repo.name.clone(),
+                        "-".to_string(),
+                        "-".to_string(),
+                        format!("failed: {}", repo.error),
+                    ]);
+                }
+            }
+            ScrapedServer::Failed(failed) => rows.push([
+                failed.key(),
+                format!("{:?}", failed.server_type),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                format!("failed: {}", failed.error),
+            ]),
+        }
+    }
+
+    let mut widths = [0; 6];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<String>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let servers = match run(&args).await {
+        Ok(servers) => servers,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    match args.output {
+        OutputFormat::Human => {
+            for server in &servers {
+                match server {
+                    ScrapedServer::Populated(populated) => populated.output(),
+                    ScrapedServer::Failed(failed) => failed.output(),
+                }
+                println!();
+            }
+        }
+        OutputFormat::Json => match ScrapeReport::new(servers.clone()).to_json() {
+            Ok(json) => println!("{}", json),
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                return ExitCode::from(EXIT_USAGE);
+            }
+        },
+        OutputFormat::Table => println!("{}", render_table(&servers)),
+    }
+
+    ExitCode::from(exit_code(&servers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cvmfs_server_scraper::{CVMFSScraperError, ScrapeError};
+
+    fn failed(hostname: &str) -> ScrapedServer {
+        let server = Server::new(
+            ServerType::Stratum1,
+            ServerBackendType::CVMFS,
+            hostname.parse().unwrap(),
+        );
+        ScrapedServer::Failed(server.to_failed_server(CVMFSScraperError::from(
+            ScrapeError::EmptyRepositoryList(hostname.to_string()),
+        )))
+    }
+
+    #[test]
+    fn test_args_are_merged_into_inventory() {
+        let args = Args::parse_from([
+            "cvmfs-server-scraper",
+            "--server",
+            "s1.example.com",
+            "--server-type",
+            "sync-server",
+            "--backend",
+            "s3",
+            "-r",
+            "software.eessi.io",
+            "--only-forced-repositories",
+        ]);
+        let config = merge_args(Inventory::default(), &args).unwrap();
+        assert_eq!(config.servers.len(), 1);
+        assert_eq!(config.servers[0].server_type, ServerType::SyncServer);
+        assert_eq!(config.servers[0].backend_type, ServerBackendType::S3);
+        assert_eq!(config.forced_repositories, vec!["software.eessi.io"]);
+        assert!(config.only_scrape_forced_repositories);
+    }
+
+    #[test]
+    fn test_record_and_replay_are_exclusive() {
+        let args = Args::try_parse_from([
+            "cvmfs-server-scraper",
+            "--server",
+            "s1.example.com",
+            "--record",
+            "a.json",
+            "--replay",
+            "b.json",
+        ]);
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn test_server_urls_are_accepted() {
+        let args = Args::parse_from([
+            "cvmfs-server-scraper",
+            "-s",
+            "s1.example.com",
+            "-s",
+            "https://cdn.example.com:8443/bucket/cvmfs",
+            "-s",
+            "s1.example.com@192.0.2.1",
+        ]);
+        let config = merge_args(Inventory::default(), &args).unwrap();
+        assert_eq!(config.servers[0].base_url(), "http://s1.example.com/cvmfs");
+        assert_eq!(
+            config.servers[1].base_url(),
+            "https://cdn.example.com:8443/bucket/cvmfs"
+        );
+        assert_eq!(
+            config.servers[2].hostname.ip(),
+            Some("192.0.2.1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_invalid_server_is_rejected() {
+        let args = Args::parse_from(["cvmfs-server-scraper", "--server", "not_a_host"]);
+        assert!(merge_args(Inventory::default(), &args).is_err());
+    }
+
+    #[test]
+    fn test_geoapi_servers_replace_those_of_the_inventory() {
+        let inventory = Inventory {
+            geoapi_servers: Some(vec!["a.example.com".parse().unwrap()]),
+            ..Inventory::default()
+        };
+        let args = Args::parse_from(["cvmfs-server-scraper", "-g", "b.example.com"]);
+        let inventory = merge_args(inventory, &args).unwrap();
+        assert_eq!(
+            inventory.geoapi_servers,
+            Some(vec!["b.example.com".parse().unwrap()])
+        );
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(&[]), EXIT_OK);
+        assert_eq!(exit_code(&[failed("s1.example.com")]), EXIT_SERVER_FAILED);
+    }
+
+    #[test]
+    fn test_render_table_lists_failed_servers() {
+        let table = render_table(&[failed("s1.example.com")]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("SERVER"));
+        assert!(lines[1].starts_with("s1.example.com"));
+        assert!(lines[1].contains("failed: "));
+    }
+}
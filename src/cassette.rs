@@ -0,0 +1,434 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::errors::{CassetteError, FetchError, ScrapeError};
+use crate::fetcher::{normalize_url, FetchResponse, Fetcher, GEOAPI_PLACEHOLDER};
+
+/// The current version of the `Cassette` format.
+pub const CASSETTE_VERSION: u32 = 1;
+
+/// A recording of every fetch made during one or more scrapes.
+///
+/// Cassettes are written by a `RecordingFetcher` and replayed by a `ReplayFetcher`, e.g. to
+/// capture what a misbehaving server served and reproduce the scrape later.
+///
+/// Fields:
+///
+/// - version: The version of the cassette format, see `CASSETTE_VERSION`
+/// - recorded_at: When the recording started
+/// - interactions: Every fetch, in the order the responses were received
+///
+/// ### Example
+///
+/// ```rust,no_run
+/// use cvmfs_server_scraper::{ClientConfig, RecordingFetcher, ReplayFetcher, ReqwestFetcher,
+///     Scraper, ScraperCommon, Server, ServerBackendType, ServerType};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let servers = vec![Server::new(
+///         ServerType::Stratum1,
+///         ServerBackendType::CVMFS,
+///         "aws-eu-central-s1.eessi.science".parse()?,
+///     )];
+///
+///     // Record a scrape...
+///     let recorder = RecordingFetcher::new(ReqwestFetcher::new(&ClientConfig::default())?);
+///     let recorded = Scraper::new()
+///         .fetcher(recorder.clone())
+///         .with_servers(servers.clone())
+///         .validate()?
+///         .scrape()
+///         .await;
+///     recorder.save("scrape.cassette.json")?;
+///
+///     // ...and replay it later, with the same servers and options.
+///     let replayed = Scraper::new()
+///         .fetcher(ReplayFetcher::from_file("scrape.cassette.json")?)
+///         .with_servers(servers)
+///         .validate()?
+///         .scrape()
+///         .await;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    pub version: u32,
+    pub recorded_at: DateTime<Utc>,
+    pub interactions: Vec<Interaction>,
+}
+
+/// A single recorded fetch.
+///
+/// Fields:
+///
+/// - url: The URL that was fetched
+/// - response: The response, if one was received
+/// - error: The error, if no response was received (e.g. the connection was refused)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<FetchResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<FetchError>,
+}
+
+impl Cassette {
+    /// Create an empty cassette, recorded now.
+    pub fn new() -> Self {
+        Cassette {
+            version: CASSETTE_VERSION,
+            recorded_at: Utc::now(),
+            interactions: Vec::new(),
+        }
+    }
+
+    /// Serialize the cassette to JSON.
+    pub fn to_json(&self) -> Result<String, ScrapeError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Deserialize a cassette from JSON.
+    ///
+    /// Fails if the cassette was written with a newer, unsupported, version of the format.
+    pub fn from_json(json: &str) -> Result<Self, CassetteError> {
+        Cassette::parse(json, Path::new("<json>"))
+    }
+
+    /// Read a cassette from a file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CassetteError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|e| CassetteError::Io {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        Cassette::parse(&json, path)
+    }
+
+    /// Write the cassette to a file, replacing it if it exists.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CassetteError> {
+        let path = path.as_ref();
+        let json = self.to_json().map_err(|e| CassetteError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        std::fs::write(path, json).map_err(|e| CassetteError::Io {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    fn parse(json: &str, path: &Path) -> Result<Self, CassetteError> {
+        let cassette: Cassette = serde_json::from_str(json).map_err(|e| CassetteError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        if cassette.version > CASSETTE_VERSION {
+            return Err(CassetteError::UnsupportedVersion {
+                found: cassette.version,
+                supported: CASSETTE_VERSION,
+            });
+        }
+        Ok(cassette)
+    }
+}
+
+impl Default for Cassette {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A fetcher recording every fetch made through another fetcher.
+///
+/// Clones share the recording, so keep a clone to save the cassette after the scrape (see the
+/// example of `Cassette`). Pinned fetchers record to the same cassette.
+#[derive(Debug, Clone)]
+pub struct RecordingFetcher {
+    inner: Arc<dyn Fetcher>,
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+impl RecordingFetcher {
+    /// Record the fetches made through the given fetcher.
+    pub fn new<F: Fetcher + 'static>(inner: F) -> Self {
+        RecordingFetcher {
+            inner: Arc::new(inner),
+            cassette: Arc::new(Mutex::new(Cassette::new())),
+        }
+    }
+
+    /// The cassette recorded so far.
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap().clone()
+    }
+
+    /// Write the cassette recorded so far to a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CassetteError> {
+        self.cassette().save(path)
+    }
+}
+
+#[async_trait]
+impl Fetcher for RecordingFetcher {
+    async fn fetch(&self, url: &str) -> Result<FetchResponse, FetchError> {
+        let result = self.inner.fetch(url).await;
+        let (response, error) = match &result {
+            Ok(response) => (Some(response.clone()), None),
+            Err(error) => (None, Some(error.clone())),
+        };
+        self.cassette
+            .lock()
+            .unwrap()
+            .interactions
+            .push(Interaction {
+                url: url.to_string(),
+                response,
+                error,
+            });
+        result
+    }
+
+    fn pinned(&self, hostname: &str, ip: IpAddr) -> Result<Option<Arc<dyn Fetcher>>, ScrapeError> {
+        Ok(self.inner.pinned(hostname, ip)?.map(|inner| {
+            Arc::new(RecordingFetcher {
+                inner,
+                cassette: self.cassette.clone(),
+            }) as Arc<dyn Fetcher>
+        }))
+    }
+
+    fn geoapi_cache_buster(&self) -> String {
+        self.inner.geoapi_cache_buster()
+    }
+
+    fn scrape_time(&self) -> Option<DateTime<Utc>> {
+        self.inner.scrape_time()
+    }
+}
+
+/// A fetcher replaying the responses of a `Cassette`.
+///
+/// Fetches are matched on the URL, with the random segment of GeoAPI URLs ignored (see
+/// `GEOAPI_PLACEHOLDER`). If a URL was fetched more than once while recording (e.g. because it
+/// was retried), the recorded responses are replayed in order and the last one is repeated.
+/// URLs that were never recorded fail with a request error, which is not retried.
+///
+/// Replaying the cassette with the same servers and options gives the same scrape results every
+/// time, apart from the scrape durations (which equality ignores). The scrape is taken to happen
+/// at `Cassette::recorded_at` (see `Fetcher::scrape_time`), so checks such as whitelist expiry
+/// do not change as the cassette ages. GeoAPI URLs use `GEOAPI_PLACEHOLDER` as their random
+/// segment, so repeated replays also record the same URLs.
+#[derive(Debug)]
+pub struct ReplayFetcher {
+    interactions: Mutex<HashMap<String, (Vec<Interaction>, usize)>>,
+    recorded_at: DateTime<Utc>,
+}
+
+impl ReplayFetcher {
+    /// Replay the given cassette.
+    pub fn new(cassette: Cassette) -> Self {
+        let mut interactions: HashMap<String, (Vec<Interaction>, usize)> = HashMap::new();
+        for interaction in cassette.interactions {
+            interactions
+                .entry(normalize_url(&interaction.url))
+                .or_default()
+                .0
+                .push(interaction);
+        }
+        ReplayFetcher {
+            interactions: Mutex::new(interactions),
+            recorded_at: cassette.recorded_at,
+        }
+    }
+
+    /// Replay the cassette in the given file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, CassetteError> {
+        Ok(ReplayFetcher::new(Cassette::load(path)?))
+    }
+}
+
+#[async_trait]
+impl Fetcher for ReplayFetcher {
+    async fn fetch(&self, url: &str) -> Result<FetchResponse, FetchError> {
+        let mut interactions = self.interactions.lock().unwrap();
+        let Some((recorded, next)) = interactions.get_mut(&normalize_url(url)) else {
+            warn!("No recorded response for {}", url);
+            return Err(FetchError::request(
+                url,
+                format!("No recorded response for {}", url),
+            ));
+        };
+        let interaction = &recorded[(*next).min(recorded.len() - 1)];
+        *next += 1;
+        debug!("Replaying {} as {}", url, interaction.url);
+        match (&interaction.response, &interaction.error) {
+            (Some(response), _) => Ok(response.clone()),
+            (None, Some(error)) => Err(error.clone()),
+            (None, None) => Err(FetchError::request(
+                url,
+                format!("Recorded interaction for {} has no outcome", url),
+            )),
+        }
+    }
+
+    fn geoapi_cache_buster(&self) -> String {
+        GEOAPI_PLACEHOLDER.to_string()
+    }
+
+    fn scrape_time(&self) -> Option<DateTime<Utc>> {
+        Some(self.recorded_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorKind;
+    use crate::fetcher::DirectoryFetcher;
+    use crate::models::{Hostname, ScrapedServer, Server, ServerBackendType, ServerType};
+    use crate::scraper::{Scraper, ScraperCommon};
+
+    fn cassette(interactions: Vec<Interaction>) -> Cassette {
+        Cassette {
+            interactions,
+            ..Cassette::new()
+        }
+    }
+
+    fn interaction(url: &str, response: FetchResponse) -> Interaction {
+        Interaction {
+            url: url.to_string(),
+            response: Some(response),
+            error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_repeats_the_last_response() {
+        let url = "http://s1.example.org/cvmfs/info/v1/meta.json";
+        let fetcher = ReplayFetcher::new(cassette(vec![
+            interaction(url, FetchResponse::new(503, "unavailable")),
+            interaction(url, FetchResponse::ok("{}")),
+        ]));
+        assert_eq!(fetcher.fetch(url).await.unwrap().status, 503);
+        assert_eq!(fetcher.fetch(url).await.unwrap().status, 200);
+        assert_eq!(fetcher.fetch(url).await.unwrap().status, 200);
+    }
+
+    #[tokio::test]
+    async fn test_replay_ignores_the_geoapi_random_segment() {
+        let fetcher = ReplayFetcher::new(cassette(vec![interaction(
+            "http://s1.example.org/cvmfs/repo/api/v1.0/geo/Xy12Ab34Cd56/a.org,b.org",
+            FetchResponse::ok("2,1"),
+        )]));
+        let url = format!(
+            "http://s1.example.org/cvmfs/repo/api/v1.0/geo/{}/a.org,b.org",
+            fetcher.geoapi_cache_buster()
+        );
+        assert_eq!(fetcher.fetch(&url).await.unwrap().body, b"2,1");
+    }
+
+    #[tokio::test]
+    async fn test_replay_of_errors_and_unknown_urls() {
+        let url = "http://s1.example.org/cvmfs/info/v1/repositories.json";
+        let fetcher = ReplayFetcher::new(cassette(vec![Interaction {
+            url: url.to_string(),
+            response: None,
+            error: Some(FetchError::connect(url, "connection refused")),
+        }]));
+        assert_eq!(
+            fetcher.fetch(url).await.unwrap_err().kind,
+            ErrorKind::Connect
+        );
+        let unknown = fetcher
+            .fetch("http://s2.example.org/cvmfs/info/v1/repositories.json")
+            .await
+            .unwrap_err();
+        assert_eq!(unknown.kind, ErrorKind::Request);
+    }
+
+    #[test]
+    fn test_cassette_rejects_newer_versions() {
+        let mut cassette = Cassette::new();
+        cassette.version = CASSETTE_VERSION + 1;
+        let json = cassette.to_json().unwrap();
+        assert!(matches!(
+            Cassette::from_json(&json),
+            Err(CassetteError::UnsupportedVersion { .. })
+        ));
+    }
+
+    async fn scrape<F: Fetcher + 'static>(fetcher: F) -> Vec<ScrapedServer> {
+        let scraper = Scraper::new().fetcher(fetcher).fetch_whitelists(true);
+        #[cfg(feature = "signatures")]
+        let scraper = scraper.verify_signatures(true);
+        scraper
+            .with_servers(vec![Server::new(
+                ServerType::Stratum1,
+                ServerBackendType::CVMFS,
+                Hostname::try_from("stratum1.example.org").unwrap(),
+            )])
+            .validate()
+            .unwrap()
+            .scrape()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay_a_scrape() {
+        let mirror = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/mirror");
+        let recorder = RecordingFetcher::new(DirectoryFetcher::new(mirror).per_host(true));
+        let recorded = scrape(recorder.clone()).await;
+        assert!(recorded[0].is_ok());
+
+        let json = recorder.cassette().to_json().unwrap();
+        let cassette = Cassette::from_json(&json).unwrap();
+        assert!(cassette
+            .interactions
+            .iter()
+            .any(|i| i.response.as_ref().is_some_and(|r| r.status == 404)));
+
+        let first = scrape(ReplayFetcher::new(cassette.clone())).await;
+        let second = scrape(ReplayFetcher::new(cassette)).await;
+        assert_eq!(first, second);
+
+        // The replay only differs from the recording in the GeoAPI random segment.
+        let first = first[0].clone().get_populated_server().unwrap();
+        let recorded = recorded[0].clone().get_populated_server().unwrap();
+        assert_eq!(first.repositories, recorded.repositories);
+        assert_eq!(first.geoapi, recorded.geoapi);
+        assert_eq!(first.metadata, recorded.metadata);
+    }
+
+    #[cfg(feature = "signatures")]
+    #[tokio::test]
+    async fn test_replay_checks_whitelists_at_the_recording_time() {
+        let mirror = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/mirror");
+        let recorder = RecordingFetcher::new(DirectoryFetcher::new(mirror).per_host(true));
+        scrape(recorder.clone()).await;
+
+        // The fixture whitelist of software.eessi.io was valid in June 2024.
+        let mut cassette = recorder.cassette();
+        cassette.recorded_at = "2024-06-20T00:00:00Z".parse().unwrap();
+        let server = scrape(ReplayFetcher::new(cassette))
+            .await
+            .remove(0)
+            .get_populated_server()
+            .unwrap();
+        let software = server
+            .repositories
+            .iter()
+            .find(|repo| repo.name == "software.eessi.io")
+            .unwrap();
+        assert!(software.whitelist_verification.as_ref().unwrap().is_valid());
+    }
+}
@@ -0,0 +1,736 @@
+use log::{debug, trace};
+use rand::{rng, Rng};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::errors::{DnsError, ErrorKind, FetchError, ScrapeError};
+use crate::fetcher::{Fetcher, ReqwestFetcher};
+
+/// The default user agent sent with every request.
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// How the HTTP client should handle redirects.
+///
+/// None: Do not follow redirects, the redirect response is treated as the final response.
+/// Limited: Follow up to the given number of redirects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedirectPolicy {
+    None,
+    Limited(usize),
+}
+
+/// Configuration for the HTTP client used by the scraper.
+///
+/// A single client is built from this configuration and shared by every fetch performed during a
+/// scrape, including repositories.json, meta.json, the per-repository files and GeoAPI queries.
+///
+/// Defaults:
+/// - connect_timeout: 10 seconds
+/// - timeout: 30 seconds (total time for a request, including reading the body)
+/// - user_agent: `cvmfs_server_scraper/<version>`
+/// - proxy: None (the standard proxy environment variables are still honored by reqwest)
+/// - headers: None
+/// - redirect_policy: Limited(10)
+///
+/// ### Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use cvmfs_server_scraper::{ClientConfig, RedirectPolicy};
+///
+/// let config = ClientConfig::new()
+///     .connect_timeout(Duration::from_secs(5))
+///     .timeout(Duration::from_secs(20))
+///     .user_agent("my-monitoring/1.0")
+///     .proxy("http://proxy.example.com:3128")
+///     .header("X-Monitoring", "yes")
+///     .redirect_policy(RedirectPolicy::None);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientConfig {
+    pub connect_timeout: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub user_agent: String,
+    pub proxy: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub redirect_policy: RedirectPolicy,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientConfig {
+    /// Create a new ClientConfig with the default settings.
+    pub fn new() -> Self {
+        ClientConfig {
+            connect_timeout: Some(Duration::from_secs(10)),
+            timeout: Some(Duration::from_secs(30)),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            proxy: None,
+            headers: Vec::new(),
+            redirect_policy: RedirectPolicy::Limited(10),
+        }
+    }
+
+    /// Set the timeout for establishing a connection.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the total timeout for a request, from connecting until the body has been read.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the user agent sent with every request.
+    pub fn user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Route all requests through the given proxy (e.g. `http://proxy.example.com:3128`).
+    pub fn proxy<S: Into<String>>(mut self, proxy: S) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// Add an extra header sent with every request. May be called multiple times.
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the redirect policy.
+    pub fn redirect_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.redirect_policy = policy;
+        self
+    }
+
+    /// Build a reqwest client from the configuration.
+    ///
+    /// This fails if the proxy URL or any of the extra headers are invalid.
+    pub fn build(&self) -> Result<reqwest::Client, ScrapeError> {
+        self.builder()?
+            .build()
+            .map_err(|e| ScrapeError::ClientConfigError(e.to_string()))
+    }
+
+    pub(crate) fn builder(&self) -> Result<reqwest::ClientBuilder, ScrapeError> {
+        trace!("Building HTTP client from {:?}", self);
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                ScrapeError::ClientConfigError(format!("Invalid header name '{}': {}", name, e))
+            })?;
+            let value = HeaderValue::from_str(value).map_err(|e| {
+                ScrapeError::ClientConfigError(format!("Invalid header value '{}': {}", value, e))
+            })?;
+            headers.append(name, value);
+        }
+
+        let redirect = match self.redirect_policy {
+            RedirectPolicy::None => reqwest::redirect::Policy::none(),
+            RedirectPolicy::Limited(max) => reqwest::redirect::Policy::limited(max),
+        };
+
+        let mut builder = reqwest::Client::builder()
+            .dns_resolver(Arc::new(Resolver))
+            .user_agent(&self.user_agent)
+            .default_headers(headers)
+            .redirect(redirect);
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy).map_err(|e| {
+                ScrapeError::ClientConfigError(format!("Invalid proxy '{}': {}", proxy, e))
+            })?;
+            builder = builder.proxy(proxy);
+        }
+
+        Ok(builder)
+    }
+}
+
+/// Resolves hostnames with the system resolver, like the default resolver of reqwest, but fails
+/// with a `DnsError` so that DNS failures can be classified.
+struct Resolver;
+
+impl reqwest::dns::Resolve for Resolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let name = name.as_str().to_string();
+        Box::pin(async move {
+            let resolved = tokio::net::lookup_host((name.as_str(), 0))
+                .await
+                .map(|addrs| addrs.collect::<Vec<_>>());
+            match resolved {
+                Ok(addrs) => Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs),
+                Err(source) => Err(Box::new(DnsError { name, source }) as _),
+            }
+        })
+    }
+}
+
+/// Classes of errors that may be retried.
+///
+/// Connect: The connection to the server could not be established (includes DNS failures).
+/// Timeout: The request timed out.
+/// ServerError: The server responded with a 5xx status code.
+///
+/// Other errors, such as a 404 or a response that cannot be parsed, are never retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetryOn {
+    Connect,
+    Timeout,
+    ServerError,
+}
+
+/// The retry policy for fetches.
+///
+/// A failed fetch is retried if the error is of one of the classes in `retry_on`, until
+/// `max_attempts` attempts have been made. Between attempts the scraper sleeps for an exponentially
+/// increasing delay, `base_delay * 2^(attempt - 1)`, randomized by up to `jitter` (a fraction
+/// between 0.0 and 1.0) in either direction, and then capped at `max_delay`.
+///
+/// Defaults:
+/// - max_attempts: 3
+/// - base_delay: 500 milliseconds
+/// - max_delay: 10 seconds
+/// - jitter: 0.2
+/// - retry_on: Connect, Timeout, ServerError
+///
+/// ### Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use cvmfs_server_scraper::{RetryOn, RetryPolicy};
+///
+/// let policy = RetryPolicy::new()
+///     .max_attempts(5)
+///     .base_delay(Duration::from_millis(200))
+///     .jitter(0.5)
+///     .retry_on(vec![RetryOn::Connect, RetryOn::Timeout]);
+///
+/// // Disable retries entirely.
+/// let no_retries = RetryPolicy::no_retries();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: f64,
+    pub retry_on: Vec<RetryOn>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// Create a new RetryPolicy with the default settings.
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.2,
+            retry_on: vec![RetryOn::Connect, RetryOn::Timeout, RetryOn::ServerError],
+        }
+    }
+
+    /// A policy that makes a single attempt for every fetch.
+    pub fn no_retries() -> Self {
+        RetryPolicy::new().max_attempts(1)
+    }
+
+    /// Set the maximum number of attempts, including the first one. Zero is treated as one.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Set the delay before the first retry.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Set the upper bound for the delay between attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Set the jitter as a fraction of the delay. The value is clamped to 0.0..=1.0.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set which classes of errors are retried.
+    pub fn retry_on(mut self, retry_on: Vec<RetryOn>) -> Self {
+        self.retry_on = retry_on;
+        self
+    }
+
+    /// Check if the given error should be retried under this policy.
+    pub fn is_retryable(&self, error: &FetchError) -> bool {
+        match error.kind {
+            ErrorKind::Timeout => self.retry_on.contains(&RetryOn::Timeout),
+            ErrorKind::Connect | ErrorKind::Dns => self.retry_on.contains(&RetryOn::Connect),
+            _ => error.is_server_error() && self.retry_on.contains(&RetryOn::ServerError),
+        }
+    }
+
+    /// The delay before the given retry, where `attempt` is the attempt that just failed (1-based).
+    pub fn delay_for_attempt(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self.base_delay.saturating_mul(1u32 << exponent);
+        if self.jitter <= 0.0 {
+            return delay.min(self.max_delay);
+        }
+        let factor = rng().random_range((1.0 - self.jitter)..=(1.0 + self.jitter));
+        // The uncapped delay can be large enough for mul_f64 to overflow.
+        Duration::try_from_secs_f64(delay.as_secs_f64() * factor)
+            .unwrap_or(Duration::MAX)
+            .min(self.max_delay)
+    }
+}
+
+/// A record of a single fetch, including any retries.
+///
+/// Fields:
+///
+/// - url: The URL that was fetched
+/// - attempts: The number of attempts made
+/// - succeeded: Whether the final attempt succeeded
+/// - errors: The error of each failed attempt, in order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FetchRecord {
+    pub url: String,
+    pub attempts: u32,
+    pub succeeded: bool,
+    pub errors: Vec<String>,
+}
+
+impl FetchRecord {
+    /// Returns true if more than one attempt was needed.
+    pub fn retried(&self) -> bool {
+        self.attempts > 1
+    }
+}
+
+impl std::fmt::Display for FetchRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcome = if self.succeeded {
+            "succeeded"
+        } else {
+            "failed"
+        };
+        let plural = if self.attempts == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} {} after {} attempt{}",
+            self.url, outcome, self.attempts, plural
+        )
+    }
+}
+
+/// The HTTP client used during a scrape.
+///
+/// This wraps the fetcher together with the retry policy and the limits on in-flight requests,
+/// and records every fetch made through it.
+///
+/// - `for_server()` gives a client with a fresh per-server limit, sharing the global limit.
+/// - `scoped()` gives a client sharing the connection pool, policy and limits, but with its own
+///   record of fetches.
+#[derive(Debug, Clone)]
+pub(crate) struct HttpClient {
+    fetcher: Arc<dyn Fetcher>,
+    retry_policy: RetryPolicy,
+    records: Arc<Mutex<Vec<FetchRecord>>>,
+    global_limit: Option<Arc<Semaphore>>,
+    server_limit: Option<Arc<Semaphore>>,
+    max_requests_per_server: Option<usize>,
+}
+
+impl HttpClient {
+    pub fn new(fetcher: Arc<dyn Fetcher>, retry_policy: RetryPolicy) -> Self {
+        HttpClient {
+            fetcher,
+            retry_policy,
+            records: Arc::new(Mutex::new(Vec::new())),
+            global_limit: None,
+            server_limit: None,
+            max_requests_per_server: None,
+        }
+    }
+
+    pub fn from_config(
+        config: &ClientConfig,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self, ScrapeError> {
+        Ok(HttpClient::new(
+            Arc::new(ReqwestFetcher::new(config)?),
+            retry_policy,
+        ))
+    }
+
+    /// A client that sends requests for the given hostname to the given IP address.
+    ///
+    /// The client shares the limits, see `Fetcher::pinned` for how the fetcher is pinned.
+    pub fn pinned(&self, hostname: &str, ip: IpAddr) -> Result<Self, ScrapeError> {
+        let fetcher = self
+            .fetcher
+            .pinned(hostname, ip)?
+            .unwrap_or_else(|| self.fetcher.clone());
+        Ok(HttpClient {
+            fetcher,
+            ..self.scoped()
+        })
+    }
+
+    /// Limit the number of in-flight requests, globally and per server. None means unlimited.
+    pub fn with_limits(mut self, global: Option<usize>, per_server: Option<usize>) -> Self {
+        self.global_limit = global.map(|n| Arc::new(Semaphore::new(n.max(1))));
+        self.max_requests_per_server = per_server.map(|n| n.max(1));
+        self
+    }
+
+    pub fn for_server(&self) -> Self {
+        let mut client = self.scoped();
+        client.server_limit = self
+            .max_requests_per_server
+            .map(|n| Arc::new(Semaphore::new(n)));
+        client
+    }
+
+    pub fn scoped(&self) -> Self {
+        HttpClient {
+            fetcher: self.fetcher.clone(),
+            retry_policy: self.retry_policy.clone(),
+            records: Arc::new(Mutex::new(Vec::new())),
+            global_limit: self.global_limit.clone(),
+            server_limit: self.server_limit.clone(),
+            max_requests_per_server: self.max_requests_per_server,
+        }
+    }
+
+    /// Wait for a slot under both the per-server and the global limit.
+    ///
+    /// The per-server permit is always acquired first, so a server waiting for a global slot
+    /// holds at most its own slots.
+    async fn acquire(&self) -> Vec<OwnedSemaphorePermit> {
+        let mut permits = Vec::with_capacity(2);
+        for semaphore in [&self.server_limit, &self.global_limit]
+            .into_iter()
+            .flatten()
+        {
+            permits.push(
+                semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("request limit semaphore is never closed"),
+            );
+        }
+        permits
+    }
+
+    /// The random segment for GeoAPI URLs, see `Fetcher::geoapi_cache_buster`.
+    pub fn geoapi_cache_buster(&self) -> String {
+        self.fetcher.geoapi_cache_buster()
+    }
+
+    /// The fetches made through this client, sorted by URL so that the order does not depend on
+    /// which of the concurrent fetches finished first.
+    pub fn records(&self) -> Vec<FetchRecord> {
+        let mut records = self.records.lock().unwrap().clone();
+        records.sort_by(|a, b| {
+            (&a.url, a.attempts, a.succeeded, &a.errors).cmp(&(
+                &b.url,
+                b.attempts,
+                b.succeeded,
+                &b.errors,
+            ))
+        });
+        records
+    }
+
+    /// Fetch the body of the given URL as text, retrying according to the retry policy.
+    ///
+    /// Invalid UTF-8 in the body is replaced, use `get_bytes` for binary content.
+    pub async fn get_text(&self, url: &str) -> Result<String, FetchError> {
+        let body = self.get_bytes(url).await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// Fetch the body of the given URL, retrying according to the retry policy.
+    pub async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, FetchError> {
+        let max_attempts = self.retry_policy.max_attempts.max(1);
+        let mut record = FetchRecord {
+            url: url.to_string(),
+            attempts: 0,
+            succeeded: false,
+            errors: Vec::new(),
+        };
+
+        let result = loop {
+            record.attempts += 1;
+            let permits = self.acquire().await;
+            let attempt = match self.fetcher.fetch(url).await {
+                Ok(response) => response.into_body(url),
+                Err(error) => Err(error),
+            };
+            drop(permits);
+            match attempt {
+                Ok(body) => {
+                    record.succeeded = true;
+                    break Ok(body);
+                }
+                Err(error) => {
+                    record.errors.push(error.to_string());
+                    if record.attempts >= max_attempts || !self.retry_policy.is_retryable(&error) {
+                        break Err(error);
+                    }
+                    let delay = self.retry_policy.delay_for_attempt(record.attempts);
+                    debug!(
+                        "Attempt {} of {} for {} failed ({}), retrying in {:?}",
+                        record.attempts, max_attempts, url, error, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        };
+
+        self.records.lock().unwrap().push(record);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yare::parameterized;
+
+    #[test]
+    fn test_default_client_config_builds() {
+        assert!(ClientConfig::default().build().is_ok());
+    }
+
+    #[test]
+    fn test_full_client_config_builds() {
+        let config = ClientConfig::new()
+            .connect_timeout(Duration::from_secs(1))
+            .timeout(Duration::from_secs(2))
+            .user_agent("test-agent")
+            .proxy("http://proxy.example.com:3128")
+            .header("X-Test", "yes")
+            .redirect_policy(RedirectPolicy::None);
+        assert_eq!(config.headers.len(), 1);
+        assert!(config.build().is_ok());
+    }
+
+    #[parameterized(
+        invalid_header_name = { ClientConfig::new().header("invalid header", "value") },
+        invalid_header_value = { ClientConfig::new().header("X-Test", "line\nbreak") },
+        invalid_proxy = { ClientConfig::new().proxy("not a proxy url") },
+    )]
+    fn test_invalid_client_config(config: ClientConfig) {
+        match config.build() {
+            Err(ScrapeError::ClientConfigError(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_retry_delay_is_exponential_and_capped() {
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(350))
+            .jitter(0.0);
+        assert_eq!(policy.delay_for_attempt(1), Duration::from_millis(100));
+        assert_eq!(policy.delay_for_attempt(2), Duration::from_millis(200));
+        assert_eq!(policy.delay_for_attempt(3), Duration::from_millis(350));
+        assert_eq!(policy.delay_for_attempt(40), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn test_retry_delay_with_jitter_is_bounded() {
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(100))
+            .jitter(0.5);
+        for _ in 0..100 {
+            let delay = policy.delay_for_attempt(1);
+            assert!(delay >= Duration::from_millis(50));
+            assert!(delay <= Duration::from_millis(150));
+        }
+    }
+
+    #[test]
+    fn test_retry_delay_with_jitter_is_capped() {
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(120))
+            .jitter(1.0);
+        for attempt in [1, 2, 40] {
+            assert!(policy.delay_for_attempt(attempt) <= Duration::from_millis(120));
+        }
+    }
+
+    #[test]
+    fn test_fetch_record_display() {
+        let record = FetchRecord {
+            url: "http://example.com/".to_string(),
+            attempts: 3,
+            succeeded: true,
+            errors: vec!["error".to_string(), "error".to_string()],
+        };
+        assert!(record.retried());
+        assert_eq!(
+            record.to_string(),
+            "http://example.com/ succeeded after 3 attempts"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_errors_are_retried() {
+        // Nothing listens on port 9 (discard) on localhost, so the connection is refused.
+        let policy = RetryPolicy::new()
+            .max_attempts(3)
+            .base_delay(Duration::from_millis(1));
+        let client = HttpClient::from_config(&ClientConfig::new(), policy).unwrap();
+        assert!(client.get_text("http://127.0.0.1:9/").await.is_err());
+
+        let records = client.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].attempts, 3);
+        assert!(!records[0].succeeded);
+        assert_eq!(records[0].errors.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_connect_errors_are_not_retried_if_excluded() {
+        let policy = RetryPolicy::new()
+            .max_attempts(3)
+            .base_delay(Duration::from_millis(1))
+            .retry_on(vec![RetryOn::ServerError]);
+        let client = HttpClient::from_config(&ClientConfig::new(), policy).unwrap();
+        assert!(client.get_text("http://127.0.0.1:9/").await.is_err());
+        assert_eq!(client.records()[0].attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_errors_are_classified() {
+        // A plain HTTP server, so that an HTTPS request fails in the TLS handshake.
+        let mock = crate::mock::MockServer::start(crate::mock::MockServerConfig::new(
+            crate::models::ServerType::Stratum1,
+        ))
+        .await
+        .unwrap();
+        let client =
+            HttpClient::from_config(&ClientConfig::new(), RetryPolicy::no_retries()).unwrap();
+
+        for (url, kind) in [
+            ("http://127.0.0.1:9/".to_string(), ErrorKind::Connect),
+            ("http://nonexistent.invalid/".to_string(), ErrorKind::Dns),
+            (format!("https://{}/", mock.address()), ErrorKind::Tls),
+            (
+                format!("{}/missing", mock.base_url()),
+                ErrorKind::HttpStatus,
+            ),
+        ] {
+            let error = client.get_text(&url).await.unwrap_err();
+            assert_eq!(error.kind, kind, "{}: {}", url, error);
+            assert_eq!(error.url, url);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pinned_client_connects_to_the_pinned_address() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A single-response HTTP server on localhost, returning the request it received.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let n = stream.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..n]).into_owned();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                request.len(),
+                request
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let client = HttpClient::from_config(&ClientConfig::new(), RetryPolicy::no_retries())
+            .unwrap()
+            .pinned("pinned.example.invalid", "127.0.0.1".parse().unwrap())
+            .unwrap();
+        let request = client
+            .get_text(&format!("http://pinned.example.invalid:{}/", port))
+            .await
+            .unwrap();
+        assert!(
+            request
+                .to_lowercase()
+                .contains(&format!("host: pinned.example.invalid:{}", port)),
+            "{}",
+            request
+        );
+    }
+
+    #[tokio::test]
+    async fn test_limits_are_shared_and_scoped() {
+        let client = HttpClient::from_config(&ClientConfig::new(), RetryPolicy::new())
+            .unwrap()
+            .with_limits(Some(3), Some(2));
+        let server_a = client.for_server();
+        let server_b = client.for_server();
+        let repo_a = server_a.scoped();
+
+        // The global limit is shared by everything, the server limit only within a server.
+        assert!(Arc::ptr_eq(
+            server_a.global_limit.as_ref().unwrap(),
+            server_b.global_limit.as_ref().unwrap()
+        ));
+        assert!(Arc::ptr_eq(
+            server_a.server_limit.as_ref().unwrap(),
+            repo_a.server_limit.as_ref().unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            server_a.server_limit.as_ref().unwrap(),
+            server_b.server_limit.as_ref().unwrap()
+        ));
+
+        let _first = repo_a.acquire().await;
+        let _second = server_a.acquire().await;
+        assert_eq!(
+            server_a.server_limit.as_ref().unwrap().available_permits(),
+            0
+        );
+        assert_eq!(client.global_limit.as_ref().unwrap().available_permits(), 1);
+        let _third = server_b.acquire().await;
+        assert_eq!(client.global_limit.as_ref().unwrap().available_permits(), 0);
+    }
+}
@@ -2,6 +2,12 @@ use lazy_static::lazy_static;
 
 use crate::models::Hostname;
 
+/// The default limit on in-flight requests against a single server.
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS_PER_SERVER: usize = 4;
+
+/// The default path under which a server serves its repositories.
+pub const DEFAULT_BASE_PATH: &str = "/cvmfs";
+
 lazy_static! {
     pub static ref DEFAULT_GEOAPI_SERVERS: Vec<Hostname> = vec![
         "cvmfs-s1fnal.opensciencegrid.org".parse().unwrap(),
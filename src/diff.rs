@@ -0,0 +1,633 @@
+//! Changes between two scrape runs.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ErrorReport;
+use crate::models::{
+    FailedRepositoryOrReplica, PopulatedRepositoryOrReplica, PopulatedServer, ScrapedServer,
+};
+
+/// A change between two scrape runs.
+///
+/// ServerAdded, ServerRemoved: The server was only scraped in the current, or the previous, run.
+/// ServerFailed, ServerRecovered: The server went from populated to failed, or back.
+/// RepositoryAdded, RepositoryRemoved: The repository appeared on, or disappeared from, the server.
+/// RepositoryFailed, RepositoryRecovered: The repository went from scraped to failed, or back.
+/// RevisionChanged: The revision of the repository changed (usually advanced).
+/// RootCatalogChanged: The root catalog hash (`C`) changed without a change of revision.
+/// CvmfsVersionChanged: The CVMFS version of the server changed.
+/// ContactChanged: The administrator, email or organisation in meta.json changed.
+/// GeoapiOrderChanged: The GeoAPI response changed for the same GeoAPI servers.
+///
+/// Events serialize with an `event` tag, e.g. `{"event": "revision_changed", ...}`, and display
+/// as a single line suitable for chat messages or tickets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ChangeEvent {
+    ServerAdded {
+        server: String,
+    },
+    ServerRemoved {
+        server: String,
+    },
+    ServerFailed {
+        server: String,
+        error: ErrorReport,
+    },
+    ServerRecovered {
+        server: String,
+    },
+    RepositoryAdded {
+        server: String,
+        repository: String,
+    },
+    RepositoryRemoved {
+        server: String,
+        repository: String,
+    },
+    RepositoryFailed {
+        server: String,
+        repository: String,
+        error: ErrorReport,
+    },
+    RepositoryRecovered {
+        server: String,
+        repository: String,
+    },
+    RevisionChanged {
+        server: String,
+        repository: String,
+        previous: i32,
+        current: i32,
+    },
+    RootCatalogChanged {
+        server: String,
+        repository: String,
+        revision: i32,
+        previous: String,
+        current: String,
+    },
+    CvmfsVersionChanged {
+        server: String,
+        previous: Option<String>,
+        current: Option<String>,
+    },
+    ContactChanged {
+        server: String,
+        field: String,
+        previous: Option<String>,
+        current: Option<String>,
+    },
+    GeoapiOrderChanged {
+        server: String,
+        previous: Vec<u32>,
+        current: Vec<u32>,
+    },
+}
+
+impl ChangeEvent {
+    /// The key of the server the change happened on, see `ScrapedServer::key`.
+    pub fn server(&self) -> &str {
+        match self {
+            ChangeEvent::ServerAdded { server }
+            | ChangeEvent::ServerRemoved { server }
+            | ChangeEvent::ServerFailed { server, .. }
+            | ChangeEvent::ServerRecovered { server }
+            | ChangeEvent::RepositoryAdded { server, .. }
+            | ChangeEvent::RepositoryRemoved { server, .. }
+            | ChangeEvent::RepositoryFailed { server, .. }
+            | ChangeEvent::RepositoryRecovered { server, .. }
+            | ChangeEvent::RevisionChanged { server, .. }
+            | ChangeEvent::RootCatalogChanged { server, .. }
+            | ChangeEvent::CvmfsVersionChanged { server, .. }
+            | ChangeEvent::ContactChanged { server, .. }
+            | ChangeEvent::GeoapiOrderChanged { server, .. } => server,
+        }
+    }
+
+    /// The repository the change happened in, if it is a change to a single repository.
+    pub fn repository(&self) -> Option<&str> {
+        match self {
+            ChangeEvent::RepositoryAdded { repository, .. }
+            | ChangeEvent::RepositoryRemoved { repository, .. }
+            | ChangeEvent::RepositoryFailed { repository, .. }
+            | ChangeEvent::RepositoryRecovered { repository, .. }
+            | ChangeEvent::RevisionChanged { repository, .. }
+            | ChangeEvent::RootCatalogChanged { repository, .. } => Some(repository),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ChangeEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_none = |value: &Option<String>| value.clone().unwrap_or("none".to_string());
+        match self {
+            ChangeEvent::ServerAdded { server } => write!(f, "{}: server added", server),
+            ChangeEvent::ServerRemoved { server } => write!(f, "{}: server removed", server),
+            ChangeEvent::ServerFailed { server, error } => {
+                write!(f, "{}: server failed: {}", server, error.message)
+            }
+            ChangeEvent::ServerRecovered { server } => {
+                write!(f, "{}: server recovered", server)
+            }
+            ChangeEvent::RepositoryAdded { server, repository } => {
+                write!(f, "{}: repository {} added", server, repository)
+            }
+            ChangeEvent::RepositoryRemoved { server, repository } => {
+                write!(f, "{}: repository {} removed", server, repository)
+            }
+            ChangeEvent::RepositoryFailed {
+                server,
+                repository,
+                error,
+            } => write!(
+                f,
+                "{}: repository {} failed: {}",
+                server, repository, error.message
+            ),
+            ChangeEvent::RepositoryRecovered { server, repository } => {
+                write!(f, "{}: repository {} recovered", server, repository)
+            }
+            ChangeEvent::RevisionChanged {
+                server,
+                repository,
+                previous,
+                current,
+            } => write!(
+                f,
+                "{}: {} revision {} -> {}",
+                server, repository, previous, current
+            ),
+            ChangeEvent::RootCatalogChanged {
+                server,
+                repository,
+                revision,
+                previous,
+                current,
+            } => write!(
+                f,
+                "{}: {} root catalog changed at revision {}: {} -> {}",
+                server, repository, revision, previous, current
+            ),
+            ChangeEvent::CvmfsVersionChanged {
+                server,
+                previous,
+                current,
+            } => write!(
+                f,
+                "{}: CVMFS version {} -> {}",
+                server,
+                or_none(previous),
+                or_none(current)
+            ),
+            ChangeEvent::ContactChanged {
+                server,
+                field,
+                previous,
+                current,
+            } => write!(
+                f,
+                "{}: {} {} -> {}",
+                server,
+                field,
+                or_none(previous),
+                or_none(current)
+            ),
+            ChangeEvent::GeoapiOrderChanged {
+                server,
+                previous,
+                current,
+            } => write!(
+                f,
+                "{}: GeoAPI order {:?} -> {:?}",
+                server, previous, current
+            ),
+        }
+    }
+}
+
+/// The changes between two scrape runs.
+///
+/// Servers are matched by key (see `ScrapedServer::key`), and repositories by name within a
+/// server. The events of each server are listed in the order of the servers in the current run,
+/// followed by the servers that were removed. Within a server, server-level events come first,
+/// then the events of each repository, sorted by name.
+///
+/// ### Example
+///
+/// ```rust
+/// use cvmfs_server_scraper::{Scraper, ScrapeDiff};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let scraper = Scraper::new().with_servers(vec![]).validate()?;
+///     let previous = scraper.scrape().await;
+///     let current = scraper.scrape().await;
+///     let diff = ScrapeDiff::new(&previous, &current);
+///     for event in &diff.events {
+///         println!("{}", event);
+///     }
+///     assert!(diff.is_empty());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScrapeDiff {
+    pub events: Vec<ChangeEvent>,
+}
+
+impl ScrapeDiff {
+    pub fn new(previous: &[ScrapedServer], current: &[ScrapedServer]) -> Self {
+        let previous_by_key = previous
+            .iter()
+            .map(|server| (server.key(), server))
+            .collect::<BTreeMap<_, _>>();
+        let current_keys = current
+            .iter()
+            .map(|server| server.key())
+            .collect::<BTreeSet<_>>();
+
+        let mut events = Vec::new();
+        for server in current {
+            let key = server.key();
+            match (previous_by_key.get(&key), server) {
+                (None, _) => events.push(ChangeEvent::ServerAdded { server: key }),
+                (Some(ScrapedServer::Populated(_)), ScrapedServer::Failed(failed)) => {
+                    events.push(ChangeEvent::ServerFailed {
+                        server: key,
+                        error: failed.error.report(),
+                    })
+                }
+                (Some(ScrapedServer::Failed(_)), ScrapedServer::Populated(_)) => {
+                    events.push(ChangeEvent::ServerRecovered { server: key })
+                }
+                (Some(ScrapedServer::Populated(previous)), ScrapedServer::Populated(current)) => {
+                    diff_servers(previous, current, &mut events)
+                }
+                (Some(ScrapedServer::Failed(_)), ScrapedServer::Failed(_)) => {}
+            }
+        }
+        for server in previous {
+            let key = server.key();
+            if !current_keys.contains(&key) {
+                events.push(ChangeEvent::ServerRemoved { server: key });
+            }
+        }
+        ScrapeDiff { events }
+    }
+
+    /// Returns true if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn output(&self) {
+        for event in &self.events {
+            println!("{}", event);
+        }
+    }
+}
+
+/// The state of a repository on a populated server.
+enum RepositoryState<'a> {
+    Populated(&'a PopulatedRepositoryOrReplica),
+    Failed(&'a FailedRepositoryOrReplica),
+}
+
+fn repository_states(server: &PopulatedServer) -> BTreeMap<&str, RepositoryState<'_>> {
+    let mut states = BTreeMap::new();
+    for repo in &server.repositories {
+        states.insert(repo.name.as_str(), RepositoryState::Populated(repo));
+    }
+    for repo in &server.failed_repositories {
+        states.insert(repo.name.as_str(), RepositoryState::Failed(repo));
+    }
+    states
+}
+
+fn diff_servers(
+    previous: &PopulatedServer,
+    current: &PopulatedServer,
+    events: &mut Vec<ChangeEvent>,
+) {
+    let server = current.key();
+
+    let version = |server: &PopulatedServer| {
+        server
+            .metadata
+            .cvmfs_version
+            .as_ref()
+            .map(|v| v.to_string())
+    };
+    if version(previous) != version(current) {
+        events.push(ChangeEvent::CvmfsVersionChanged {
+            server: server.clone(),
+            previous: version(previous),
+            current: version(current),
+        });
+    }
+
+    for (field, previous_value, current_value) in [
+        (
+            "administrator",
+            &previous.metadata.administrator,
+            &current.metadata.administrator,
+        ),
+        ("email", &previous.metadata.email, &current.metadata.email),
+        (
+            "organisation",
+            &previous.metadata.organisation,
+            &current.metadata.organisation,
+        ),
+    ] {
+        if previous_value != current_value {
+            events.push(ChangeEvent::ContactChanged {
+                server: server.clone(),
+                field: field.to_string(),
+                previous: previous_value.clone(),
+                current: current_value.clone(),
+            });
+        }
+    }
+
+    // An empty response means the GeoAPI was not queried (e.g. no repositories or an S3
+    // backend), and responses for different GeoAPI servers are not comparable.
+    let (previous_geoapi, current_geoapi) = (&previous.geoapi, &current.geoapi);
+    if !previous_geoapi.response.is_empty()
+        && !current_geoapi.response.is_empty()
+        && previous_geoapi.geoapi_hosts == current_geoapi.geoapi_hosts
+        && previous_geoapi.response != current_geoapi.response
+    {
+        events.push(ChangeEvent::GeoapiOrderChanged {
+            server: server.clone(),
+            previous: previous_geoapi.response.clone(),
+            current: current_geoapi.response.clone(),
+        });
+    }
+
+    let previous_repos = repository_states(previous);
+    let current_repos = repository_states(current);
+    let names = previous_repos
+        .keys()
+        .chain(current_repos.keys())
+        .collect::<BTreeSet<_>>();
+    for name in names {
+        let repository = name.to_string();
+        match (previous_repos.get(name), current_repos.get(name)) {
+            (None, Some(_)) => events.push(ChangeEvent::RepositoryAdded {
+                server: server.clone(),
+                repository,
+            }),
+            (Some(_), None) => events.push(ChangeEvent::RepositoryRemoved {
+                server: server.clone(),
+                repository,
+            }),
+            (Some(RepositoryState::Populated(_)), Some(RepositoryState::Failed(failed))) => events
+                .push(ChangeEvent::RepositoryFailed {
+                    server: server.clone(),
+                    repository,
+                    error: failed.error.report(),
+                }),
+            (Some(RepositoryState::Failed(_)), Some(RepositoryState::Populated(_))) => {
+                events.push(ChangeEvent::RepositoryRecovered {
+                    server: server.clone(),
+                    repository,
+                })
+            }
+            (
+                Some(RepositoryState::Populated(previous)),
+                Some(RepositoryState::Populated(current)),
+            ) => {
+                let (previous, current) = (&previous.manifest, &current.manifest);
+                if previous.s != current.s {
+                    events.push(ChangeEvent::RevisionChanged {
+                        server: server.clone(),
+                        repository,
+                        previous: previous.s,
+                        current: current.s,
+                    });
+                } else if previous.c != current.c {
+                    events.push(ChangeEvent::RootCatalogChanged {
+                        server: server.clone(),
+                        repository,
+                        revision: current.s,
+                        previous: previous.c.to_string(),
+                        current: current.c.to_string(),
+                    });
+                }
+            }
+            (Some(RepositoryState::Failed(_)), Some(RepositoryState::Failed(_))) | (None, None) => {
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{ErrorKind, ManifestError};
+    use crate::models::ServerType;
+    use crate::test_helpers::{failed_server, populated_server};
+
+    const REPO: &str = "software.eessi.io";
+
+    fn s1(repositories: &[(&str, i32, i64)]) -> ScrapedServer {
+        populated_server("s1.example.org", ServerType::Stratum1, repositories)
+    }
+
+    fn modify<F: FnOnce(&mut PopulatedServer)>(server: ScrapedServer, modify: F) -> ScrapedServer {
+        let mut populated = server.get_populated_server().unwrap();
+        modify(&mut populated);
+        ScrapedServer::Populated(populated)
+    }
+
+    fn events(previous: ScrapedServer, current: ScrapedServer) -> Vec<String> {
+        ScrapeDiff::new(&[previous], &[current])
+            .events
+            .iter()
+            .map(|event| event.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_identical_runs_have_no_changes() {
+        let run = vec![s1(&[(REPO, 1, 0)]), failed_server("s2.example.org")];
+        assert!(ScrapeDiff::new(&run, &run).is_empty());
+    }
+
+    #[test]
+    fn test_server_changes() {
+        let previous = vec![s1(&[(REPO, 1, 0)]), failed_server("s2.example.org")];
+        let current = vec![
+            failed_server("s1.example.org"),
+            populated_server("s2.example.org", ServerType::Stratum1, &[]),
+            populated_server("s3.example.org", ServerType::Stratum1, &[]),
+        ];
+        let diff = ScrapeDiff::new(&previous, &current);
+        let summary = diff
+            .events
+            .iter()
+            .map(|event| event.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                "s1.example.org: server failed: Scrape error: Server type mismatch: s1.example.org is not a Stratum1",
+                "s2.example.org: server recovered",
+                "s3.example.org: server added",
+            ]
+        );
+        let ChangeEvent::ServerFailed { error, .. } = &diff.events[0] else {
+            panic!("Expected ServerFailed, got {:?}", diff.events[0]);
+        };
+        assert_eq!(error.kind, ErrorKind::ServerTypeMismatch);
+
+        let removed = ScrapeDiff::new(&current, &previous[..1]);
+        assert_eq!(
+            removed.events.last().unwrap(),
+            &ChangeEvent::ServerRemoved {
+                server: "s3.example.org".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_servers_differing_in_port_are_distinct() {
+        let on_port = modify(s1(&[(REPO, 2, 0)]), |s| s.port = Some(8000));
+        let previous = vec![s1(&[(REPO, 1, 0)]), on_port.clone()];
+        let current = vec![
+            s1(&[(REPO, 1, 0)]),
+            modify(on_port, |s| s.repositories[0].manifest.s = 3),
+        ];
+        let summary = ScrapeDiff::new(&previous, &current)
+            .events
+            .iter()
+            .map(|event| event.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec!["http://s1.example.org:8000/cvmfs: software.eessi.io revision 2 -> 3"]
+        );
+    }
+
+    #[test]
+    fn test_servers_pinned_to_different_ips_are_distinct() {
+        let pinned = |ip: &str, revision: i32| {
+            populated_server(
+                &format!("s1.example.org@{}", ip),
+                ServerType::Stratum1,
+                &[(REPO, revision, 0)],
+            )
+        };
+        let previous = vec![pinned("192.0.2.1", 1), pinned("192.0.2.2", 1)];
+        let current = vec![pinned("192.0.2.1", 1), pinned("192.0.2.2", 2)];
+        let summary = ScrapeDiff::new(&previous, &current)
+            .events
+            .iter()
+            .map(|event| event.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec!["s1.example.org@192.0.2.2: software.eessi.io revision 1 -> 2"]
+        );
+    }
+
+    #[test]
+    fn test_repository_changes() {
+        let previous = s1(&[(REPO, 10, 0), ("dev.eessi.io", 1, 0)]);
+        let current = s1(&[(REPO, 11, 0), ("riscv.eessi.io", 1, 0)]);
+        assert_eq!(
+            events(previous, current),
+            vec![
+                "s1.example.org: repository dev.eessi.io removed",
+                "s1.example.org: repository riscv.eessi.io added",
+                "s1.example.org: software.eessi.io revision 10 -> 11",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repository_failure_and_recovery() {
+        let populated = s1(&[(REPO, 10, 0)]);
+        let failed = modify(s1(&[]), |server| {
+            server.failed_repositories.push(FailedRepositoryOrReplica {
+                name: REPO.to_string(),
+                error: ManifestError::MissingField('C').into(),
+                fetches: Vec::new(),
+            });
+        });
+        assert_eq!(
+            events(populated.clone(), failed.clone()),
+            vec!["s1.example.org: repository software.eessi.io failed: Manifest error: Missing field C"]
+        );
+        assert_eq!(
+            events(failed, populated),
+            vec!["s1.example.org: repository software.eessi.io recovered"]
+        );
+    }
+
+    #[test]
+    fn test_root_catalog_change_without_revision_bump() {
+        let previous = s1(&[(REPO, 10, 0)]);
+        let current = modify(previous.clone(), |server| {
+            server.repositories[0].manifest.c =
+                "0123456789abcdef0123456789abcdef01234567".parse().unwrap();
+        });
+        assert_eq!(
+            events(previous, current),
+            vec!["s1.example.org: software.eessi.io root catalog changed at revision 10: 600230b0ba7620426f2e898f1e1f43c5466efe59 -> 0123456789abcdef0123456789abcdef01234567"]
+        );
+    }
+
+    #[test]
+    fn test_metadata_and_geoapi_changes() {
+        let previous = modify(s1(&[(REPO, 10, 0)]), |server| {
+            server.geoapi.geoapi_hosts = crate::constants::DEFAULT_GEOAPI_SERVERS.clone();
+            server.geoapi.response = vec![1, 2, 3];
+        });
+        let current = modify(previous.clone(), |server| {
+            server.metadata.cvmfs_version = Some(semver::Version::new(2, 12, 0));
+            server.metadata.email = Some("cvmfs@example.org".to_string());
+            server.geoapi.response = vec![2, 1, 3];
+        });
+        assert_eq!(
+            events(previous.clone(), current),
+            vec![
+                "s1.example.org: CVMFS version 2.11.3 -> 2.12.0",
+                "s1.example.org: email admin@example.com -> cvmfs@example.org",
+                "s1.example.org: GeoAPI order [1, 2, 3] -> [2, 1, 3]",
+            ]
+        );
+
+        // A GeoAPI query that was not made is not a change of order.
+        let unqueried = modify(previous.clone(), |server| server.geoapi.response.clear());
+        assert!(events(previous, unqueried).is_empty());
+    }
+
+    #[test]
+    fn test_events_serialize_with_a_tag() {
+        let event = ChangeEvent::RevisionChanged {
+            server: "s1.example.org".to_string(),
+            repository: REPO.to_string(),
+            previous: 10,
+            current: 11,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "event": "revision_changed",
+                "server": "s1.example.org",
+                "repository": REPO,
+                "previous": 10,
+                "current": 11,
+            })
+        );
+        assert_eq!(serde_json::from_value::<ChangeEvent>(json).unwrap(), event);
+        assert_eq!(event.repository(), Some(REPO));
+        assert_eq!(event.server(), "s1.example.org");
+    }
+}
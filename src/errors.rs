@@ -1,10 +1,11 @@
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use thiserror::Error;
 
 #[derive(Error, Debug, Clone)]
 pub enum ManifestError {
     #[error("Failed to fetch manifest: {0}")]
-    FetchError(Arc<reqwest::Error>),
+    FetchError(#[from] FetchError),
 
     #[error("Missing field {0}")]
     MissingField(char),
@@ -17,6 +18,135 @@ pub enum ManifestError {
 
     #[error("Invalid certificate: {0}")]
     InvalidCertificate(String),
+
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+}
+
+/// An error from fetching a URL.
+///
+/// Fetch errors are independent of the `Fetcher` that made the request, a fetcher serving files
+/// from disk reports a missing file the same way as a server responding with 404 Not Found.
+///
+/// Fields:
+///
+/// - kind: One of `ErrorKind::Dns`, `Connect`, `Tls`, `Timeout`, `HttpStatus` or `Request`
+/// - url: The URL that was fetched
+/// - status: The HTTP status code, if the server responded with an error status
+/// - message: A description of the error
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[error("{message}")]
+pub struct FetchError {
+    pub kind: ErrorKind,
+    pub url: String,
+    pub status: Option<u16>,
+    pub message: String,
+}
+
+impl FetchError {
+    /// The connection to the server could not be established.
+    pub fn connect<U: Into<String>, M: Into<String>>(url: U, message: M) -> Self {
+        FetchError::new(ErrorKind::Connect, url, None, message)
+    }
+
+    /// The request timed out.
+    pub fn timeout<U: Into<String>, M: Into<String>>(url: U, message: M) -> Self {
+        FetchError::new(ErrorKind::Timeout, url, None, message)
+    }
+
+    /// The server responded with the given error status.
+    pub fn status<U: Into<String>>(url: U, status: u16) -> Self {
+        let url = url.into();
+        let reason = reqwest::StatusCode::from_u16(status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or("Unknown");
+        let message = format!("HTTP status {} {} for url ({})", status, reason, url);
+        FetchError::new(ErrorKind::HttpStatus, url, Some(status), message)
+    }
+
+    /// Any other failure while making the request or reading the response.
+    pub fn request<U: Into<String>, M: Into<String>>(url: U, message: M) -> Self {
+        FetchError::new(ErrorKind::Request, url, None, message)
+    }
+
+    fn new<U: Into<String>, M: Into<String>>(
+        kind: ErrorKind,
+        url: U,
+        status: Option<u16>,
+        message: M,
+    ) -> Self {
+        FetchError {
+            kind,
+            url: url.into(),
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// Returns true if the server responded with 404 Not Found.
+    pub fn is_not_found(&self) -> bool {
+        self.status == Some(404)
+    }
+
+    /// Returns true if the server responded with a 5xx status code.
+    pub fn is_server_error(&self) -> bool {
+        self.status
+            .is_some_and(|status| (500..600).contains(&status))
+    }
+}
+
+/// A failure to resolve a hostname, from the resolver of the HTTP client.
+///
+/// The resolver returns this type so that DNS failures can be told apart from other connect
+/// errors in the sources of a `reqwest::Error`.
+#[derive(Error, Debug)]
+#[error("Failed to resolve {name}: {source}")]
+pub(crate) struct DnsError {
+    pub name: String,
+    pub source: std::io::Error,
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(error: reqwest::Error) -> Self {
+        // reqwest only tells connect errors apart from other errors, whether the name did not
+        // resolve or the TLS handshake failed is only found in the chain of sources.
+        let mut causes: Vec<&(dyn std::error::Error + 'static)> = Vec::new();
+        let mut source = std::error::Error::source(&error);
+        while let Some(cause) = source {
+            causes.push(cause);
+            source = cause.source();
+        }
+        let timed_out = causes.iter().any(|cause| {
+            cause
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::TimedOut)
+        });
+
+        let kind = if error.is_timeout() || timed_out {
+            ErrorKind::Timeout
+        } else if causes.iter().any(|cause| cause.is::<DnsError>()) {
+            ErrorKind::Dns
+        } else if causes.iter().any(|cause| cause.is::<native_tls::Error>()) {
+            ErrorKind::Tls
+        } else if error.is_connect() {
+            ErrorKind::Connect
+        } else if error.status().is_some() {
+            ErrorKind::HttpStatus
+        } else {
+            ErrorKind::Request
+        };
+        let message = match causes.last() {
+            Some(cause) => format!("{}: {}", error, cause),
+            None => error.to_string(),
+        };
+        FetchError::new(
+            kind,
+            error.url().map(|url| url.to_string()).unwrap_or_default(),
+            error.status().map(|status| status.as_u16()),
+            message,
+        )
+    }
 }
 
 #[derive(Error, Debug, Clone)]
@@ -35,12 +165,113 @@ pub enum HostnameError {
 
     #[error("Label contains consecutive dashes: {0}")]
     ConsecutiveDashes(String),
+
+    #[error("Invalid IP address: {0}")]
+    InvalidIpAddress(String),
+}
+
+/// Errors from loading an inventory file.
+///
+/// Parse and validation errors carry the file and, where known, the line they occurred on.
+#[cfg(feature = "inventory")]
+#[derive(Error, Debug, Clone)]
+pub enum InventoryError {
+    #[error("{}: {message}", .path.display())]
+    Read {
+        path: std::path::PathBuf,
+        message: String,
+    },
+
+    #[error("{}:{line}:{column}: {message}", .path.display())]
+    Parse {
+        path: std::path::PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    #[error("{}{}: {message}", .path.display(), .line.map(|line| format!(":{}", line)).unwrap_or_default())]
+    Invalid {
+        path: std::path::PathBuf,
+        line: Option<usize>,
+        message: String,
+    },
+
+    #[error("Unsupported inventory format (expected .toml, .yaml, .yml or .json): {}", .0.display())]
+    UnsupportedFormat(std::path::PathBuf),
+
+    #[error("Inventory include cycle: {}", .0.display())]
+    IncludeCycle(std::path::PathBuf),
+}
+
+/// Errors from the scrape history, see `HistoryStore`.
+#[cfg(feature = "history")]
+#[derive(Error, Debug, Clone)]
+pub enum HistoryError {
+    #[error("History database error: {0}")]
+    Database(String),
+
+    #[error("Invalid history entry: {0}")]
+    Serialization(String),
+
+    #[error("Unsupported history schema version {found} (supported up to {supported})")]
+    UnsupportedVersion { found: u32, supported: u32 },
+}
+
+#[cfg(feature = "history")]
+impl From<rusqlite::Error> for HistoryError {
+    fn from(error: rusqlite::Error) -> Self {
+        HistoryError::Database(error.to_string())
+    }
+}
+
+#[cfg(feature = "history")]
+impl From<serde_json::Error> for HistoryError {
+    fn from(error: serde_json::Error) -> Self {
+        HistoryError::Serialization(error.to_string())
+    }
+}
+
+/// Errors from building a `Watcher`.
+#[cfg(feature = "watch")]
+#[derive(Error, Debug, Clone)]
+pub enum WatchError {
+    #[error("Duplicate server {0}: every watched server must have a unique key")]
+    DuplicateServer(String),
+
+    #[error("Unknown server {0}: no watched server has this key")]
+    UnknownServer(String),
+
+    #[error("Invalid interval: the interval between scrapes must be greater than zero")]
+    ZeroInterval,
+
+    #[error("Invalid channel capacity: the capacity must be greater than zero")]
+    ZeroChannelCapacity,
+}
+
+/// Errors from reading or writing a cassette, see `Cassette`.
+#[derive(Error, Debug, Clone)]
+pub enum CassetteError {
+    #[error("{}: {message}", .path.display())]
+    Io {
+        path: std::path::PathBuf,
+        message: String,
+    },
+
+    #[error("{}: {message}", .path.display())]
+    Parse {
+        path: std::path::PathBuf,
+        message: String,
+    },
+
+    #[error("Unsupported cassette version {found} (supported up to {supported})")]
+    UnsupportedVersion { found: u32, supported: u32 },
 }
 
 #[derive(Error, Debug, Clone)]
 pub enum ScrapeError {
     #[error("Failed to scrape: {0}")]
-    FetchError(Arc<reqwest::Error>),
+    FetchError(#[from] FetchError),
 
     #[error("Failed to parse scrape result: {0}")]
     ParseError(Arc<serde_json::Error>),
@@ -62,6 +293,12 @@ pub enum ScrapeError {
 
     #[error("GeoAPI failure: {0}")]
     GeoAPIFailure(String),
+
+    #[error("Invalid client configuration: {0}")]
+    ClientConfigError(String),
+
+    #[error("Invalid server URL: {0}")]
+    InvalidUrl(String),
 }
 
 #[derive(Error, Debug, Clone)]
@@ -84,17 +321,273 @@ pub enum CVMFSScraperError {
 
     #[error("Generic error: {0}")]
     GenericError(#[from] GenericError),
+
+    /// An error that occurred in the given phase of a scrape, in the given repository if any.
+    /// See `CVMFSScraperError::in_phase`.
+    #[error("{source}")]
+    InPhase {
+        phase: ScrapePhase,
+        repository: Option<String>,
+        source: Box<CVMFSScraperError>,
+    },
+
+    /// An error restored from a serialized scrape result. See `ErrorReport`.
+    #[error("{}", .0.message)]
+    Restored(ErrorReport),
+}
+
+/// The phase of a scrape an error occurred in, as used in `ErrorReport`.
+///
+/// Every error that fails a server or a repository has a phase, and so does a failure to fetch
+/// meta.json, which is recorded in `PopulatedServer::meta_json_error` without failing the server.
+/// Failures to fetch the whitelist or the replication markers do not fail the scrape, so they
+/// have no phase. They are logged, and recorded in the `fetches` of the repository.
+///
+/// RepositoriesJson: Fetching, parsing or validating info/v1/repositories.json of the server, or
+/// getting the list of repositories of an S3 server (which must be given, as S3 servers have no
+/// repositories.json).
+/// MetaJson: Fetching or parsing info/v1/meta.json of the server.
+/// StatusJson: Fetching or parsing .cvmfs_status.json of a repository.
+/// Manifest: Fetching or parsing .cvmfspublished of a repository.
+/// GeoApi: Querying the GeoAPI of the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrapePhase {
+    RepositoriesJson,
+    MetaJson,
+    StatusJson,
+    Manifest,
+    GeoApi,
+}
+
+impl std::fmt::Display for ScrapePhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScrapePhase::RepositoriesJson => write!(f, "repositories.json"),
+            ScrapePhase::MetaJson => write!(f, "meta.json"),
+            ScrapePhase::StatusJson => write!(f, ".cvmfs_status.json"),
+            ScrapePhase::Manifest => write!(f, ".cvmfspublished"),
+            ScrapePhase::GeoApi => write!(f, "GeoAPI"),
+        }
+    }
+}
+
+/// The kind of an error, as used in `ErrorReport`.
+///
+/// Dns: The hostname of the server could not be resolved.
+/// Connect: The connection to the server could not be established.
+/// Tls: The TLS handshake failed, e.g. because the certificate of the server was invalid.
+/// Timeout: The request timed out.
+/// HttpStatus: The server responded with an error status code (see `ErrorReport::status`).
+/// Request: Any other failure while making a request or reading the response.
+/// Parse: The response could not be parsed (JSON, manifest fields, hex strings, dates).
+/// EmptyRepositoryList: An S3 backend was used without a list of repositories.
+/// ServerTypeMismatch: The server type did not match the contents of repositories.json.
+/// Conversion: A value could not be converted (e.g. an invalid CVMFS version).
+/// GeoApi: The GeoAPI query failed.
+/// ClientConfig: The HTTP client configuration was invalid.
+/// InvalidUrl: A server URL was invalid.
+/// InvalidCertificate: The signing certificate was invalid.
+/// InvalidSignature: The manifest signature was invalid.
+/// InvalidHostname: A hostname was invalid.
+/// Type: A type error, e.g. asking a failed server for its populated data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Dns,
+    Connect,
+    Tls,
+    Timeout,
+    HttpStatus,
+    Request,
+    Parse,
+    EmptyRepositoryList,
+    ServerTypeMismatch,
+    Conversion,
+    GeoApi,
+    ClientConfig,
+    InvalidUrl,
+    InvalidCertificate,
+    InvalidSignature,
+    InvalidHostname,
+    Type,
+}
+
+/// A structured, serializable form of a `CVMFSScraperError`.
+///
+/// Fields:
+///
+/// - kind: The kind of error
+/// - message: The full error message, as given by the Display implementation of the error
+/// - url: The URL that was being fetched, if the error came from a fetch
+/// - status: The HTTP status code, if the server responded with an error status
+/// - phase: The phase of the scrape the error occurred in, if known
+/// - repository: The repository the error occurred in, if any
+///
+/// `CVMFSScraperError` serializes as an ErrorReport, and deserializes into
+/// `CVMFSScraperError::Restored`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorReport {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub url: Option<String>,
+    pub status: Option<u16>,
+    #[serde(default)]
+    pub phase: Option<ScrapePhase>,
+    #[serde(default)]
+    pub repository: Option<String>,
+}
+
+impl ErrorReport {
+    fn new(kind: ErrorKind, error: &CVMFSScraperError) -> Self {
+        ErrorReport {
+            kind,
+            message: error.to_string(),
+            url: None,
+            status: None,
+            phase: None,
+            repository: None,
+        }
+    }
+
+    fn from_fetch(error: &FetchError, outer: &CVMFSScraperError) -> Self {
+        ErrorReport {
+            kind: error.kind,
+            message: outer.to_string(),
+            url: Some(error.url.clone()).filter(|url| !url.is_empty()),
+            status: error.status,
+            phase: None,
+            repository: None,
+        }
+    }
+}
+
+impl From<&CVMFSScraperError> for ErrorReport {
+    fn from(error: &CVMFSScraperError) -> Self {
+        match error {
+            CVMFSScraperError::ScrapeError(scrape_error) => match scrape_error {
+                ScrapeError::FetchError(e) => ErrorReport::from_fetch(e, error),
+                ScrapeError::ParseError(_)
+                | ScrapeError::InvalidJson(_)
+                | ScrapeError::ChronoParseError(_) => ErrorReport::new(ErrorKind::Parse, error),
+                ScrapeError::EmptyRepositoryList(_) => {
+                    ErrorReport::new(ErrorKind::EmptyRepositoryList, error)
+                }
+                ScrapeError::ServerTypeMismatch(_) => {
+                    ErrorReport::new(ErrorKind::ServerTypeMismatch, error)
+                }
+                ScrapeError::ConversionError(_) => ErrorReport::new(ErrorKind::Conversion, error),
+                ScrapeError::GeoAPIFailure(_) => ErrorReport::new(ErrorKind::GeoApi, error),
+                ScrapeError::ClientConfigError(_) => {
+                    ErrorReport::new(ErrorKind::ClientConfig, error)
+                }
+                ScrapeError::InvalidUrl(_) => ErrorReport::new(ErrorKind::InvalidUrl, error),
+            },
+            CVMFSScraperError::ManifestError(manifest_error) => match manifest_error {
+                ManifestError::FetchError(e) => ErrorReport::from_fetch(e, error),
+                ManifestError::MissingField(_)
+                | ManifestError::ParseError(_, _)
+                | ManifestError::InvalidHex(_) => ErrorReport::new(ErrorKind::Parse, error),
+                ManifestError::InvalidCertificate(_) => {
+                    ErrorReport::new(ErrorKind::InvalidCertificate, error)
+                }
+                ManifestError::InvalidSignature(_) => {
+                    ErrorReport::new(ErrorKind::InvalidSignature, error)
+                }
+            },
+            CVMFSScraperError::HostnameError(_) => {
+                ErrorReport::new(ErrorKind::InvalidHostname, error)
+            }
+            CVMFSScraperError::GenericError(_) => ErrorReport::new(ErrorKind::Type, error),
+            CVMFSScraperError::InPhase {
+                phase,
+                repository,
+                source,
+            } => ErrorReport {
+                phase: Some(*phase),
+                repository: repository.clone(),
+                ..ErrorReport::from(source.as_ref())
+            },
+            CVMFSScraperError::Restored(report) => report.clone(),
+        }
+    }
+}
+
+impl CVMFSScraperError {
+    /// Get the structured, serializable form of the error.
+    pub fn report(&self) -> ErrorReport {
+        ErrorReport::from(self)
+    }
+
+    /// Record the phase of the scrape the error occurred in, and the repository if any.
+    ///
+    /// The message of the error is unchanged. An error that already has a phase keeps it.
+    pub fn in_phase(self, phase: ScrapePhase, repository: Option<&str>) -> Self {
+        match self {
+            CVMFSScraperError::InPhase { .. } => self,
+            error => CVMFSScraperError::InPhase {
+                phase,
+                repository: repository.map(|r| r.to_string()),
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// The phase of the scrape the error occurred in, if known.
+    pub fn phase(&self) -> Option<ScrapePhase> {
+        match self {
+            CVMFSScraperError::InPhase { phase, .. } => Some(*phase),
+            CVMFSScraperError::Restored(report) => report.phase,
+            _ => None,
+        }
+    }
+}
+
+/// Attach a `ScrapePhase` to the error of a result, see `CVMFSScraperError::in_phase`.
+pub(crate) trait InPhase<T> {
+    fn in_phase(self, phase: ScrapePhase, repository: Option<&str>)
+        -> Result<T, CVMFSScraperError>;
+}
+
+impl<T, E: Into<CVMFSScraperError>> InPhase<T> for Result<T, E> {
+    fn in_phase(
+        self,
+        phase: ScrapePhase,
+        repository: Option<&str>,
+    ) -> Result<T, CVMFSScraperError> {
+        self.map_err(|error| error.into().in_phase(phase, repository))
+    }
+}
+
+impl Serialize for CVMFSScraperError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.report().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CVMFSScraperError {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(CVMFSScraperError::Restored(ErrorReport::deserialize(
+            deserializer,
+        )?))
+    }
 }
 
 impl From<reqwest::Error> for ManifestError {
     fn from(error: reqwest::Error) -> Self {
-        ManifestError::FetchError(Arc::new(error))
+        ManifestError::FetchError(error.into())
     }
 }
 
 impl From<reqwest::Error> for ScrapeError {
     fn from(error: reqwest::Error) -> Self {
-        ScrapeError::FetchError(Arc::new(error))
+        ScrapeError::FetchError(error.into())
     }
 }
 
@@ -111,3 +604,95 @@ impl From<std::convert::Infallible> for HostnameError {
         unreachable!("Infallible conversions cannot fail")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_report_for_server_type_mismatch() {
+        let error: CVMFSScraperError =
+            ScrapeError::ServerTypeMismatch("example.com is not a Stratum0".to_string()).into();
+        let report = error.report();
+        assert_eq!(report.kind, ErrorKind::ServerTypeMismatch);
+        assert_eq!(
+            report.message,
+            "Scrape error: Server type mismatch: example.com is not a Stratum0"
+        );
+        assert_eq!(report.url, None);
+        assert_eq!(report.status, None);
+    }
+
+    #[test]
+    fn test_error_roundtrip_through_json() {
+        let error: CVMFSScraperError = ManifestError::MissingField('C').into();
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "kind": "parse",
+                "message": "Manifest error: Missing field C",
+                "url": null,
+                "status": null,
+                "phase": null,
+                "repository": null,
+            })
+        );
+
+        let restored: CVMFSScraperError = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.to_string(), error.to_string());
+        assert_eq!(restored.report(), error.report());
+    }
+
+    #[test]
+    fn test_error_report_for_fetch_error() {
+        let error: CVMFSScraperError =
+            ScrapeError::FetchError(FetchError::status("http://example.com/x", 404)).into();
+        let report = error.report();
+        assert_eq!(report.kind, ErrorKind::HttpStatus);
+        assert_eq!(
+            report.message,
+            "Scrape error: Failed to scrape: HTTP status 404 Not Found for url (http://example.com/x)"
+        );
+        assert_eq!(report.url, Some("http://example.com/x".to_string()));
+        assert_eq!(report.status, Some(404));
+    }
+
+    #[test]
+    fn test_error_report_with_phase() {
+        let error: CVMFSScraperError =
+            ScrapeError::FetchError(FetchError::status("http://example.com/x", 404)).into();
+        let message = error.to_string();
+        let error = error
+            .in_phase(ScrapePhase::StatusJson, Some("software.eessi.io"))
+            .in_phase(ScrapePhase::Manifest, None);
+        assert_eq!(error.to_string(), message);
+        assert_eq!(error.phase(), Some(ScrapePhase::StatusJson));
+
+        let report = error.report();
+        assert_eq!(report.kind, ErrorKind::HttpStatus);
+        assert_eq!(report.url, Some("http://example.com/x".to_string()));
+        assert_eq!(report.status, Some(404));
+        assert_eq!(report.phase, Some(ScrapePhase::StatusJson));
+        assert_eq!(report.repository, Some("software.eessi.io".to_string()));
+
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["phase"], "status_json");
+        let restored: CVMFSScraperError = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.report(), report);
+        assert_eq!(restored.phase(), Some(ScrapePhase::StatusJson));
+    }
+
+    #[test]
+    fn test_error_report_without_phase_deserializes() {
+        let report: ErrorReport = serde_json::from_value(serde_json::json!({
+            "kind": "timeout",
+            "message": "timed out",
+            "url": "http://example.com/x",
+            "status": null,
+        }))
+        .unwrap();
+        assert_eq!(report.phase, None);
+        assert_eq!(report.repository, None);
+    }
+}
@@ -0,0 +1,511 @@
+//! Render scrape results as Prometheus/OpenMetrics text.
+//!
+//! The exporter turns a scrape run into the OpenMetrics text exposition format, and
+//! `MetricsEndpoint` serves the latest rendered run over HTTP at `/metrics`.
+//!
+//! This module requires the `prometheus` feature.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+
+use crate::models::{Hostname, MaybeRfc2822DateTime, PopulatedRepositoryOrReplica, ScrapedServer};
+
+/// The content type of the OpenMetrics text exposition format.
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Renders scrape results in the OpenMetrics text format.
+///
+/// The following metric families are produced, all labelled by `server` (the key of the server,
+/// see `ScrapedServer::key`), and `repository` for the per-repository families:
+///
+/// - cvmfs_scrape_success: 1 if the server was scraped, 0 if it failed
+/// - cvmfs_scrape_duration_seconds: Time spent scraping the server
+/// - cvmfs_server (info): Server type, backend and CVMFS/OS version labels
+/// - cvmfs_repository_scrape_success: 1 if the repository was scraped, 0 if it failed
+/// - cvmfs_repository_revision: The revision of the repository (`Manifest::s`)
+/// - cvmfs_repository_timestamp_seconds: The timestamp of the revision (`Manifest::t`)
+/// - cvmfs_repository_ttl_seconds: The TTL of the root catalog (`Manifest::d`)
+/// - cvmfs_repository_last_snapshot_age_seconds: Time since the last snapshot, if known
+/// - cvmfs_repository_last_gc_age_seconds: Time since the last garbage collection, if known
+/// - cvmfs_repository_whitelist_expiry_seconds: Time until the whitelist expires (negative once
+///   expired), if the whitelist was fetched (see `ScraperCommon::fetch_whitelists`)
+/// - cvmfs_geoapi_order_correct: 1 if the GeoAPI order matches the expected order, 0 if not
+///
+/// The GeoAPI family is only produced for servers with an expected order, see
+/// `with_expected_geoapi_order`.
+///
+/// ### Example
+///
+/// ```rust
+/// use cvmfs_server_scraper::OpenMetricsExporter;
+///
+/// let exporter = OpenMetricsExporter::new();
+/// let text = exporter.render(&[]);
+/// assert!(text.ends_with("# EOF\n"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OpenMetricsExporter {
+    expected_geoapi_order: HashMap<String, Vec<Hostname>>,
+}
+
+impl OpenMetricsExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the expected GeoAPI order of the hosts for the server with the given key.
+    ///
+    /// The key is as given by `Server::key`, e.g. "stratum1.example.com". The order is compared
+    /// to the GeoAPI response of the server, mapped to hostnames.
+    pub fn with_expected_geoapi_order<S: Into<String>>(
+        mut self,
+        server: S,
+        order: Vec<Hostname>,
+    ) -> Self {
+        self.expected_geoapi_order.insert(server.into(), order);
+        self
+    }
+
+    /// Render the scrape results, computing ages relative to the current time.
+    pub fn render(&self, servers: &[ScrapedServer]) -> String {
+        self.render_at(servers, Utc::now())
+    }
+
+    /// Render the scrape results, computing ages relative to the given time.
+    pub fn render_at(&self, servers: &[ScrapedServer], now: DateTime<Utc>) -> String {
+        let mut families = Families::default();
+
+        for server in servers {
+            let key = server.key();
+            let server_labels = [("server", key.as_str())];
+            match server {
+                ScrapedServer::Populated(populated) => {
+                    families.push("cvmfs_scrape_success", &server_labels, 1.0);
+                    families.push(
+                        "cvmfs_scrape_duration_seconds",
+                        &server_labels,
+                        populated.scrape_duration.as_secs_f64(),
+                    );
+
+                    let server_type = format!("{:?}", populated.server_type);
+                    let backend = format!("{:?}", populated.backend_detected);
+                    let cvmfs_version = populated
+                        .metadata
+                        .cvmfs_version
+                        .as_ref()
+                        .map(|v| v.to_string())
+                        .unwrap_or_default();
+                    let os_id = populated.metadata.os_id.clone().unwrap_or_default();
+                    let os_version_id =
+                        populated.metadata.os_version_id.clone().unwrap_or_default();
+                    families.push(
+                        "cvmfs_server_info",
+                        &[
+                            ("server", key.as_str()),
+                            ("server_type", server_type.as_str()),
+                            ("backend", backend.as_str()),
+                            ("cvmfs_version", cvmfs_version.as_str()),
+                            ("os_id", os_id.as_str()),
+                            ("os_version_id", os_version_id.as_str()),
+                        ],
+                        1.0,
+                    );
+
+                    for repo in &populated.repositories {
+                        self.render_repository(&mut families, &key, repo, now);
+                    }
+                    for repo in &populated.failed_repositories {
+                        families.push(
+                            "cvmfs_repository_scrape_success",
+                            &[("server", key.as_str()), ("repository", &repo.name)],
+                            0.0,
+                        );
+                    }
+
+                    if let Some(expected) = self.expected_geoapi_order.get(&key) {
+                        let correct = populated
+                            .geoapi
+                            .check_against_expected_order_by_hostname(expected.clone())
+                            .unwrap_or(false);
+                        families.push(
+                            "cvmfs_geoapi_order_correct",
+                            &server_labels,
+                            if correct { 1.0 } else { 0.0 },
+                        );
+                    }
+                }
+                ScrapedServer::Failed(failed) => {
+                    families.push("cvmfs_scrape_success", &server_labels, 0.0);
+                    families.push(
+                        "cvmfs_scrape_duration_seconds",
+                        &server_labels,
+                        failed.scrape_duration.as_secs_f64(),
+                    );
+                }
+            }
+        }
+
+        families.render()
+    }
+
+    fn render_repository(
+        &self,
+        families: &mut Families,
+        key: &str,
+        repo: &PopulatedRepositoryOrReplica,
+        now: DateTime<Utc>,
+    ) {
+        let labels = [("server", key), ("repository", repo.name.as_str())];
+        families.push("cvmfs_repository_scrape_success", &labels, 1.0);
+        families.push("cvmfs_repository_revision", &labels, repo.manifest.s as f64);
+        families.push(
+            "cvmfs_repository_timestamp_seconds",
+            &labels,
+            repo.manifest.t as f64,
+        );
+        families.push(
+            "cvmfs_repository_ttl_seconds",
+            &labels,
+            repo.manifest.d as f64,
+        );
+        if let Some(age) = age_in_seconds(repo.last_snapshot.as_ref(), now) {
+            families.push("cvmfs_repository_last_snapshot_age_seconds", &labels, age);
+        }
+        if let Some(age) = age_in_seconds(repo.last_gc.as_ref(), now) {
+            families.push("cvmfs_repository_last_gc_age_seconds", &labels, age);
+        }
+        if let Some(whitelist) = &repo.whitelist {
+            families.push(
+                "cvmfs_repository_whitelist_expiry_seconds",
+                &labels,
+                whitelist.time_to_expiry_at(now).num_seconds() as f64,
+            );
+        }
+    }
+}
+
+/// Seconds between the given date and now, if the date is present and parseable.
+fn age_in_seconds(date: Option<&MaybeRfc2822DateTime>, now: DateTime<Utc>) -> Option<f64> {
+    let date = date?.try_into_datetime().ok()??;
+    Some((now - date).num_milliseconds() as f64 / 1000.0)
+}
+
+/// The type, help text and OpenMetrics family name of each metric we produce.
+///
+/// The order here is the order in which the families are rendered.
+const FAMILIES: &[(&str, &str, &str, &str)] = &[
+    (
+        "cvmfs_scrape_success",
+        "cvmfs_scrape_success",
+        "gauge",
+        "Whether the server was scraped successfully.",
+    ),
+    (
+        "cvmfs_scrape_duration_seconds",
+        "cvmfs_scrape_duration_seconds",
+        "gauge",
+        "Time spent scraping the server.",
+    ),
+    (
+        "cvmfs_server_info",
+        "cvmfs_server",
+        "info",
+        "Server type, backend and version information.",
+    ),
+    (
+        "cvmfs_repository_scrape_success",
+        "cvmfs_repository_scrape_success",
+        "gauge",
+        "Whether the repository was scraped successfully.",
+    ),
+    (
+        "cvmfs_repository_revision",
+        "cvmfs_repository_revision",
+        "gauge",
+        "Revision number of the published repository.",
+    ),
+    (
+        "cvmfs_repository_timestamp_seconds",
+        "cvmfs_repository_timestamp_seconds",
+        "gauge",
+        "Unix timestamp of the published revision.",
+    ),
+    (
+        "cvmfs_repository_ttl_seconds",
+        "cvmfs_repository_ttl_seconds",
+        "gauge",
+        "Time to live of the root catalog.",
+    ),
+    (
+        "cvmfs_repository_last_snapshot_age_seconds",
+        "cvmfs_repository_last_snapshot_age_seconds",
+        "gauge",
+        "Time since the last snapshot of the replica.",
+    ),
+    (
+        "cvmfs_repository_last_gc_age_seconds",
+        "cvmfs_repository_last_gc_age_seconds",
+        "gauge",
+        "Time since the last garbage collection of the repository.",
+    ),
+    (
+        "cvmfs_repository_whitelist_expiry_seconds",
+        "cvmfs_repository_whitelist_expiry_seconds",
+        "gauge",
+        "Time until the whitelist of the repository expires.",
+    ),
+    (
+        "cvmfs_geoapi_order_correct",
+        "cvmfs_geoapi_order_correct",
+        "gauge",
+        "Whether the GeoAPI order of the server matches the expected order.",
+    ),
+];
+
+/// Samples grouped by metric, as OpenMetrics requires each family to be contiguous.
+#[derive(Default)]
+struct Families {
+    samples: HashMap<&'static str, Vec<String>>,
+}
+
+impl Families {
+    fn push(&mut self, metric: &'static str, labels: &[(&str, &str)], value: f64) {
+        let labels = labels
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+            .collect::<Vec<String>>()
+            .join(",");
+        self.samples
+            .entry(metric)
+            .or_default()
+            .push(format!("{}{{{}}} {}", metric, labels, value));
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for (metric, family, kind, help) in FAMILIES {
+            if let Some(samples) = self.samples.get(metric) {
+                let _ = writeln!(out, "# TYPE {} {}", family, kind);
+                let _ = writeln!(out, "# HELP {} {}", family, help);
+                for sample in samples {
+                    let _ = writeln!(out, "{}", sample);
+                }
+            }
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// A small HTTP endpoint serving the latest scrape results as OpenMetrics at `/metrics`.
+///
+/// The endpoint is cheap to clone, and clones share the same results. Update the results with
+/// `update` after each scrape run.
+///
+/// ### Example
+///
+/// ```rust,no_run
+/// use cvmfs_server_scraper::{MetricsEndpoint, OpenMetricsExporter, Scraper};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let endpoint = MetricsEndpoint::new(OpenMetricsExporter::new());
+///     let listener = tokio::net::TcpListener::bind("127.0.0.1:9100").await?;
+///     tokio::spawn(endpoint.clone().serve(listener));
+///
+///     let scraper = Scraper::new().with_servers(vec![]).validate()?;
+///     loop {
+///         endpoint.update(scraper.scrape().await).await;
+///         tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MetricsEndpoint {
+    exporter: Arc<OpenMetricsExporter>,
+    servers: Arc<RwLock<Vec<ScrapedServer>>>,
+}
+
+impl MetricsEndpoint {
+    pub fn new(exporter: OpenMetricsExporter) -> Self {
+        MetricsEndpoint {
+            exporter: Arc::new(exporter),
+            servers: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    /// Replace the served results with the given scrape run.
+    pub async fn update(&self, servers: Vec<ScrapedServer>) {
+        *self.servers.write().await = servers;
+    }
+
+    /// Render the current results.
+    pub async fn render(&self) -> String {
+        self.exporter.render(&self.servers.read().await)
+    }
+
+    /// An axum router serving `/metrics`, for embedding into an existing application.
+    pub fn router(&self) -> axum::Router {
+        let endpoint = self.clone();
+        axum::Router::new().route(
+            "/metrics",
+            axum::routing::get(move || async move {
+                (
+                    [(axum::http::header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)],
+                    endpoint.render().await,
+                )
+            }),
+        )
+    }
+
+    /// Serve `/metrics` on the given listener until the task is cancelled or fails.
+    pub async fn serve(self, listener: tokio::net::TcpListener) -> std::io::Result<()> {
+        axum::serve(listener, self.router()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ServerType;
+    use crate::test_helpers::{failed_server, populated_server};
+    use yare::parameterized;
+
+    fn servers() -> Vec<ScrapedServer> {
+        vec![
+            populated_server(
+                "s1.example.com",
+                ServerType::Stratum1,
+                &[("software.eessi.io", 42, 1718990402)],
+            ),
+            failed_server("s2.example.com"),
+        ]
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2024-06-21T18:40:02Z".parse().unwrap()
+    }
+
+    #[parameterized(
+        success = { "cvmfs_scrape_success{server=\"s1.example.com\"} 1" },
+        failure = { "cvmfs_scrape_success{server=\"s2.example.com\"} 0" },
+        duration = { "cvmfs_scrape_duration_seconds{server=\"s1.example.com\"} 0.25" },
+        revision = { "cvmfs_repository_revision{server=\"s1.example.com\",repository=\"software.eessi.io\"} 42" },
+        timestamp = { "cvmfs_repository_timestamp_seconds{server=\"s1.example.com\",repository=\"software.eessi.io\"} 1718990402" },
+        ttl = { "cvmfs_repository_ttl_seconds{server=\"s1.example.com\",repository=\"software.eessi.io\"} 240" },
+        snapshot_age = { "cvmfs_repository_last_snapshot_age_seconds{server=\"s1.example.com\",repository=\"software.eessi.io\"} 3600" },
+        whitelist_expiry = { "cvmfs_repository_whitelist_expiry_seconds{server=\"s1.example.com\",repository=\"software.eessi.io\"} 2179201" },
+        info = { "cvmfs_server_info{server=\"s1.example.com\",server_type=\"Stratum1\",backend=\"CVMFS\",cvmfs_version=\"2.11.3\",os_id=\"\",os_version_id=\"\"} 1" },
+        info_type = { "# TYPE cvmfs_server info" },
+    )]
+    fn test_render_contains(line: &str) {
+        let text = OpenMetricsExporter::new().render_at(&servers(), now());
+        assert!(
+            text.lines().any(|l| l == line),
+            "Missing {:?} in:\n{}",
+            line,
+            text
+        );
+    }
+
+    #[test]
+    fn test_render_groups_families_and_ends_with_eof() {
+        let text = OpenMetricsExporter::new().render_at(&servers(), now());
+        assert!(text.ends_with("# EOF\n"));
+        assert_eq!(text.matches("# TYPE cvmfs_scrape_success gauge").count(), 1);
+        // No GC date in the fixture, and no expected GeoAPI order configured.
+        assert!(!text.contains("cvmfs_repository_last_gc_age_seconds"));
+        assert!(!text.contains("cvmfs_geoapi_order_correct"));
+    }
+
+    #[parameterized(
+        correct = { vec![0, 1], 1 },
+        incorrect = { vec![1, 0], 0 },
+    )]
+    fn test_render_geoapi_order(response: Vec<u32>, expected: u32) {
+        let mut servers = servers();
+        if let ScrapedServer::Populated(populated) = &mut servers[0] {
+            populated.geoapi.geoapi_hosts = vec![
+                "a.example.com".parse().unwrap(),
+                "b.example.com".parse().unwrap(),
+            ];
+            populated.geoapi.response = response;
+        }
+        let exporter = OpenMetricsExporter::new().with_expected_geoapi_order(
+            "s1.example.com",
+            vec![
+                "a.example.com".parse().unwrap(),
+                "b.example.com".parse().unwrap(),
+            ],
+        );
+        let text = exporter.render_at(&servers, now());
+        assert!(text.contains(&format!(
+            "cvmfs_geoapi_order_correct{{server=\"s1.example.com\"}} {}",
+            expected
+        )));
+    }
+
+    #[test]
+    fn test_pinned_servers_get_distinct_series() {
+        let repositories = [("software.eessi.io", 42, 1718990402)];
+        let servers = vec![
+            populated_server(
+                "s1.example.com@192.0.2.1",
+                ServerType::Stratum1,
+                &repositories,
+            ),
+            populated_server(
+                "s1.example.com@192.0.2.2",
+                ServerType::Stratum1,
+                &repositories,
+            ),
+        ];
+        let text = OpenMetricsExporter::new().render_at(&servers, now());
+        for ip in ["192.0.2.1", "192.0.2.2"] {
+            let line = format!(
+                "cvmfs_repository_revision{{server=\"s1.example.com@{}\",repository=\"software.eessi.io\"}} 42",
+                ip
+            );
+            assert!(
+                text.lines().any(|l| l == line),
+                "Missing {:?} in:\n{}",
+                line,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_escape_label_value() {
+        assert_eq!(escape_label_value("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_serves_latest_results() {
+        let endpoint = MetricsEndpoint::new(OpenMetricsExporter::new());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(endpoint.clone().serve(listener));
+
+        endpoint.update(servers()).await;
+        let response = reqwest::get(format!("http://{}/metrics", addr))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], OPENMETRICS_CONTENT_TYPE);
+        let body = response.text().await.unwrap();
+        assert!(body.contains("cvmfs_scrape_success{server=\"s1.example.com\"} 1"));
+        assert!(body.ends_with("# EOF\n"));
+    }
+}
@@ -0,0 +1,393 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::trace;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::client::ClientConfig;
+use crate::errors::{FetchError, ScrapeError};
+use crate::utilities::generate_random_string;
+
+/// The placeholder used for the random segment of GeoAPI URLs in `normalize_url`.
+pub const GEOAPI_PLACEHOLDER: &str = "_";
+
+/// A response returned by a `Fetcher`.
+///
+/// Fields:
+///
+/// - status: The HTTP status code
+/// - headers: The response headers as (name, value) pairs, in the order they were received
+/// - body: The response body
+///
+/// When serialized, a body that is valid UTF-8 is stored as `{"text": "..."}`, any other body
+/// (e.g. a signed manifest or a certificate) as `{"base64": "..."}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FetchResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(with = "body_encoding")]
+    pub body: Vec<u8>,
+}
+
+impl FetchResponse {
+    /// A response with the given status and body, and no headers.
+    pub fn new<B: Into<Vec<u8>>>(status: u16, body: B) -> Self {
+        FetchResponse {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    /// A 200 OK response with the given body.
+    pub fn ok<B: Into<Vec<u8>>>(body: B) -> Self {
+        FetchResponse::new(200, body)
+    }
+
+    /// A 404 Not Found response with an empty body.
+    pub fn not_found() -> Self {
+        FetchResponse::new(404, Vec::new())
+    }
+
+    /// Add a header to the response.
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Returns true if the status is 2xx.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// The body of a successful response, or a `FetchError` for any other status.
+    pub fn into_body(self, url: &str) -> Result<Vec<u8>, FetchError> {
+        if self.is_success() {
+            Ok(self.body)
+        } else {
+            Err(FetchError::status(url, self.status))
+        }
+    }
+}
+
+mod body_encoding {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Body {
+        Text(String),
+        Base64(String),
+    }
+
+    pub fn serialize<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(body) {
+            Ok(text) => Body::Text(text.to_string()),
+            Err(_) => Body::Base64(STANDARD.encode(body)),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        match Body::deserialize(deserializer)? {
+            Body::Text(text) => Ok(text.into_bytes()),
+            Body::Base64(encoded) => STANDARD.decode(encoded).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// The transport used by the scraper to fetch files from servers.
+///
+/// The scraper builds every URL it needs (repositories.json, meta.json, the per-repository files,
+/// GeoAPI queries) and hands it to a fetcher, which returns the response or a `FetchError` if no
+/// response was received. Responses with a status other than 2xx are treated as errors by the
+/// scraper. Retries, limits on in-flight requests and the record of fetches are handled by the
+/// scraper, so a fetcher only makes a single attempt.
+///
+/// Implementations:
+///
+/// - `ReqwestFetcher`: Fetches over HTTP(S), this is the default.
+/// - `DirectoryFetcher`: Serves files from a local mirror of the `/cvmfs/...` layout.
+/// - `RecordingFetcher`: Records every fetch of another fetcher to a `Cassette`.
+/// - `ReplayFetcher`: Replays the responses of a `Cassette`.
+///
+/// Use `ScraperCommon::fetcher()` to scrape with a different fetcher.
+#[async_trait]
+pub trait Fetcher: std::fmt::Debug + Send + Sync {
+    /// Fetch the given URL.
+    ///
+    /// A missing file should be a 404 response, the scraper treats optional files (e.g. the
+    /// replication markers) as absent on 404.
+    async fn fetch(&self, url: &str) -> Result<FetchResponse, FetchError>;
+
+    /// A fetcher that sends requests for the given hostname to the given IP address.
+    ///
+    /// Used for servers given as `name@ip`. Returns None (the default) if the fetcher does not
+    /// resolve hostnames, in which case the fetcher itself is used.
+    fn pinned(&self, hostname: &str, ip: IpAddr) -> Result<Option<Arc<dyn Fetcher>>, ScrapeError> {
+        let _ = (hostname, ip);
+        Ok(None)
+    }
+
+    /// The random segment of GeoAPI URLs, which keeps caches from answering the query.
+    ///
+    /// Fetchers replaying recorded responses return a fixed value, so replays are deterministic.
+    fn geoapi_cache_buster(&self) -> String {
+        generate_random_string(12)
+    }
+
+    /// The time the scrape is taken to happen at, used e.g. to check whitelist expiry.
+    ///
+    /// Returns None (the default) for the current time. Fetchers replaying recorded responses
+    /// return the time of the recording, so replays are deterministic.
+    fn scrape_time(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+}
+
+#[async_trait]
+impl<F: Fetcher + ?Sized> Fetcher for Arc<F> {
+    async fn fetch(&self, url: &str) -> Result<FetchResponse, FetchError> {
+        (**self).fetch(url).await
+    }
+
+    fn pinned(&self, hostname: &str, ip: IpAddr) -> Result<Option<Arc<dyn Fetcher>>, ScrapeError> {
+        (**self).pinned(hostname, ip)
+    }
+
+    fn geoapi_cache_buster(&self) -> String {
+        (**self).geoapi_cache_buster()
+    }
+
+    fn scrape_time(&self) -> Option<DateTime<Utc>> {
+        (**self).scrape_time()
+    }
+}
+
+/// A fetcher making HTTP(S) requests with reqwest.
+///
+/// The client is built from a `ClientConfig`, see there for the defaults.
+#[derive(Debug, Clone)]
+pub struct ReqwestFetcher {
+    client: reqwest::Client,
+    config: ClientConfig,
+}
+
+impl ReqwestFetcher {
+    /// Create a fetcher from the given configuration.
+    ///
+    /// This fails if the proxy URL or any of the extra headers are invalid.
+    pub fn new(config: &ClientConfig) -> Result<Self, ScrapeError> {
+        Ok(ReqwestFetcher {
+            client: config.build()?,
+            config: config.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Fetcher for ReqwestFetcher {
+    async fn fetch(&self, url: &str) -> Result<FetchResponse, FetchError> {
+        let response = self.client.get(url).send().await?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        let body = response.bytes().await?.to_vec();
+        Ok(FetchResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    /// The client is built from the same configuration, but does not share the connection pool.
+    /// Requests still use the hostname for the Host header and TLS.
+    fn pinned(&self, hostname: &str, ip: IpAddr) -> Result<Option<Arc<dyn Fetcher>>, ScrapeError> {
+        // The port is ignored by reqwest, the port of the URL is used.
+        let client = self
+            .config
+            .builder()?
+            .resolve(hostname, SocketAddr::new(ip, 0))
+            .build()
+            .map_err(|e| ScrapeError::ClientConfigError(e.to_string()))?;
+        Ok(Some(Arc::new(ReqwestFetcher {
+            client,
+            config: self.config.clone(),
+        })))
+    }
+}
+
+/// A fetcher serving files from a local directory.
+///
+/// The path of the URL is looked up below the root directory, so a mirror of a server is laid out
+/// as the server itself: `<root>/cvmfs/info/v1/repositories.json`,
+/// `<root>/cvmfs/software.eessi.io/.cvmfspublished` and so on. With `per_host(true)` the
+/// hostname of the URL is added as the first directory, `<root>/<hostname>/cvmfs/...`, so a
+/// single root can hold mirrors of several servers. The scheme and port are ignored.
+///
+/// GeoAPI URLs contain a random segment, which is replaced by `GEOAPI_PLACEHOLDER`, e.g.
+/// `<root>/cvmfs/software.eessi.io/api/v1.0/geo/_/<geoapi servers>`.
+///
+/// A missing file is served as 404 Not Found.
+///
+/// ### Example
+///
+/// ```rust
+/// use cvmfs_server_scraper::{DirectoryFetcher, Scraper, ScraperCommon, Server, ServerBackendType,
+///     ServerType};
+///
+/// let scraper = Scraper::new()
+///     .fetcher(DirectoryFetcher::new("tests/fixtures/mirror").per_host(true))
+///     .with_servers(vec![Server::new(
+///         ServerType::Stratum1,
+///         ServerBackendType::CVMFS,
+///         "stratum1.example.org".parse().unwrap(),
+///     )]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectoryFetcher {
+    root: PathBuf,
+    per_host: bool,
+}
+
+impl DirectoryFetcher {
+    /// Create a fetcher serving files from the given directory.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        DirectoryFetcher {
+            root: root.into(),
+            per_host: false,
+        }
+    }
+
+    /// Set whether the hostname of the URL is the first directory below the root.
+    pub fn per_host(mut self, per_host: bool) -> Self {
+        self.per_host = per_host;
+        self
+    }
+
+    /// The file the given URL is served from.
+    pub fn path_for(&self, url: &str) -> Result<PathBuf, FetchError> {
+        let parsed = reqwest::Url::parse(&normalize_url(url))
+            .map_err(|e| FetchError::request(url, format!("Invalid URL: {}", e)))?;
+        let mut path = self.root.clone();
+        if self.per_host {
+            path.push(parsed.host_str().unwrap_or_default());
+        }
+        for segment in parsed.path_segments().into_iter().flatten() {
+            if segment.is_empty() {
+                continue;
+            }
+            path.push(segment);
+        }
+        Ok(path)
+    }
+}
+
+#[async_trait]
+impl Fetcher for DirectoryFetcher {
+    async fn fetch(&self, url: &str) -> Result<FetchResponse, FetchError> {
+        let path = self.path_for(url)?;
+        trace!("Serving {} from {}", url, path.display());
+        match tokio::fs::read(&path).await {
+            Ok(body) => Ok(FetchResponse::ok(body)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(FetchResponse::not_found()),
+            Err(e) => Err(FetchError::request(
+                url,
+                format!("Failed to read {}: {}", path.display(), e),
+            )),
+        }
+    }
+}
+
+/// Replace the random segment of GeoAPI URLs with `GEOAPI_PLACEHOLDER`.
+///
+/// GeoAPI queries are `<repository>/api/v1.0/geo/<random>/<servers>`, where the random segment
+/// defeats caching. Other URLs are returned unchanged.
+pub(crate) fn normalize_url(url: &str) -> String {
+    let Some(index) = url.find("/api/v1.0/geo/") else {
+        return url.to_string();
+    };
+    let (head, tail) = url.split_at(index + "/api/v1.0/geo/".len());
+    match tail.split_once('/') {
+        Some((_, rest)) => format!("{}{}/{}", head, GEOAPI_PLACEHOLDER, rest),
+        None => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yare::parameterized;
+
+    #[parameterized(
+        plain = { "http://s1.example.org/cvmfs/repo/.cvmfspublished", "http://s1.example.org/cvmfs/repo/.cvmfspublished" },
+        geoapi = { "http://s1.example.org/cvmfs/repo/api/v1.0/geo/a1b2c3/x.org,y.org", "http://s1.example.org/cvmfs/repo/api/v1.0/geo/_/x.org,y.org" },
+        geoapi_without_servers = { "http://s1.example.org/cvmfs/repo/api/v1.0/geo/a1b2c3", "http://s1.example.org/cvmfs/repo/api/v1.0/geo/a1b2c3" },
+    )]
+    fn test_normalize_url(url: &str, expected: &str) {
+        assert_eq!(normalize_url(url), expected);
+    }
+
+    #[parameterized(
+        shared = { false, "http://s1.example.org:8080/cvmfs/info/v1/meta.json", "root/cvmfs/info/v1/meta.json" },
+        per_host = { true, "https://s1.example.org/cvmfs/repo/.cvmfswhitelist", "root/s1.example.org/cvmfs/repo/.cvmfswhitelist" },
+        geoapi = { false, "http://s1.example.org/cvmfs/repo/api/v1.0/geo/a1b2c3/x.org", "root/cvmfs/repo/api/v1.0/geo/_/x.org" },
+    )]
+    fn test_directory_fetcher_path(per_host: bool, url: &str, expected: &str) {
+        let fetcher = DirectoryFetcher::new("root").per_host(per_host);
+        assert_eq!(fetcher.path_for(url).unwrap(), PathBuf::from(expected));
+    }
+
+    #[tokio::test]
+    async fn test_directory_fetcher_reads_files() {
+        let fetcher = DirectoryFetcher::new(env!("CARGO_MANIFEST_DIR")).per_host(true);
+        let response = fetcher
+            .fetch("http://tests/fixtures/signing.crt")
+            .await
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert!(String::from_utf8(response.body)
+            .unwrap()
+            .contains("BEGIN CERTIFICATE"));
+    }
+
+    #[tokio::test]
+    async fn test_directory_fetcher_missing_file_is_not_found() {
+        let fetcher = DirectoryFetcher::new(env!("CARGO_MANIFEST_DIR"));
+        let url = "http://s1.example.org/cvmfs/missing/.cvmfspublished";
+        let response = fetcher.fetch(url).await.unwrap();
+        assert_eq!(response, FetchResponse::not_found());
+        let error = response.into_body(url).unwrap_err();
+        assert!(error.is_not_found());
+        assert_eq!(error.url, url);
+    }
+
+    #[parameterized(
+        text = { b"repositories".to_vec(), serde_json::json!({"text": "repositories"}) },
+        binary = { vec![0xff, 0x00, 0x01], serde_json::json!({"base64": "/wAB"}) },
+    )]
+    fn test_fetch_response_body_encoding(body: Vec<u8>, encoded: serde_json::Value) {
+        let response = FetchResponse::ok(body).header("Content-Type", "text/plain");
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["body"], encoded);
+        assert_eq!(
+            json["headers"],
+            serde_json::json!([["Content-Type", "text/plain"]])
+        );
+        let restored: FetchResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(restored, response);
+    }
+}
@@ -0,0 +1,697 @@
+//! A persistent history of scrape results.
+//!
+//! `HistoryStore` records scrape runs in an SQLite database, with the revision, root catalog
+//! hash, snapshot and GC times of every repository and the errors of every failed server and
+//! repository, and answers questions about them over time: which revisions a server served and
+//! when, how available a server was, and what the last good state of a server was.
+//!
+//! This module requires the `history` feature.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ErrorReport, HistoryError};
+use crate::models::{PopulatedServer, ScrapeReport, ScrapedServer};
+
+/// The current version of the history database schema.
+pub const HISTORY_SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA: &str = "
+    CREATE TABLE runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        recorded_at INTEGER NOT NULL
+    );
+    CREATE TABLE servers (
+        run_id INTEGER NOT NULL REFERENCES runs (id),
+        server TEXT NOT NULL,
+        server_type TEXT NOT NULL,
+        backend_type TEXT NOT NULL,
+        ok INTEGER NOT NULL,
+        error_kind TEXT,
+        error TEXT,
+        scrape_duration_ms INTEGER NOT NULL,
+        result TEXT NOT NULL
+    );
+    CREATE TABLE repositories (
+        run_id INTEGER NOT NULL REFERENCES runs (id),
+        server TEXT NOT NULL,
+        repository TEXT NOT NULL,
+        ok INTEGER NOT NULL,
+        revision INTEGER,
+        root_catalog TEXT,
+        published INTEGER,
+        last_snapshot TEXT,
+        last_gc TEXT,
+        error_kind TEXT,
+        error TEXT
+    );
+    CREATE INDEX servers_by_key ON servers (server, run_id);
+    CREATE INDEX repositories_by_name ON repositories (repository, server, run_id);
+";
+
+/// A stretch of consecutive runs in which a server served the same revision and root catalog of
+/// a repository.
+///
+/// Runs in which the server or the repository failed, or the server was not scraped, do not end
+/// a span: if the server serves the same revision and root catalog again afterwards, the span
+/// continues across the gap. `observations` only counts the runs the revision was seen in, so it
+/// is lower than the number of runs between `first_seen` and `last_seen` if there were gaps.
+///
+/// Fields:
+///
+/// - server: The key of the server, see `ScrapedServer::key`
+/// - revision: The revision served
+/// - root_catalog: The root catalog hash of the manifest (`C`)
+/// - published: The timestamp of the revision, from the manifest (`T`)
+/// - first_seen: The first run the revision was seen in
+/// - last_seen: The last run the revision was seen in
+/// - observations: The number of runs the revision was seen in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RevisionSpan {
+    pub server: String,
+    pub revision: i32,
+    pub root_catalog: String,
+    pub published: i64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub observations: u32,
+}
+
+/// A stretch of consecutive runs in which a server failed to scrape.
+///
+/// Fields:
+///
+/// - first_failed: The first failed run
+/// - last_failed: The last failed run
+/// - recovered: The first successful run after the outage, None if the server had not recovered
+///   by the end of the queried window
+/// - failed_runs: The number of failed runs
+/// - error: The error of the first failed run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Outage {
+    pub first_failed: DateTime<Utc>,
+    pub last_failed: DateTime<Utc>,
+    pub recovered: Option<DateTime<Utc>>,
+    pub failed_runs: u32,
+    pub error: ErrorReport,
+}
+
+impl Outage {
+    /// How long the server was down, from the first failed run to the run it recovered in.
+    ///
+    /// None if the server had not recovered.
+    pub fn duration(&self) -> Option<TimeDelta> {
+        self.recovered
+            .map(|recovered| recovered - self.first_failed)
+    }
+}
+
+/// The availability of a server over a window of time.
+///
+/// Fields:
+///
+/// - server: The key of the server, see `ScrapedServer::key`
+/// - from, to: The window, inclusive
+/// - runs: The number of runs the server was scraped in
+/// - successful: The number of runs the server was populated in
+/// - outages: The outages in the window, oldest first
+///
+/// A server is available in a run if it was populated, even if some of its repositories failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Availability {
+    pub server: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub runs: u32,
+    pub successful: u32,
+    pub outages: Vec<Outage>,
+}
+
+impl Availability {
+    /// The fraction of runs the server was available in, None if it was not scraped at all.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.runs > 0).then(|| self.successful as f64 / self.runs as f64)
+    }
+}
+
+/// A populated server as recorded in a run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedServer {
+    pub run_id: i64,
+    pub recorded_at: DateTime<Utc>,
+    pub server: PopulatedServer,
+}
+
+/// A history of scrape runs, stored in SQLite.
+///
+/// Every call to `record` stores one run. The database is created on first use, and a database
+/// written by a newer, unsupported, version of the schema is rejected.
+///
+/// ### Example
+///
+/// ```rust
+/// use cvmfs_server_scraper::{HistoryStore, Scraper};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let history = HistoryStore::in_memory()?;
+///     let servers = Scraper::new().with_servers(vec![]).validate()?.scrape().await;
+///     history.record(&servers)?;
+///
+///     // Servers are identified by their key, see `Server::key`.
+///     let server = "aws-eu-central-s1.eessi.science";
+///     for span in history.revision_timeline("software.eessi.io", Some(server))? {
+///         println!("{}: {} to {}", span.revision, span.first_seen, span.last_seen);
+///     }
+///     assert!(history.latest_good(server)?.is_none());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct HistoryStore {
+    connection: Mutex<Connection>,
+}
+
+impl HistoryStore {
+    /// Open the history database at the given path, creating it if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, HistoryError> {
+        HistoryStore::init(Connection::open(path)?)
+    }
+
+    /// Create a history that is kept in memory only.
+    pub fn in_memory() -> Result<Self, HistoryError> {
+        HistoryStore::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<Self, HistoryError> {
+        let version: u32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > HISTORY_SCHEMA_VERSION {
+            return Err(HistoryError::UnsupportedVersion {
+                found: version,
+                supported: HISTORY_SCHEMA_VERSION,
+            });
+        }
+        if version == 0 {
+            connection.execute_batch(&format!(
+                "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                SCHEMA, HISTORY_SCHEMA_VERSION
+            ))?;
+        }
+        Ok(HistoryStore {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Record the results of a scrape run, made now. Returns the id of the run.
+    pub fn record(&self, servers: &[ScrapedServer]) -> Result<i64, HistoryError> {
+        self.record_at(Utc::now(), servers)
+    }
+
+    /// Record a scrape report, as a run made when the report was generated.
+    pub fn record_report(&self, report: &ScrapeReport) -> Result<i64, HistoryError> {
+        self.record_at(report.generated_at, &report.servers)
+    }
+
+    /// Record the results of a scrape run made at the given time. Returns the id of the run.
+    pub fn record_at(
+        &self,
+        recorded_at: DateTime<Utc>,
+        servers: &[ScrapedServer],
+    ) -> Result<i64, HistoryError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO runs (recorded_at) VALUES (?1)",
+            params![recorded_at.timestamp_millis()],
+        )?;
+        let run_id = transaction.last_insert_rowid();
+
+        for server in servers {
+            let key = server.key();
+            let (server_type, backend_type, duration, error) = match server {
+                ScrapedServer::Populated(populated) => (
+                    populated.server_type,
+                    populated.backend_type,
+                    populated.scrape_duration,
+                    None,
+                ),
+                ScrapedServer::Failed(failed) => (
+                    failed.server_type,
+                    failed.backend_type,
+                    failed.scrape_duration,
+                    Some(failed.error.report()),
+                ),
+            };
+            let (error_kind, error) = error_columns(error.as_ref())?;
+            transaction.execute(
+                "INSERT INTO servers (run_id, server, server_type, backend_type, ok, error_kind,
+                    error, scrape_duration_ms, result)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    run_id,
+                    key,
+                    format!("{:?}", server_type),
+                    format!("{:?}", backend_type),
+                    server.is_ok(),
+                    error_kind,
+                    error,
+                    duration.as_millis() as i64,
+                    serde_json::to_string(server)?,
+                ],
+            )?;
+
+            let ScrapedServer::Populated(populated) = server else {
+                continue;
+            };
+            for repo in &populated.repositories {
+                transaction.execute(
+                    "INSERT INTO repositories (run_id, server, repository, ok, revision,
+                        root_catalog, published, last_snapshot, last_gc)
+                     VALUES (?1, ?2, ?3, 1, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        run_id,
+                        key,
+                        repo.name,
+                        repo.manifest.s,
+                        repo.manifest.c.to_string(),
+                        repo.manifest.t,
+                        repo.last_snapshot.as_ref().and_then(|date| date.0.clone()),
+                        repo.last_gc.as_ref().and_then(|date| date.0.clone()),
+                    ],
+                )?;
+            }
+            for repo in &populated.failed_repositories {
+                let (error_kind, error) = error_columns(Some(&repo.error.report()))?;
+                transaction.execute(
+                    "INSERT INTO repositories (run_id, server, repository, ok, error_kind, error)
+                     VALUES (?1, ?2, ?3, 0, ?4, ?5)",
+                    params![run_id, key, repo.name, error_kind, error],
+                )?;
+            }
+        }
+
+        transaction.commit()?;
+        Ok(run_id)
+    }
+
+    /// The revisions of a repository over time, on the server with the given key or on every
+    /// server.
+    ///
+    /// Consecutive runs in which a server served the same revision and root catalog are merged
+    /// into a single `RevisionSpan`, so a root catalog change without a revision bump starts a new
+    /// span. Runs in which the repository failed or was not scraped are skipped, see
+    /// `RevisionSpan` for how they affect the spans. The spans are sorted by server, then by
+    /// time.
+    pub fn revision_timeline(
+        &self,
+        repository: &str,
+        server: Option<&str>,
+    ) -> Result<Vec<RevisionSpan>, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT r.server, r.revision, r.root_catalog, r.published, runs.recorded_at
+             FROM repositories r JOIN runs ON runs.id = r.run_id
+             WHERE r.repository = ?1 AND r.ok = 1 AND (?2 IS NULL OR r.server = ?2)
+             ORDER BY r.server, runs.recorded_at, runs.id",
+        )?;
+        let rows = statement.query_map(params![repository, server], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i32>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+            ))
+        })?;
+
+        let mut spans: Vec<RevisionSpan> = Vec::new();
+        for row in rows {
+            let (server, revision, root_catalog, published, recorded_at) = row?;
+            let recorded_at = datetime(recorded_at)?;
+            match spans.last_mut() {
+                Some(span)
+                    if span.server == server
+                        && span.revision == revision
+                        && span.root_catalog == root_catalog =>
+                {
+                    span.last_seen = recorded_at;
+                    span.observations += 1;
+                }
+                _ => spans.push(RevisionSpan {
+                    server,
+                    revision,
+                    root_catalog,
+                    published,
+                    first_seen: recorded_at,
+                    last_seen: recorded_at,
+                    observations: 1,
+                }),
+            }
+        }
+        Ok(spans)
+    }
+
+    /// The last time the server with the given key served the given revision of a repository, if
+    /// ever.
+    pub fn last_seen_revision(
+        &self,
+        repository: &str,
+        server: &str,
+        revision: i32,
+    ) -> Result<Option<DateTime<Utc>>, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let last_seen: Option<i64> = connection.query_row(
+            "SELECT MAX(runs.recorded_at)
+             FROM repositories r JOIN runs ON runs.id = r.run_id
+             WHERE r.repository = ?1 AND r.server = ?2 AND r.revision = ?3 AND r.ok = 1",
+            params![repository, server, revision],
+            |row| row.get(0),
+        )?;
+        last_seen.map(datetime).transpose()
+    }
+
+    /// The availability of the server with the given key in the runs recorded between `from` and
+    /// `to`, inclusive.
+    pub fn availability(
+        &self,
+        server: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Availability, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT runs.recorded_at, s.ok, s.error
+             FROM servers s JOIN runs ON runs.id = s.run_id
+             WHERE s.server = ?1 AND runs.recorded_at BETWEEN ?2 AND ?3
+             ORDER BY runs.recorded_at, runs.id",
+        )?;
+        let rows = statement.query_map(
+            params![server, from.timestamp_millis(), to.timestamp_millis()],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, bool>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            },
+        )?;
+
+        let mut availability = Availability {
+            server: server.to_string(),
+            from,
+            to,
+            runs: 0,
+            successful: 0,
+            outages: Vec::new(),
+        };
+        let mut ongoing: Option<Outage> = None;
+        for row in rows {
+            let (recorded_at, ok, error) = row?;
+            let recorded_at = datetime(recorded_at)?;
+            availability.runs += 1;
+            if ok {
+                availability.successful += 1;
+                if let Some(mut outage) = ongoing.take() {
+                    outage.recovered = Some(recorded_at);
+                    availability.outages.push(outage);
+                }
+            } else if let Some(outage) = ongoing.as_mut() {
+                outage.last_failed = recorded_at;
+                outage.failed_runs += 1;
+            } else {
+                let error = error.ok_or_else(|| {
+                    HistoryError::Serialization("Failed server without an error".to_string())
+                })?;
+                ongoing = Some(Outage {
+                    first_failed: recorded_at,
+                    last_failed: recorded_at,
+                    recovered: None,
+                    failed_runs: 1,
+                    error: serde_json::from_str(&error)?,
+                });
+            }
+        }
+        availability.outages.extend(ongoing);
+        Ok(availability)
+    }
+
+    /// The most recent run in which the server with the given key was populated, if any.
+    pub fn latest_good(&self, server: &str) -> Result<Option<RecordedServer>, HistoryError> {
+        let connection = self.connection.lock().unwrap();
+        let row = connection
+            .query_row(
+                "SELECT s.run_id, runs.recorded_at, s.result
+                 FROM servers s JOIN runs ON runs.id = s.run_id
+                 WHERE s.server = ?1 AND s.ok = 1
+                 ORDER BY runs.recorded_at DESC, runs.id DESC
+                 LIMIT 1",
+                params![server],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((run_id, recorded_at, result)) = row else {
+            return Ok(None);
+        };
+        let server = serde_json::from_str::<ScrapedServer>(&result)?
+            .get_populated_server()
+            .map_err(|error| HistoryError::Serialization(error.to_string()))?;
+        Ok(Some(RecordedServer {
+            run_id,
+            recorded_at: datetime(recorded_at)?,
+            server,
+        }))
+    }
+}
+
+/// The error_kind and error columns for an optional error.
+fn error_columns(
+    error: Option<&ErrorReport>,
+) -> Result<(Option<String>, Option<String>), HistoryError> {
+    let Some(error) = error else {
+        return Ok((None, None));
+    };
+    let kind = serde_json::to_value(error.kind)?
+        .as_str()
+        .map(|kind| kind.to_string());
+    Ok((kind, Some(serde_json::to_string(error)?)))
+}
+
+fn datetime(millis: i64) -> Result<DateTime<Utc>, HistoryError> {
+    DateTime::from_timestamp_millis(millis)
+        .ok_or_else(|| HistoryError::Serialization(format!("Invalid timestamp: {}", millis)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorKind;
+    use crate::models::{HexString, ServerType};
+    use crate::test_helpers::{failed_server, populated_server};
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_718_000_000, 0).unwrap() + TimeDelta::minutes(minutes)
+    }
+
+    fn s1(revision: i32) -> ScrapedServer {
+        populated_server(
+            "s1.example.org",
+            ServerType::Stratum1,
+            &[(
+                "software.eessi.io",
+                revision,
+                1_718_000_000 + revision as i64,
+            )],
+        )
+    }
+
+    #[test]
+    fn test_revision_timeline_merges_consecutive_runs() {
+        let history = HistoryStore::in_memory().unwrap();
+        let s0 = |revision| {
+            populated_server(
+                "s0.example.org",
+                ServerType::Stratum0,
+                &[("software.eessi.io", revision, 0)],
+            )
+        };
+        history.record_at(at(0), &[s0(10), s1(9)]).unwrap();
+        history.record_at(at(1), &[s0(10), s1(10)]).unwrap();
+        history.record_at(at(2), &[s0(11), s1(10)]).unwrap();
+        history
+            .record_at(at(3), &[failed_server("s1.example.org")])
+            .unwrap();
+        history.record_at(at(4), &[s1(10)]).unwrap();
+
+        let timeline = history
+            .revision_timeline("software.eessi.io", Some("s1.example.org"))
+            .unwrap();
+        let summary = timeline
+            .iter()
+            .map(|span| {
+                (
+                    span.revision,
+                    span.first_seen,
+                    span.last_seen,
+                    span.observations,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![(9, at(0), at(0), 1), (10, at(1), at(4), 3)]);
+        assert_eq!(
+            timeline[0].root_catalog,
+            "600230b0ba7620426f2e898f1e1f43c5466efe59"
+        );
+        assert_eq!(timeline[1].published, 1_718_000_010);
+
+        let all = history
+            .revision_timeline("software.eessi.io", None)
+            .unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[0].server, "s0.example.org");
+
+        assert_eq!(
+            history
+                .last_seen_revision("software.eessi.io", "s1.example.org", 10)
+                .unwrap(),
+            Some(at(4))
+        );
+        assert_eq!(
+            history
+                .last_seen_revision("software.eessi.io", "s1.example.org", 11)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_revision_timeline_splits_on_root_catalog_changes() {
+        let history = HistoryStore::in_memory().unwrap();
+        let mut republished = s1(10);
+        if let ScrapedServer::Populated(populated) = &mut republished {
+            populated.repositories[0].manifest.c =
+                HexString::new("1111111111111111111111111111111111111111").unwrap();
+        }
+        history.record_at(at(0), &[s1(10)]).unwrap();
+        history.record_at(at(1), &[republished.clone()]).unwrap();
+        history.record_at(at(2), &[republished]).unwrap();
+        history.record_at(at(3), &[s1(10)]).unwrap();
+
+        let summary = history
+            .revision_timeline("software.eessi.io", Some("s1.example.org"))
+            .unwrap()
+            .iter()
+            .map(|span| {
+                (
+                    span.root_catalog.clone(),
+                    span.first_seen,
+                    span.last_seen,
+                    span.observations,
+                )
+            })
+            .collect::<Vec<_>>();
+        let original = "600230b0ba7620426f2e898f1e1f43c5466efe59".to_string();
+        let changed = "1".repeat(40);
+        assert_eq!(
+            summary,
+            vec![
+                (original.clone(), at(0), at(0), 1),
+                (changed, at(1), at(2), 2),
+                (original, at(3), at(3), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_availability_and_outages() {
+        let history = HistoryStore::in_memory().unwrap();
+        let failed = || failed_server("s1.example.org");
+        history.record_at(at(0), &[s1(1)]).unwrap();
+        history.record_at(at(10), &[failed()]).unwrap();
+        history.record_at(at(20), &[failed()]).unwrap();
+        history.record_at(at(30), &[s1(1)]).unwrap();
+        history.record_at(at(40), &[failed()]).unwrap();
+
+        let server = "s1.example.org";
+        let availability = history.availability(server, at(0), at(40)).unwrap();
+        assert_eq!(availability.runs, 5);
+        assert_eq!(availability.successful, 2);
+        assert_eq!(availability.ratio(), Some(0.4));
+        assert_eq!(availability.outages.len(), 2);
+
+        let outage = &availability.outages[0];
+        assert_eq!(outage.first_failed, at(10));
+        assert_eq!(outage.last_failed, at(20));
+        assert_eq!(outage.recovered, Some(at(30)));
+        assert_eq!(outage.failed_runs, 2);
+        assert_eq!(outage.duration(), Some(TimeDelta::minutes(20)));
+        assert_eq!(outage.error.kind, ErrorKind::ServerTypeMismatch);
+        assert_eq!(availability.outages[1].recovered, None);
+        assert_eq!(availability.outages[1].duration(), None);
+
+        let window = history.availability(server, at(5), at(25)).unwrap();
+        assert_eq!(window.runs, 2);
+        assert_eq!(window.ratio(), Some(0.0));
+
+        let unknown = history
+            .availability("unknown.example.org", at(0), at(40))
+            .unwrap();
+        assert_eq!(unknown.ratio(), None);
+    }
+
+    #[test]
+    fn test_latest_good_state() {
+        let history = HistoryStore::in_memory().unwrap();
+        let server = "s1.example.org";
+        assert_eq!(history.latest_good(server).unwrap(), None);
+
+        history.record_at(at(0), &[s1(1)]).unwrap();
+        let run = history.record_at(at(1), &[s1(2)]).unwrap();
+        history
+            .record_at(at(2), &[failed_server("s1.example.org")])
+            .unwrap();
+
+        let latest = history.latest_good(server).unwrap().unwrap();
+        assert_eq!(latest.run_id, run);
+        assert_eq!(latest.recorded_at, at(1));
+        assert_eq!(latest.server.repositories[0].revision(), 2);
+    }
+
+    #[test]
+    fn test_history_persists_and_rejects_newer_schemas() {
+        let path = std::env::temp_dir().join(format!(
+            "cvmfs-history-{}-{}.sqlite",
+            std::process::id(),
+            crate::utilities::generate_random_string(8)
+        ));
+        {
+            let history = HistoryStore::open(&path).unwrap();
+            history.record_at(at(0), &[s1(1)]).unwrap();
+        }
+        let history = HistoryStore::open(&path).unwrap();
+        assert!(history.latest_good("s1.example.org").unwrap().is_some());
+        drop(history);
+
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("PRAGMA user_version = 99")
+            .unwrap();
+        assert!(matches!(
+            HistoryStore::open(&path),
+            Err(HistoryError::UnsupportedVersion {
+                found: 99,
+                supported: HISTORY_SCHEMA_VERSION
+            })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
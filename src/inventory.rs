@@ -0,0 +1,820 @@
+//! Declarative inventories of servers and repositories.
+//!
+//! An inventory describes the servers to scrape, together with the forced and ignored
+//! repositories and GeoAPI servers, in a TOML, YAML or JSON file. The format is chosen from the
+//! file extension.
+//!
+//! This module requires the `inventory` feature.
+
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::errors::{HostnameError, InventoryError};
+use crate::models::{Hostname, Scheme, Server, ServerAddress, ServerBackendType, ServerType};
+use crate::scraper::{Scraper, ScraperCommon, WithServers, WithoutServers};
+
+/// A resolved inventory, with every include loaded and every default applied.
+///
+/// Fields:
+///
+/// - servers: The servers to scrape
+/// - forced_repositories: Repositories to scrape even if not listed in repositories.json
+/// - ignored_repositories: Repositories to never scrape
+/// - only_scrape_forced_repositories: Whether to only scrape the forced repositories
+/// - geoapi_servers: The GeoAPI servers to query with, None for the scraper default
+///
+/// ### File format
+///
+/// ```toml
+/// # Paths are relative to this file. Included servers and repositories are added to the ones
+/// # in this file, settings in this file take precedence.
+/// include = ["eessi.toml"]
+///
+/// forced_repositories = ["software.eessi.io"]
+/// ignored_repositories = ["dev.eessi.io"]
+/// only_scrape_forced_repositories = false
+/// geoapi_servers = ["aws-eu-central-s1.eessi.science"]
+///
+/// # Defaults for every server in this file.
+/// [defaults]
+/// server_type = "Stratum1"
+/// backend_type = "AutoDetect"
+///
+/// # Groups set defaults for their servers, overriding the file defaults.
+/// [[groups]]
+/// name = "s3"
+/// backend_type = "S3"
+/// servers = ["s3-s1.example.com"]
+///
+/// # Servers may be given as an address or as a table, overriding the defaults. Addresses may
+/// # be hostnames, IP addresses or hostnames pinned to an IP (see `ServerAddress`).
+/// [[servers]]
+/// hostname = "stratum0.example.com"
+/// server_type = "Stratum0"
+///
+/// # Tables may also set how the server is reached (by default http, port 80 and "/cvmfs").
+/// [[servers]]
+/// hostname = "cdn.example.com"
+/// scheme = "https"
+/// port = 8443
+/// base_path = "/bucket/cvmfs"
+/// ```
+///
+/// If no backend type is given the server uses AutoDetect. A server type must be given, either
+/// on the server, its group or the file defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Inventory {
+    pub servers: Vec<Server>,
+    pub forced_repositories: Vec<String>,
+    pub ignored_repositories: Vec<String>,
+    pub only_scrape_forced_repositories: bool,
+    pub geoapi_servers: Option<Vec<Hostname>>,
+}
+
+/// The format of an inventory file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InventoryFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl InventoryFormat {
+    /// The format given by the extension of the path, if supported.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "toml" => Some(InventoryFormat::Toml),
+            "yaml" | "yml" => Some(InventoryFormat::Yaml),
+            "json" => Some(InventoryFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+impl Inventory {
+    /// Load an inventory file, following its includes.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, InventoryError> {
+        let mut seen = HashSet::new();
+        let file = load_file(path.as_ref(), &mut seen)?;
+        let inventory = Inventory {
+            servers: file.servers,
+            forced_repositories: file.forced_repositories,
+            ignored_repositories: file.ignored_repositories,
+            only_scrape_forced_repositories: file.only_scrape_forced_repositories.unwrap_or(false),
+            geoapi_servers: file.geoapi_servers,
+        };
+        Ok(inventory)
+    }
+
+    /// Build a scraper for the inventory.
+    ///
+    /// The scraper is not validated, so further options may be set before calling `validate()`.
+    pub fn scraper(self) -> Scraper<WithServers> {
+        let mut scraper = Scraper::new()
+            .forced_repositories(self.forced_repositories)
+            .ignored_repositories(self.ignored_repositories)
+            .only_scrape_forced_repositories(self.only_scrape_forced_repositories);
+        if let Some(geoapi_servers) = self.geoapi_servers {
+            // Hostnames are validated when deserialized, so the conversion cannot fail.
+            scraper = scraper.geoapi_servers(geoapi_servers).unwrap();
+        }
+        scraper.with_servers(self.servers)
+    }
+}
+
+impl Scraper<WithoutServers> {
+    /// Create a scraper with the servers and repositories of an inventory file.
+    ///
+    /// See `Inventory` for the file format.
+    ///
+    /// ### Example
+    ///
+    /// ```rust,no_run
+    /// use cvmfs_server_scraper::Scraper;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let scraper = Scraper::from_inventory("inventory.toml")?.validate()?;
+    ///     let servers = scraper.scrape().await;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn from_inventory<P: AsRef<Path>>(path: P) -> Result<Scraper<WithServers>, InventoryError> {
+        Ok(Inventory::load(path)?.scraper())
+    }
+}
+
+/// The defaults for servers in a file or group.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServerDefaults {
+    server_type: Option<ServerType>,
+    backend_type: Option<ServerBackendType>,
+}
+
+impl ServerDefaults {
+    fn or(&self, fallback: &ServerDefaults) -> ServerDefaults {
+        ServerDefaults {
+            server_type: self.server_type.or(fallback.server_type),
+            backend_type: self.backend_type.or(fallback.backend_type),
+        }
+    }
+}
+
+/// A server entry, either a bare hostname or a table overriding the defaults.
+///
+/// The address type is generic so that YAML entries can be parsed after deserializing, see
+/// `parse`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServerEntry<A = ServerAddress> {
+    hostname: A,
+    server_type: Option<ServerType>,
+    backend_type: Option<ServerBackendType>,
+    scheme: Option<Scheme>,
+    port: Option<u16>,
+    base_path: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum ServerEntryOrHostname<A = ServerAddress> {
+    Hostname(A),
+    Entry(ServerEntry<A>),
+}
+
+impl ServerEntryOrHostname<String> {
+    /// Parse the address of an entry deserialized without checking it.
+    fn parse_address(self) -> Result<ServerEntryOrHostname, HostnameError> {
+        Ok(match self {
+            ServerEntryOrHostname::Hostname(hostname) => {
+                ServerEntryOrHostname::Hostname(hostname.parse()?)
+            }
+            ServerEntryOrHostname::Entry(entry) => ServerEntryOrHostname::Entry(ServerEntry {
+                hostname: entry.hostname.parse()?,
+                server_type: entry.server_type,
+                backend_type: entry.backend_type,
+                scheme: entry.scheme,
+                port: entry.port,
+                base_path: entry.base_path,
+            }),
+        })
+    }
+}
+
+// Deserialized by hand rather than untagged, so that errors within an entry (e.g. an invalid
+// hostname) are reported as such and keep their location.
+impl<'de, A> Deserialize<'de> for ServerEntryOrHostname<A>
+where
+    A: Deserialize<'de> + FromStr,
+    A::Err: std::fmt::Display,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct EntryVisitor<A>(PhantomData<A>);
+
+        impl<'de, A> serde::de::Visitor<'de> for EntryVisitor<A>
+        where
+            A: Deserialize<'de> + FromStr,
+            A::Err: std::fmt::Display,
+        {
+            type Value = ServerEntryOrHostname<A>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a hostname or a server entry")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                value
+                    .parse()
+                    .map(ServerEntryOrHostname::Hostname)
+                    .map_err(E::custom)
+            }
+
+            fn visit_map<M: serde::de::MapAccess<'de>>(
+                self,
+                map: M,
+            ) -> Result<Self::Value, M::Error> {
+                ServerEntry::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+                    .map(ServerEntryOrHostname::Entry)
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor(PhantomData))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, bound(deserialize = "E: Deserialize<'de>"))]
+struct Group<E> {
+    name: String,
+    server_type: Option<ServerType>,
+    backend_type: Option<ServerBackendType>,
+    #[serde(default)]
+    servers: Vec<E>,
+}
+
+/// An inventory file as parsed, generic over the server entries so that each format can keep
+/// the location of its entries (see `parse`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, bound(deserialize = "E: Deserialize<'de>"))]
+struct InventoryFile<E> {
+    #[serde(default)]
+    include: Vec<PathBuf>,
+    #[serde(default)]
+    defaults: ServerDefaults,
+    #[serde(default)]
+    groups: Vec<Group<E>>,
+    #[serde(default)]
+    servers: Vec<E>,
+    #[serde(default)]
+    forced_repositories: Vec<String>,
+    #[serde(default)]
+    ignored_repositories: Vec<String>,
+    #[serde(default)]
+    only_scrape_forced_repositories: Option<bool>,
+    #[serde(default)]
+    geoapi_servers: Option<Vec<Hostname>>,
+}
+
+impl<E> InventoryFile<E> {
+    /// Convert every server entry, in groups and at the top level.
+    fn map_entries<T>(
+        self,
+        f: impl Fn(E) -> Result<T, InventoryError>,
+    ) -> Result<InventoryFile<T>, InventoryError> {
+        let mut groups = Vec::with_capacity(self.groups.len());
+        for group in self.groups {
+            groups.push(Group {
+                name: group.name,
+                server_type: group.server_type,
+                backend_type: group.backend_type,
+                servers: group
+                    .servers
+                    .into_iter()
+                    .map(&f)
+                    .collect::<Result<_, _>>()?,
+            });
+        }
+        Ok(InventoryFile {
+            include: self.include,
+            defaults: self.defaults,
+            groups,
+            servers: self.servers.into_iter().map(&f).collect::<Result<_, _>>()?,
+            forced_repositories: self.forced_repositories,
+            ignored_repositories: self.ignored_repositories,
+            only_scrape_forced_repositories: self.only_scrape_forced_repositories,
+            geoapi_servers: self.geoapi_servers,
+        })
+    }
+}
+
+/// A server entry with its one-based line in the file, if the format keeps it.
+#[derive(Debug, Clone)]
+struct LocatedEntry {
+    entry: ServerEntryOrHostname,
+    line: Option<usize>,
+}
+
+/// A file with its includes merged and its servers resolved.
+struct ResolvedFile {
+    servers: Vec<Server>,
+    forced_repositories: Vec<String>,
+    ignored_repositories: Vec<String>,
+    only_scrape_forced_repositories: Option<bool>,
+    geoapi_servers: Option<Vec<Hostname>>,
+}
+
+fn load_file(path: &Path, seen: &mut HashSet<PathBuf>) -> Result<ResolvedFile, InventoryError> {
+    let read_error = |e: std::io::Error| InventoryError::Read {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    };
+    let canonical = path.canonicalize().map_err(read_error)?;
+    if !seen.insert(canonical.clone()) {
+        return Err(InventoryError::IncludeCycle(path.to_path_buf()));
+    }
+
+    let format = InventoryFormat::from_path(path)
+        .ok_or_else(|| InventoryError::UnsupportedFormat(path.to_path_buf()))?;
+    let content = std::fs::read_to_string(path).map_err(read_error)?;
+    let file = parse(path, &content, format)?;
+
+    let mut resolved = ResolvedFile {
+        servers: Vec::new(),
+        forced_repositories: Vec::new(),
+        ignored_repositories: Vec::new(),
+        only_scrape_forced_repositories: None,
+        geoapi_servers: None,
+    };
+
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    for include in &file.include {
+        let included = load_file(&base.join(include), seen)?;
+        resolved.servers.extend(included.servers);
+        resolved
+            .forced_repositories
+            .extend(included.forced_repositories);
+        resolved
+            .ignored_repositories
+            .extend(included.ignored_repositories);
+        resolved.only_scrape_forced_repositories = included
+            .only_scrape_forced_repositories
+            .or(resolved.only_scrape_forced_repositories);
+        resolved.geoapi_servers = included.geoapi_servers.or(resolved.geoapi_servers);
+    }
+    // Only the files on the current include chain count as a cycle, including the same file
+    // twice from different places is fine.
+    seen.remove(&canonical);
+
+    let mut entries = Vec::new();
+    for group in &file.groups {
+        let defaults = ServerDefaults {
+            server_type: group.server_type,
+            backend_type: group.backend_type,
+        }
+        .or(&file.defaults);
+        for entry in &group.servers {
+            entries.push((entry, defaults.clone(), Some(group.name.as_str())));
+        }
+    }
+    for entry in &file.servers {
+        entries.push((entry, file.defaults.clone(), None));
+    }
+
+    for (located, defaults, group) in entries {
+        let invalid = |message: String| InventoryError::Invalid {
+            path: path.to_path_buf(),
+            line: located.line,
+            message,
+        };
+        let server = resolve_server(&located.entry, &defaults).ok_or_else(|| {
+            let hostname = entry_hostname(&located.entry);
+            invalid(match group {
+                Some(group) => format!(
+                    "No server type for {} (set server_type on the server, group {} or defaults)",
+                    hostname, group
+                ),
+                None => format!(
+                    "No server type for {} (set server_type on the server or defaults)",
+                    hostname
+                ),
+            })
+        })?;
+
+        if resolved.servers.iter().any(|s| s.key() == server.key()) {
+            return Err(invalid(match group {
+                Some(group) => format!("Duplicate server {} in group {}", server.key(), group),
+                None => format!("Duplicate server {}", server.key()),
+            }));
+        }
+        resolved.servers.push(server);
+    }
+
+    resolved
+        .forced_repositories
+        .extend(file.forced_repositories);
+    resolved
+        .ignored_repositories
+        .extend(file.ignored_repositories);
+    if file.only_scrape_forced_repositories.is_some() {
+        resolved.only_scrape_forced_repositories = file.only_scrape_forced_repositories;
+    }
+    if file.geoapi_servers.is_some() {
+        resolved.geoapi_servers = file.geoapi_servers;
+    }
+
+    Ok(resolved)
+}
+
+fn entry_hostname(entry: &ServerEntryOrHostname) -> &ServerAddress {
+    match entry {
+        ServerEntryOrHostname::Hostname(hostname) => hostname,
+        ServerEntryOrHostname::Entry(entry) => &entry.hostname,
+    }
+}
+
+fn resolve_server(entry: &ServerEntryOrHostname, defaults: &ServerDefaults) -> Option<Server> {
+    let overrides = match entry {
+        ServerEntryOrHostname::Hostname(_) => ServerDefaults::default(),
+        ServerEntryOrHostname::Entry(entry) => ServerDefaults {
+            server_type: entry.server_type,
+            backend_type: entry.backend_type,
+        },
+    }
+    .or(defaults);
+
+    let mut server = Server::from_address(
+        overrides.server_type?,
+        overrides
+            .backend_type
+            .unwrap_or(ServerBackendType::AutoDetect),
+        entry_hostname(entry).clone(),
+    );
+    if let ServerEntryOrHostname::Entry(entry) = entry {
+        if let Some(scheme) = entry.scheme {
+            server = server.with_scheme(scheme);
+        }
+        if let Some(port) = entry.port {
+            server = server.with_port(port);
+        }
+        if let Some(base_path) = &entry.base_path {
+            server = server.with_base_path(base_path);
+        }
+    }
+    Some(server)
+}
+
+fn parse(
+    path: &Path,
+    content: &str,
+    format: InventoryFormat,
+) -> Result<InventoryFile<LocatedEntry>, InventoryError> {
+    let parse_error = |line: usize, column: usize, message: String| InventoryError::Parse {
+        path: path.to_path_buf(),
+        line,
+        column,
+        message,
+    };
+
+    match format {
+        InventoryFormat::Toml => {
+            let file: InventoryFile<toml::Spanned<ServerEntryOrHostname>> = toml::from_str(content)
+                .map_err(|e| {
+                    let (line, column) = e
+                        .span()
+                        .map(|span| line_and_column(content, span.start))
+                        .unwrap_or((0, 0));
+                    parse_error(line, column, e.message().to_string())
+                })?;
+            file.map_entries(|entry| {
+                Ok(LocatedEntry {
+                    line: Some(line_and_column(content, entry.span().start).0),
+                    entry: entry.into_inner(),
+                })
+            })
+        }
+        InventoryFormat::Yaml => {
+            // serde-saphyr drops the location of custom errors, so addresses are parsed here,
+            // where the location of the entry is known.
+            let file: InventoryFile<serde_saphyr::Spanned<ServerEntryOrHostname<String>>> =
+                serde_saphyr::from_str(content).map_err(|e| {
+                    let (line, column) = e
+                        .location()
+                        .map(|l| (l.line() as usize, l.column() as usize))
+                        .unwrap_or((0, 0));
+                    // The location is reported separately, so keep it out of the message.
+                    let message = match e.without_snippet() {
+                        serde_saphyr::Error::Message { msg, .. } => msg.clone(),
+                        other => other.to_string(),
+                    };
+                    parse_error(line, column, message)
+                })?;
+            file.map_entries(|entry| {
+                let line = entry.referenced.line() as usize;
+                let column = entry.referenced.column() as usize;
+                Ok(LocatedEntry {
+                    entry: entry
+                        .value
+                        .parse_address()
+                        .map_err(|e| parse_error(line, column, e.to_string()))?,
+                    line: Some(line),
+                })
+            })
+        }
+        // serde_json has no spans for values, so JSON entries have no line.
+        InventoryFormat::Json => {
+            let file: InventoryFile<ServerEntryOrHostname> = serde_json::from_str(content)
+                .map_err(|e| parse_error(e.line(), e.column(), e.to_string()))?;
+            file.map_entries(|entry| Ok(LocatedEntry { entry, line: None }))
+        }
+    }
+}
+
+/// The one-based line and column of a byte offset.
+fn line_and_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use yare::parameterized;
+
+    static TEST_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+    /// A unique directory under the system temp dir, removed when dropped.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "cvmfs_server_scraper_inventory_{}_{}_{}",
+                name,
+                std::process::id(),
+                TEST_DIRS.fetch_add(1, Ordering::SeqCst)
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+
+        fn write(&self, name: &str, content: &str) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const TOML: &str = r#"
+forced_repositories = ["software.eessi.io"]
+ignored_repositories = ["dev.eessi.io"]
+
+[defaults]
+server_type = "Stratum1"
+
+[[groups]]
+name = "s3"
+backend_type = "S3"
+servers = ["s3-s1.example.com", { hostname = "s3-sync.example.com", server_type = "SyncServer" }]
+
+[[servers]]
+hostname = "stratum0.example.com"
+server_type = "Stratum0"
+backend_type = "CVMFS"
+
+[[servers]]
+hostname = "s1.example.com"
+"#;
+
+    const YAML: &str = r#"
+forced_repositories: [software.eessi.io]
+ignored_repositories: [dev.eessi.io]
+defaults:
+  server_type: Stratum1
+groups:
+  - name: s3
+    backend_type: S3
+    servers:
+      - s3-s1.example.com
+      - hostname: s3-sync.example.com
+        server_type: SyncServer
+servers:
+  - hostname: stratum0.example.com
+    server_type: Stratum0
+    backend_type: CVMFS
+  - s1.example.com
+"#;
+
+    const JSON: &str = r#"{
+  "forced_repositories": ["software.eessi.io"],
+  "ignored_repositories": ["dev.eessi.io"],
+  "defaults": {"server_type": "Stratum1"},
+  "groups": [
+    {"name": "s3", "backend_type": "S3", "servers": [
+      "s3-s1.example.com",
+      {"hostname": "s3-sync.example.com", "server_type": "SyncServer"}
+    ]}
+  ],
+  "servers": [
+    {"hostname": "stratum0.example.com", "server_type": "Stratum0", "backend_type": "CVMFS"},
+    "s1.example.com"
+  ]
+}"#;
+
+    #[parameterized(
+        toml = { "inventory.toml", TOML },
+        yaml = { "inventory.yaml", YAML },
+        json = { "inventory.json", JSON },
+    )]
+    fn test_load_inventory(name: &str, content: &str) {
+        let dir = TestDir::new(name.split('.').next_back().unwrap());
+        let inventory = Inventory::load(dir.write(name, content)).unwrap();
+
+        let expected = vec![
+            (
+                "s3-s1.example.com",
+                ServerType::Stratum1,
+                ServerBackendType::S3,
+            ),
+            (
+                "s3-sync.example.com",
+                ServerType::SyncServer,
+                ServerBackendType::S3,
+            ),
+            (
+                "stratum0.example.com",
+                ServerType::Stratum0,
+                ServerBackendType::CVMFS,
+            ),
+            (
+                "s1.example.com",
+                ServerType::Stratum1,
+                ServerBackendType::AutoDetect,
+            ),
+        ];
+        let servers = inventory
+            .servers
+            .iter()
+            .map(|s| (s.hostname.to_string(), s.server_type, s.backend_type))
+            .collect::<Vec<_>>();
+        let expected = expected
+            .into_iter()
+            .map(|(h, t, b)| (h.to_string(), t, b))
+            .collect::<Vec<_>>();
+        assert_eq!(servers, expected);
+        assert_eq!(inventory.forced_repositories, vec!["software.eessi.io"]);
+        assert_eq!(inventory.ignored_repositories, vec!["dev.eessi.io"]);
+        assert!(!inventory.only_scrape_forced_repositories);
+        assert!(inventory.geoapi_servers.is_none());
+    }
+
+    #[test]
+    fn test_load_inventory_with_includes() {
+        let dir = TestDir::new("includes");
+        std::fs::create_dir_all(dir.0.join("sub")).unwrap();
+        dir.write(
+            "sub/stratum1.yaml",
+            "defaults:\n  server_type: Stratum1\nservers: [s1.example.com]\nforced_repositories: [a.eessi.io]\nonly_scrape_forced_repositories: true\n",
+        );
+        let path = dir.write(
+            "main.toml",
+            "include = [\"sub/stratum1.yaml\"]\nforced_repositories = [\"b.eessi.io\"]\ngeoapi_servers = [\"s1.example.com\"]\n\n[[servers]]\nhostname = \"s0.example.com\"\nserver_type = \"Stratum0\"\n",
+        );
+
+        let inventory = Inventory::load(path).unwrap();
+        let hostnames = inventory
+            .servers
+            .iter()
+            .map(|s| s.hostname.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(hostnames, vec!["s1.example.com", "s0.example.com"]);
+        assert_eq!(
+            inventory.forced_repositories,
+            vec!["a.eessi.io", "b.eessi.io"]
+        );
+        assert!(inventory.only_scrape_forced_repositories);
+        assert_eq!(
+            inventory.geoapi_servers,
+            Some(vec!["s1.example.com".parse().unwrap()])
+        );
+
+        let scraper = Scraper::from_inventory(dir.0.join("main.toml"))
+            .unwrap()
+            .validate();
+        assert!(scraper.is_ok());
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = TestDir::new("cycle");
+        dir.write("a.toml", "include = [\"b.toml\"]\n");
+        let path = dir.write("b.toml", "include = [\"a.toml\"]\n");
+        assert!(matches!(
+            Inventory::load(path),
+            Err(InventoryError::IncludeCycle(_))
+        ));
+    }
+
+    #[test]
+    fn test_server_urls() {
+        let dir = TestDir::new("urls");
+        let path = dir.write(
+            "urls.toml",
+            "[defaults]\nserver_type = \"Stratum1\"\n\n\
+             [[servers]]\nhostname = \"cdn.example.com\"\n\n\
+             [[servers]]\nhostname = \"cdn.example.com\"\nscheme = \"https\"\nport = 8443\n\
+             base_path = \"bucket/cvmfs/\"\n",
+        );
+        let inventory = Inventory::load(path).unwrap();
+        let urls: Vec<String> = inventory.servers.iter().map(|s| s.base_url()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "http://cdn.example.com/cvmfs",
+                "https://cdn.example.com:8443/bucket/cvmfs"
+            ]
+        );
+    }
+
+    #[parameterized(
+        toml_hostname = { "bad.toml", "[defaults]\nserver_type = \"Stratum1\"\n\n[[servers]]\nhostname = \"bad..example.com\"\n", 5 },
+        toml_server_type = { "bad.toml", "[[servers]]\nhostname = \"s1.example.com\"\nserver_type = \"Stratum2\"\n", 3 },
+        toml_unknown_field = { "bad.toml", "servers = []\nforced_repos = []\n", 2 },
+        yaml_hostname = { "bad.yaml", "defaults:\n  server_type: Stratum1\nservers:\n  - hostname: bad..example.com\n", 4 },
+        json_server_type = { "bad.json", "{\n  \"servers\": [\n    {\"hostname\": \"s1.example.com\", \"server_type\": \"Stratum2\"}\n  ]\n}", 3 },
+    )]
+    fn test_parse_errors_have_line_context(name: &str, content: &str, expected_line: usize) {
+        let dir = TestDir::new(&format!("parse_{}", expected_line));
+        match Inventory::load(dir.write(name, content)) {
+            Err(InventoryError::Parse { path, line, .. }) => {
+                assert!(path.ends_with(name));
+                assert_eq!(line, expected_line);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[parameterized(
+        missing_server_type = { "invalid.toml", "[[servers]]\nhostname = \"s0.example.com\"\n\n[[servers]]\nhostname = \"s1.example.com\"\nserver_type = \"Stratum1\"\n", Some(1), "No server type for s0.example.com (set server_type on the server or defaults)" },
+        duplicate = { "invalid.toml", "[defaults]\nserver_type = \"Stratum1\"\n\n[[groups]]\nname = \"a\"\nservers = [\"s1.example.com\"]\n\n[[groups]]\nname = \"b\"\nservers = [\"s1.example.com\"]\n", Some(10), "Duplicate server s1.example.com in group b" },
+        duplicate_after_group = { "invalid.toml", "servers = [\"s1.example.com\"]\n\n[defaults]\nserver_type = \"Stratum1\"\n\n[[groups]]\nname = \"a\"\nservers = [\"s1.example.com\"]\n", Some(1), "Duplicate server s1.example.com" },
+        yaml_missing_server_type = { "invalid.yaml", "servers:\n  - hostname: s1.example.com\n    server_type: Stratum1\n  - s2.example.com\n", Some(4), "No server type for s2.example.com (set server_type on the server or defaults)" },
+        json_duplicate = { "invalid.json", "{\n  \"defaults\": {\"server_type\": \"Stratum1\"},\n  \"servers\": [\"s1.example.com\", \"s1.example.com\"]\n}", None, "Duplicate server s1.example.com" },
+    )]
+    fn test_validation_errors_name_the_entry(
+        name: &str,
+        content: &str,
+        expected_line: Option<usize>,
+        expected_message: &str,
+    ) {
+        let dir = TestDir::new(&format!("invalid_{}", expected_message.len()));
+        let error = Inventory::load(dir.write(name, content)).unwrap_err();
+        match &error {
+            InventoryError::Invalid { line, message, .. } => {
+                assert_eq!(*line, expected_line);
+                assert_eq!(message, expected_message);
+            }
+            other => panic!("Unexpected error {:?}", other),
+        }
+        let location = match expected_line {
+            Some(line) => format!("{}:{}", name, line),
+            None => name.to_string(),
+        };
+        assert!(error
+            .to_string()
+            .ends_with(&format!("{}: {}", location, expected_message)));
+    }
+
+    #[test]
+    fn test_unsupported_format() {
+        let dir = TestDir::new("format");
+        assert!(matches!(
+            Inventory::load(dir.write("inventory.ini", "")),
+            Err(InventoryError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[parameterized(
+        start = { 0, (1, 1) },
+        second_line = { 4, (2, 1) },
+        second_column = { 5, (2, 2) },
+    )]
+    fn test_line_and_column(offset: usize, expected: (usize, usize)) {
+        assert_eq!(line_and_column("abc\ndef\n", offset), expected);
+    }
+}
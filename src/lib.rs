@@ -71,25 +71,85 @@
 //! }
 //! ```
 
+mod cassette;
+mod client;
 mod constants;
+mod diff;
 mod errors;
+#[cfg(feature = "prometheus")]
+mod exporter;
+mod fetcher;
+#[cfg(feature = "history")]
+mod history;
+#[cfg(feature = "inventory")]
+mod inventory;
+#[cfg(any(test, feature = "test-util"))]
+mod mock;
 mod models;
+mod replication;
+mod repository_view;
 mod scraper;
+#[cfg(feature = "http-api")]
+mod status_api;
+#[cfg(test)]
+mod test_helpers;
 mod utilities;
+#[cfg(feature = "watch")]
+mod watch;
 
-pub use constants::DEFAULT_GEOAPI_SERVERS;
-pub use errors::{CVMFSScraperError, HostnameError, ManifestError, ScrapeError};
+pub use cassette::{Cassette, Interaction, RecordingFetcher, ReplayFetcher, CASSETTE_VERSION};
+pub use client::{
+    ClientConfig, FetchRecord, RedirectPolicy, RetryOn, RetryPolicy, DEFAULT_USER_AGENT,
+};
+pub use constants::{
+    DEFAULT_BASE_PATH, DEFAULT_GEOAPI_SERVERS, DEFAULT_MAX_CONCURRENT_REQUESTS_PER_SERVER,
+};
+pub use diff::{ChangeEvent, ScrapeDiff};
+#[cfg(feature = "history")]
+pub use errors::HistoryError;
+#[cfg(feature = "inventory")]
+pub use errors::InventoryError;
+#[cfg(feature = "watch")]
+pub use errors::WatchError;
+pub use errors::{
+    CVMFSScraperError, CassetteError, ErrorKind, ErrorReport, FetchError, HostnameError,
+    ManifestError, ScrapeError, ScrapePhase,
+};
+#[cfg(feature = "prometheus")]
+pub use exporter::{MetricsEndpoint, OpenMetricsExporter, OPENMETRICS_CONTENT_TYPE};
+pub use fetcher::{DirectoryFetcher, FetchResponse, Fetcher, ReqwestFetcher, GEOAPI_PLACEHOLDER};
+#[cfg(feature = "history")]
+pub use history::{
+    Availability, HistoryStore, Outage, RecordedServer, RevisionSpan, HISTORY_SCHEMA_VERSION,
+};
+#[cfg(feature = "inventory")]
+pub use inventory::{Inventory, InventoryFormat};
+#[cfg(any(test, feature = "test-util"))]
+pub use mock::{MockFault, MockFile, MockRepository, MockServer, MockServerConfig};
 pub use models::{
-    FailedServer, GeoapiServerQuery, Hostname, Manifest, MaybeRfc2822DateTime,
-    PopulatedRepositoryOrReplica, PopulatedServer, ScrapedServer, Server, ServerBackendType,
-    ServerMetadata, ServerType,
+    DateTimeFormat, FailedRepositoryOrReplica, FailedServer, GeoapiServerQuery, Hostname, Manifest,
+    MaybeRfc2822DateTime, ParsedDateTime, PopulatedRepositoryOrReplica, PopulatedServer, Scheme,
+    ScrapeReport, ScrapedServer, Server, ServerAddress, ServerBackendType, ServerMetadata,
+    ServerType, SignatureVerification, TimezoneStatus, Whitelist, SCRAPE_REPORT_VERSION,
+};
+pub use replication::{
+    ReplicaLag, ReplicationReference, ReplicationReport, ReplicationStatus, ReplicationThresholds,
+    RepositoryReplication,
+};
+pub use repository_view::{
+    RepositoryOnServer, RepositoryServers, RepositoryUnavailable, RepositoryView, UnavailableReason,
 };
 pub use scraper::{Scraper, ScraperCommon};
+#[cfg(feature = "http-api")]
+pub use status_api::StatusApi;
+#[cfg(feature = "watch")]
+pub use tokio_util::sync::CancellationToken;
+#[cfg(feature = "watch")]
+pub use watch::{WatchEvent, Watcher, DEFAULT_WATCH_CHANNEL_CAPACITY, DEFAULT_WATCH_INTERVAL};
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tokio;
 
     use futures::future::join_all;
 
@@ -351,7 +411,7 @@ mod tests {
         for result in results {
             match result {
                 ScrapedServer::Populated(popserver) => {
-                    for repo in vec!["software.eessi.io", "dev.eessi.io", "riscv.eessi.io"] {
+                    for repo in ["software.eessi.io", "dev.eessi.io", "riscv.eessi.io"] {
                         assert!(popserver.has_repository(repo));
                     }
                 }
@@ -388,4 +448,227 @@ mod tests {
             }
         }
     }
+
+    const MOCK_REPOS: [&str; 3] = ["software.eessi.io", "dev.eessi.io", "riscv.eessi.io"];
+
+    async fn mock_stratum1() -> MockServer {
+        MockServer::start(
+            MockServerConfig::new(ServerType::Stratum1)
+                .repositories(MOCK_REPOS)
+                .geoapi_order(vec![2, 1, 3]),
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn mock_sync_server() -> MockServer {
+        MockServer::start(
+            MockServerConfig::new(ServerType::SyncServer)
+                .backend_type(ServerBackendType::S3)
+                .repositories(MOCK_REPOS),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_offline_cvmfs_servers_manually() {
+        let stratum1 = mock_stratum1().await;
+        let sync_server = mock_sync_server().await;
+        let servers = vec![
+            stratum1.server(ServerType::Stratum1, ServerBackendType::CVMFS),
+            sync_server.server(ServerType::SyncServer, ServerBackendType::S3),
+        ];
+
+        let futures = servers.into_iter().map(|server| async move {
+            match server
+                .scrape(MOCK_REPOS.to_vec(), vec![], false, None)
+                .await
+            {
+                ScrapedServer::Populated(popserver) => {
+                    for repo in MOCK_REPOS {
+                        assert!(popserver.has_repository(repo));
+                    }
+                }
+                ScrapedServer::Failed(failedserver) => {
+                    panic!("Error: {:?}", failedserver.error);
+                }
+            }
+        });
+
+        join_all(futures).await;
+    }
+
+    #[tokio::test]
+    async fn test_offline_cvmfs_server_mismatch_s0_is_s1() {
+        let mock = mock_stratum1().await;
+        let server = mock.server(ServerType::Stratum0, ServerBackendType::CVMFS);
+
+        match server
+            .scrape(MOCK_REPOS.to_vec(), vec![], false, None)
+            .await
+        {
+            ScrapedServer::Populated(_) => {
+                panic!("Error, should not have succeeded");
+            }
+            ScrapedServer::Failed(failedserver) => {
+                assert_eq!(failedserver.error.to_string(), "Scrape error: Server type mismatch: 127.0.0.1 is a Stratum0 server, but replicas were found in the repositories.json");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_offline_cvmfs_server_s1_has_repos() {
+        let mock = mock_stratum1().await;
+        let server = mock.server(ServerType::Stratum1, ServerBackendType::CVMFS);
+
+        let repoparams: Vec<String> = Vec::new();
+        let popserver = server
+            .scrape(repoparams, vec![], false, None)
+            .await
+            .get_populated_server()
+            .unwrap();
+        for repo in MOCK_REPOS {
+            assert!(popserver.has_repository(repo));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_offline_cvmfs_server_autodetect() {
+        let stratum1 = mock_stratum1().await;
+        let sync_server = mock_sync_server().await;
+
+        for (mock, forced, detected) in [
+            (&stratum1, MOCK_REPOS.to_vec(), ServerBackendType::CVMFS),
+            (&stratum1, vec![], ServerBackendType::CVMFS),
+            (&sync_server, MOCK_REPOS.to_vec(), ServerBackendType::S3),
+        ] {
+            let popserver = mock
+                .server(ServerType::Stratum1, ServerBackendType::AutoDetect)
+                .scrape(forced, vec![], false, None)
+                .await
+                .get_populated_server()
+                .unwrap();
+            assert_eq!(popserver.backend_type, ServerBackendType::AutoDetect);
+            assert_eq!(popserver.backend_detected, detected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_offline_cvmfs_server_s1_cvmfs_backend_metadata() {
+        let mock = mock_stratum1().await;
+        let popserver = mock
+            .server(ServerType::Stratum1, ServerBackendType::CVMFS)
+            .scrape(MOCK_REPOS.to_vec(), vec![], false, None)
+            .await
+            .get_populated_server()
+            .unwrap();
+        assert!(popserver.metadata.schema_version.is_some());
+        assert!(popserver.metadata.cvmfs_version.is_some());
+        assert!(popserver.metadata.last_geodb_update.is_some());
+        assert!(popserver.metadata.os_version_id.is_some());
+        assert!(popserver.metadata.os_pretty_name.is_some());
+        assert!(popserver.metadata.os_id.is_some());
+        assert_eq!(
+            popserver.metadata.administrator,
+            Some("Mock CVMFS Administrators".to_string())
+        );
+        assert_eq!(
+            popserver.metadata.email,
+            Some("cvmfs@example.org".to_string())
+        );
+        assert_eq!(popserver.metadata.organisation, Some("Mock".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_offline_cvmfs_server_s1_s3_backend_no_metadata() {
+        let mock = mock_sync_server().await;
+        let popserver = mock
+            .server(ServerType::SyncServer, ServerBackendType::S3)
+            .scrape(MOCK_REPOS.to_vec(), vec![], false, None)
+            .await
+            .get_populated_server()
+            .unwrap();
+        assert!(popserver.metadata.schema_version.is_none());
+        assert!(popserver.metadata.cvmfs_version.is_none());
+        assert!(popserver.metadata.last_geodb_update.is_none());
+        assert!(popserver.metadata.os_version_id.is_none());
+        assert!(popserver.metadata.os_pretty_name.is_none());
+        assert!(popserver.metadata.os_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_offline_cvmfs_server_s1_ignored_repos() {
+        let mock = mock_stratum1().await;
+        let popserver = mock
+            .server(ServerType::Stratum1, ServerBackendType::CVMFS)
+            .scrape(MOCK_REPOS.to_vec(), vec!["riscv.eessi.io"], false, None)
+            .await
+            .get_populated_server()
+            .unwrap();
+        assert!(popserver.has_repository("software.eessi.io"));
+        assert!(popserver.has_repository("dev.eessi.io"));
+        assert!(!popserver.has_repository("riscv.eessi.io"));
+    }
+
+    #[tokio::test]
+    async fn test_offline_cvmfs_server_s1_only_forced_repos() {
+        let mock = mock_stratum1().await;
+        let popserver = mock
+            .server(ServerType::Stratum1, ServerBackendType::CVMFS)
+            .scrape(
+                vec!["software.eessi.io", "dev.eessi.io"],
+                vec![],
+                true,
+                None,
+            )
+            .await
+            .get_populated_server()
+            .unwrap();
+        assert!(popserver.has_repository("software.eessi.io"));
+        assert!(popserver.has_repository("dev.eessi.io"));
+        assert_eq!(popserver.repositories.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_offline_scraping_using_builder_interface() {
+        let stratum1 = mock_stratum1().await;
+        let sync_server = mock_sync_server().await;
+        let scraper = Scraper::new()
+            .forced_repositories(MOCK_REPOS.to_vec())
+            .geoapi_servers(vec![DEFAULT_GEOAPI_SERVERS[0].clone()])
+            .unwrap()
+            .with_servers(vec![
+                stratum1.server(ServerType::Stratum1, ServerBackendType::CVMFS),
+                stratum1.server(ServerType::Stratum1, ServerBackendType::AutoDetect),
+                sync_server.server(ServerType::SyncServer, ServerBackendType::S3),
+            ]);
+
+        let results = scraper.validate().unwrap().scrape().await;
+        assert_eq!(results.len(), 3);
+        for result in results {
+            let popserver = result.get_populated_server().unwrap();
+            for repo in MOCK_REPOS {
+                assert!(popserver.has_repository(repo));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_offline_geoapi() {
+        let mock = mock_stratum1().await;
+        let results = Scraper::new()
+            .forced_repositories(MOCK_REPOS.to_vec())
+            .with_servers(vec![
+                mock.server(ServerType::Stratum1, ServerBackendType::AutoDetect)
+            ])
+            .validate()
+            .unwrap()
+            .scrape()
+            .await;
+        for result in results {
+            let popserver = result.get_populated_server().unwrap();
+            assert_eq!(popserver.geoapi.response, vec![2, 1, 3]);
+        }
+    }
 }
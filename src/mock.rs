@@ -0,0 +1,710 @@
+//! An in-process mock CVMFS server for tests.
+//!
+//! `MockServer` serves synthetic repositories.json, meta.json and per-repository files on a
+//! local port, and can inject faults into any of them. It is meant for testing code built on
+//! the scraper (e.g. alerting) without real Stratum1 servers.
+//!
+//! This module requires the `test-util` feature.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::{StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde_json::json;
+
+use crate::constants::DEFAULT_BASE_PATH;
+#[cfg(test)]
+use crate::errors::FetchError;
+#[cfg(test)]
+use crate::fetcher::{FetchResponse, Fetcher};
+use crate::models::{Server, ServerAddress, ServerBackendType, ServerType};
+
+/// The files served by a `MockServer`.
+///
+/// RepositoriesJson and MetaJson are server-level files, the others are per repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MockFile {
+    RepositoriesJson,
+    MetaJson,
+    StatusJson,
+    Manifest,
+    Whitelist,
+    LastSnapshot,
+    GeoApi,
+}
+
+/// A fault injected into a file served by a `MockServer`.
+///
+/// NotFound: Respond with 404 Not Found.
+/// Status: Respond with the given status code and an empty body.
+/// Malformed: Respond with 200 OK and a body that cannot be parsed.
+/// Delay: Wait for the given time, then serve the file as usual.
+/// Body: Respond with 200 OK and the given body.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockFault {
+    NotFound,
+    Status(u16),
+    Malformed,
+    Delay(Duration),
+    Body(String),
+}
+
+/// A repository served by a `MockServer`.
+///
+/// Fields:
+///
+/// - name: The name of the repository
+/// - revision: The revision in the manifest
+/// - published: The timestamp of the revision in the manifest
+/// - last_snapshot: The last snapshot in .cvmfs_status.json and .cvmfs_last_snapshot, if any
+/// - last_gc: The last garbage collection in .cvmfs_status.json, if any
+/// - faults: Faults injected into the files of the repository
+///
+/// By default the repository is at revision 1, published and snapshotted now.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRepository {
+    pub name: String,
+    pub revision: i32,
+    pub published: DateTime<Utc>,
+    pub last_snapshot: Option<DateTime<Utc>>,
+    pub last_gc: Option<DateTime<Utc>>,
+    pub faults: HashMap<MockFile, MockFault>,
+}
+
+impl MockRepository {
+    /// Create a repository with the given name.
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        let now = Utc::now();
+        MockRepository {
+            name: name.into(),
+            revision: 1,
+            published: now,
+            last_snapshot: Some(now),
+            last_gc: None,
+            faults: HashMap::new(),
+        }
+    }
+
+    /// Set the revision in the manifest.
+    pub fn revision(mut self, revision: i32) -> Self {
+        self.revision = revision;
+        self
+    }
+
+    /// Set the timestamp of the revision in the manifest.
+    pub fn published(mut self, published: DateTime<Utc>) -> Self {
+        self.published = published;
+        self
+    }
+
+    /// Make the repository stale: published and last snapshotted the given time ago.
+    pub fn stale(mut self, age: TimeDelta) -> Self {
+        let then = Utc::now() - age;
+        self.published = then;
+        self.last_snapshot = Some(then);
+        self
+    }
+
+    /// Set the last garbage collection.
+    pub fn last_gc(mut self, last_gc: DateTime<Utc>) -> Self {
+        self.last_gc = Some(last_gc);
+        self
+    }
+
+    /// Inject a fault into one of the files of the repository.
+    pub fn fault(mut self, file: MockFile, fault: MockFault) -> Self {
+        self.faults.insert(file, fault);
+        self
+    }
+}
+
+/// The configuration of a `MockServer`.
+///
+/// Fields:
+///
+/// - server_type: The server type the repositories.json describes. Stratum0 servers list their
+///   repositories under "repositories", others under "replicas". Scraping the mock as a different
+///   type gives a server type mismatch.
+/// - backend_type: S3 servers serve neither repositories.json nor meta.json
+/// - administrator, email, organisation: The contents of meta.json
+/// - geoapi_order: The GeoAPI response, defaults to the GeoAPI servers in the order given
+/// - repositories: The repositories served
+/// - faults: Faults injected into the server-level files
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockServerConfig {
+    pub server_type: ServerType,
+    pub backend_type: ServerBackendType,
+    pub administrator: String,
+    pub email: String,
+    pub organisation: String,
+    pub geoapi_order: Option<Vec<u32>>,
+    pub repositories: Vec<MockRepository>,
+    pub faults: HashMap<MockFile, MockFault>,
+}
+
+impl MockServerConfig {
+    /// A server of the given type with the CVMFS backend and no repositories.
+    pub fn new(server_type: ServerType) -> Self {
+        MockServerConfig {
+            server_type,
+            backend_type: ServerBackendType::CVMFS,
+            administrator: "Mock CVMFS Administrators".to_string(),
+            email: "cvmfs@example.org".to_string(),
+            organisation: "Mock".to_string(),
+            geoapi_order: None,
+            repositories: Vec::new(),
+            faults: HashMap::new(),
+        }
+    }
+
+    /// Set the backend type. Only CVMFS and S3 are meaningful.
+    pub fn backend_type(mut self, backend_type: ServerBackendType) -> Self {
+        self.backend_type = backend_type;
+        self
+    }
+
+    /// Add a repository.
+    pub fn repository(mut self, repository: MockRepository) -> Self {
+        self.repositories.push(repository);
+        self
+    }
+
+    /// Add repositories with the default settings, by name.
+    pub fn repositories<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.repositories
+            .extend(names.into_iter().map(MockRepository::new));
+        self
+    }
+
+    /// Set the GeoAPI response (1-based indices into the GeoAPI servers of the query).
+    pub fn geoapi_order(mut self, order: Vec<u32>) -> Self {
+        self.geoapi_order = Some(order);
+        self
+    }
+
+    /// Inject a fault into repositories.json or meta.json.
+    pub fn fault(mut self, file: MockFile, fault: MockFault) -> Self {
+        self.faults.insert(file, fault);
+        self
+    }
+
+    /// Get a repository by name, for changing it with `MockServer::update`.
+    pub fn repository_mut(&mut self, name: &str) -> Option<&mut MockRepository> {
+        self.repositories.iter_mut().find(|repo| repo.name == name)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MockState {
+    config: Arc<Mutex<MockServerConfig>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+/// An in-process HTTP server serving synthetic CVMFS files.
+///
+/// The server listens on a random port on 127.0.0.1 until it is dropped. The configuration can
+/// be changed while it runs with `update()`, e.g. to make a repository fall behind between two
+/// scrapes.
+///
+/// ### Example
+///
+/// ```rust
+/// use cvmfs_server_scraper::{MockFault, MockFile, MockRepository, MockServer,
+///     MockServerConfig, ServerBackendType, ServerType};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let mock = MockServer::start(
+///         MockServerConfig::new(ServerType::Stratum1)
+///             .repositories(["software.eessi.io"])
+///             .repository(
+///                 MockRepository::new("dev.eessi.io").fault(MockFile::Manifest, MockFault::NotFound),
+///             ),
+///     )
+///     .await?;
+///
+///     let server = mock.server(ServerType::Stratum1, ServerBackendType::CVMFS);
+///     let populated = server
+///         .scrape(Vec::<String>::new(), vec![], false, None)
+///         .await
+///         .get_populated_server()?;
+///     assert!(populated.has_repository("software.eessi.io"));
+///     assert!(populated.has_failed_repository("dev.eessi.io"));
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct MockServer {
+    address: SocketAddr,
+    state: MockState,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Start a server with the given configuration.
+    pub async fn start(config: MockServerConfig) -> std::io::Result<Self> {
+        let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let address = listener.local_addr()?;
+        let state = MockState {
+            config: Arc::new(Mutex::new(config)),
+            requests: Arc::new(Mutex::new(Vec::new())),
+        };
+        let router = axum::Router::new()
+            .fallback(handle)
+            .with_state(state.clone());
+        let task = tokio::spawn(async move {
+            let _ = axum::serve(listener, router).await;
+        });
+        Ok(MockServer {
+            address,
+            state,
+            task,
+        })
+    }
+
+    /// The address the server listens on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The base URL of the server, e.g. `http://127.0.0.1:12345/cvmfs`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}{}", self.address, DEFAULT_BASE_PATH)
+    }
+
+    /// A `Server` pointing at the mock, to scrape it as the given type and backend.
+    pub fn server(&self, server_type: ServerType, backend_type: ServerBackendType) -> Server {
+        Server::from_address(
+            server_type,
+            backend_type,
+            ServerAddress::from(self.address.ip()),
+        )
+        .with_port(self.address.port())
+    }
+
+    /// Change the configuration of the running server.
+    pub fn update<F: FnOnce(&mut MockServerConfig)>(&self, update: F) {
+        update(&mut self.state.config.lock().unwrap());
+    }
+
+    /// The paths of all requests served so far, in the order they were received.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle(State(state): State<MockState>, uri: Uri) -> Response {
+    state.requests.lock().unwrap().push(uri.path().to_string());
+    let (status, body) = serve(&state.config, uri.path()).await;
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (status, body).into_response()
+}
+
+/// The status and body served at a path, with any injected fault applied.
+async fn serve(config: &Mutex<MockServerConfig>, path: &str) -> (u16, String) {
+    let (fault, response) = {
+        let config = config.lock().unwrap();
+        match respond(&config, path) {
+            Some((file, fault, body)) => (fault, Some((file, body))),
+            None => (None, None),
+        }
+    };
+    let Some((file, body)) = response else {
+        return (404, String::new());
+    };
+    match fault {
+        None => (200, body),
+        Some(MockFault::Delay(delay)) => {
+            tokio::time::sleep(delay).await;
+            (200, body)
+        }
+        Some(MockFault::NotFound) => (404, String::new()),
+        Some(MockFault::Status(status)) => (status, String::new()),
+        Some(MockFault::Malformed) => (200, malformed(file).to_string()),
+        Some(MockFault::Body(body)) => (200, body),
+    }
+}
+
+/// A fetcher serving the files of a `MockServerConfig` in-process, without a socket.
+///
+/// Every host is served the same files. Delays are slept with `tokio::time::sleep`, so tests
+/// using it can pause the clock and advance it deterministically.
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub(crate) struct MockFetcher {
+    config: Arc<Mutex<MockServerConfig>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl MockFetcher {
+    pub fn new(config: MockServerConfig) -> Self {
+        MockFetcher {
+            config: Arc::new(Mutex::new(config)),
+            requests: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// The URLs of all requests served so far, in the order they were received.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl Fetcher for MockFetcher {
+    async fn fetch(&self, url: &str) -> Result<FetchResponse, FetchError> {
+        self.requests.lock().unwrap().push(url.to_string());
+        let parsed =
+            reqwest::Url::parse(url).map_err(|e| FetchError::request(url, e.to_string()))?;
+        let (status, body) = serve(&self.config, parsed.path()).await;
+        Ok(FetchResponse::new(status, body))
+    }
+}
+
+/// The file, injected fault and body for a path, or None if nothing is served at the path.
+fn respond(config: &MockServerConfig, path: &str) -> Option<(MockFile, Option<MockFault>, String)> {
+    let path = path.strip_prefix(DEFAULT_BASE_PATH)?.strip_prefix('/')?;
+    let server_file = |file: MockFile, body: String| {
+        if config.backend_type == ServerBackendType::S3 {
+            return None;
+        }
+        Some((file, config.faults.get(&file).cloned(), body))
+    };
+    match path {
+        "info/v1/repositories.json" => {
+            server_file(MockFile::RepositoriesJson, repositories_json(config))
+        }
+        "info/v1/meta.json" => server_file(MockFile::MetaJson, meta_json(config)),
+        _ => {
+            let (name, file) = path.split_once('/')?;
+            let repository = config.repositories.iter().find(|repo| repo.name == name)?;
+            let (file, body) = match file {
+                ".cvmfs_status.json" => (MockFile::StatusJson, status_json(repository)),
+                ".cvmfspublished" => (MockFile::Manifest, manifest(repository)),
+                ".cvmfswhitelist" => (MockFile::Whitelist, whitelist(repository)),
+                ".cvmfs_last_snapshot" => (
+                    MockFile::LastSnapshot,
+                    format!("{}\n", date(&repository.last_snapshot?)),
+                ),
+                geoapi if geoapi.starts_with("api/v1.0/geo/") => {
+                    let hosts = geoapi.rsplit('/').next().unwrap_or_default();
+                    (MockFile::GeoApi, geoapi_response(config, hosts))
+                }
+                _ => return None,
+            };
+            Some((file, repository.faults.get(&file).cloned(), body))
+        }
+    }
+}
+
+/// A body of the given file that the scraper cannot parse.
+fn malformed(file: MockFile) -> &'static str {
+    match file {
+        MockFile::RepositoriesJson | MockFile::MetaJson | MockFile::StatusJson => {
+            "{ \"this is\": not json"
+        }
+        MockFile::Manifest | MockFile::Whitelist => "not a manifest\n",
+        MockFile::LastSnapshot => "not a date\n",
+        MockFile::GeoApi => "one,two,three\n",
+    }
+}
+
+/// A date in the format of `date`, as used in the CVMFS status files.
+fn date(datetime: &DateTime<Utc>) -> String {
+    datetime.format("%a %b %e %H:%M:%S UTC %Y").to_string()
+}
+
+fn repositories_json(config: &MockServerConfig) -> String {
+    let repositories = config
+        .repositories
+        .iter()
+        .map(|repo| json!({ "name": repo.name, "url": format!("/cvmfs/{}", repo.name) }))
+        .collect::<Vec<_>>();
+    let (repositories, replicas) = match config.server_type {
+        ServerType::Stratum0 => (repositories, Vec::new()),
+        _ => (Vec::new(), repositories),
+    };
+    let mut json = json!({
+        "schema": 1,
+        "cvmfs_version": "2.11.3-1",
+        "os_id": "rhel",
+        "os_version_id": "9.4",
+        "os_pretty_name": "Red Hat Enterprise Linux 9.4 (Plow)",
+        "repositories": repositories,
+        "replicas": replicas,
+    });
+    if config.server_type != ServerType::Stratum0 {
+        json["last_geodb_update"] = date(&Utc::now()).into();
+    }
+    json.to_string()
+}
+
+fn meta_json(config: &MockServerConfig) -> String {
+    json!({
+        "administrator": config.administrator,
+        "email": config.email,
+        "organisation": config.organisation,
+        "custom": {},
+    })
+    .to_string()
+}
+
+fn status_json(repository: &MockRepository) -> String {
+    let mut json = json!({});
+    if let Some(last_snapshot) = &repository.last_snapshot {
+        json["last_snapshot"] = date(last_snapshot).into();
+    }
+    if let Some(last_gc) = &repository.last_gc {
+        json["last_gc"] = date(last_gc).into();
+    }
+    json.to_string()
+}
+
+fn manifest(repository: &MockRepository) -> String {
+    format!(
+        "C600230b0ba7620426f2e898f1e1f43c5466efe59\n\
+         B1391\n\
+         Rd41d8cd98f00b204e9800998ecf8427e\n\
+         D240\n\
+         S{}\n\
+         Gno\n\
+         Ano\n\
+         N{}\n\
+         X7f4a1a8b0bc0f3e9e8b0f8e4fb9c5e0cb0d7c0a1\n\
+         H1ee5fc3a2b7a7b5c1d3c4b9e5f8e7f6a5b4c3d2e\n\
+         T{}\n\
+         M5a8b2e0f0e6a4b1c9d8f7e6a5b4c3d2e1f0a9b8c\n\
+         Y4c3b2a1f0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b\n\
+         --\n",
+        repository.revision,
+        repository.name,
+        repository.published.timestamp()
+    )
+}
+
+fn whitelist(repository: &MockRepository) -> String {
+    let now = Utc::now();
+    format!(
+        "{}\nE{}\nN{}\n00:B3:F6:1E:08:0D:88:C2:EA:02:22:9C:E2:18:E9:0F:09:24:50:69\n--\n",
+        (now - TimeDelta::days(1)).format("%Y%m%d%H%M%S"),
+        (now + TimeDelta::days(29)).format("%Y%m%d%H%M%S"),
+        repository.name
+    )
+}
+
+fn geoapi_response(config: &MockServerConfig, hosts: &str) -> String {
+    let order = match &config.geoapi_order {
+        Some(order) => order.clone(),
+        None => (1..=hosts.split(',').count() as u32).collect(),
+    };
+    order
+        .iter()
+        .map(|index| index.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{ClientConfig, RetryPolicy};
+    use crate::errors::ErrorKind;
+    use crate::models::{Hostname, ScrapedServer};
+    use crate::replication::{ReplicationReport, ReplicationStatus, ReplicationThresholds};
+    use crate::scraper::{Scraper, ScraperCommon};
+    use yare::parameterized;
+
+    async fn scrape(mock: &MockServer, server_type: ServerType) -> ScrapedServer {
+        Scraper::new()
+            .retry_policy(RetryPolicy::no_retries())
+            .client_config(ClientConfig::new().timeout(Duration::from_millis(500)))
+            .fetch_whitelists(true)
+            .with_servers(vec![mock.server(server_type, ServerBackendType::CVMFS)])
+            .validate()
+            .unwrap()
+            .scrape()
+            .await
+            .remove(0)
+    }
+
+    #[tokio::test]
+    async fn test_mock_server_is_scraped() {
+        let mock = MockServer::start(
+            MockServerConfig::new(ServerType::Stratum1)
+                .repositories(["software.eessi.io", "dev.eessi.io"])
+                .geoapi_order(vec![3, 1, 2]),
+        )
+        .await
+        .unwrap();
+        let server = scrape(&mock, ServerType::Stratum1)
+            .await
+            .get_populated_server()
+            .unwrap();
+        assert_eq!(server.repositories.len(), 2);
+        assert_eq!(server.metadata.organisation, Some("Mock".to_string()));
+        assert_eq!(server.geoapi.response, vec![3, 1, 2]);
+        let repository = &server.repositories[0];
+        assert!(repository.whitelist.is_some());
+        assert!(repository.snapshot_marker.is_some());
+        assert!(repository.last_snapshot.is_some());
+        assert!(mock
+            .requests()
+            .contains(&"/cvmfs/info/v1/repositories.json".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_mock_fetcher_is_scraped() {
+        let fetcher = MockFetcher::new(
+            MockServerConfig::new(ServerType::Stratum1).repositories(["software.eessi.io"]),
+        );
+        let server = Scraper::new()
+            .fetcher(fetcher.clone())
+            .with_servers(vec![Server::new(
+                ServerType::Stratum1,
+                ServerBackendType::CVMFS,
+                Hostname::try_from("s1.example.org").unwrap(),
+            )])
+            .validate()
+            .unwrap()
+            .scrape()
+            .await
+            .remove(0)
+            .get_populated_server()
+            .unwrap();
+        assert!(server.has_repository("software.eessi.io"));
+        assert!(fetcher
+            .requests()
+            .contains(&"http://s1.example.org/cvmfs/info/v1/repositories.json".to_string()));
+    }
+
+    #[parameterized(
+        not_found = { MockFault::NotFound, ErrorKind::HttpStatus },
+        server_error = { MockFault::Status(503), ErrorKind::HttpStatus },
+        malformed = { MockFault::Malformed, ErrorKind::Parse },
+        slow = { MockFault::Delay(Duration::from_secs(2)), ErrorKind::Timeout },
+    )]
+    fn test_server_faults(fault: MockFault, kind: ErrorKind) {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let mock = MockServer::start(
+                MockServerConfig::new(ServerType::Stratum1)
+                    .repositories(["software.eessi.io"])
+                    .fault(MockFile::RepositoriesJson, fault),
+            )
+            .await
+            .unwrap();
+            let failed = scrape(&mock, ServerType::Stratum1)
+                .await
+                .get_failed_server()
+                .unwrap();
+            assert_eq!(failed.error.report().kind, kind);
+        });
+    }
+
+    #[parameterized(
+        status_json = { MockFile::StatusJson },
+        manifest = { MockFile::Manifest },
+    )]
+    fn test_repository_faults(file: MockFile) {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let mock = MockServer::start(
+                MockServerConfig::new(ServerType::Stratum1)
+                    .repositories(["software.eessi.io"])
+                    .repository(
+                        MockRepository::new("dev.eessi.io").fault(file, MockFault::Malformed),
+                    ),
+            )
+            .await
+            .unwrap();
+            let server = scrape(&mock, ServerType::Stratum1)
+                .await
+                .get_populated_server()
+                .unwrap();
+            assert!(server.has_repository("software.eessi.io"));
+            assert!(server.has_failed_repository("dev.eessi.io"));
+        });
+    }
+
+    #[tokio::test]
+    async fn test_wrong_server_type() {
+        let mock = MockServer::start(
+            MockServerConfig::new(ServerType::Stratum1).repositories(["software.eessi.io"]),
+        )
+        .await
+        .unwrap();
+        let failed = scrape(&mock, ServerType::Stratum0)
+            .await
+            .get_failed_server()
+            .unwrap();
+        assert_eq!(failed.error.report().kind, ErrorKind::ServerTypeMismatch);
+    }
+
+    #[tokio::test]
+    async fn test_stale_revision_is_reported_as_lagging() {
+        let stratum0 = MockServer::start(
+            MockServerConfig::new(ServerType::Stratum0)
+                .repository(MockRepository::new("software.eessi.io").revision(10)),
+        )
+        .await
+        .unwrap();
+        let stratum1 = MockServer::start(
+            MockServerConfig::new(ServerType::Stratum1).repository(
+                MockRepository::new("software.eessi.io")
+                    .revision(8)
+                    .stale(TimeDelta::days(2)),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let servers = Scraper::new()
+            .with_servers(vec![
+                stratum0.server(ServerType::Stratum0, ServerBackendType::CVMFS),
+                stratum1.server(ServerType::Stratum1, ServerBackendType::CVMFS),
+            ])
+            .validate()
+            .unwrap()
+            .scrape()
+            .await;
+        let report = ReplicationReport::new(&servers, &ReplicationThresholds::default());
+        let replica = &report.repositories[0].replicas[0];
+        assert_eq!(replica.revision_gap, 2);
+        assert_eq!(replica.status, ReplicationStatus::Critical);
+
+        // The replica catches up.
+        stratum1.update(|config| {
+            *config.repository_mut("software.eessi.io").unwrap() =
+                MockRepository::new("software.eessi.io").revision(10);
+        });
+        let servers = Scraper::new()
+            .with_servers(vec![
+                stratum0.server(ServerType::Stratum0, ServerBackendType::CVMFS),
+                stratum1.server(ServerType::Stratum1, ServerBackendType::CVMFS),
+            ])
+            .validate()
+            .unwrap()
+            .scrape()
+            .await;
+        let report = ReplicationReport::new(&servers, &ReplicationThresholds::default());
+        assert_eq!(
+            report.repositories[0].replicas[0].status,
+            ReplicationStatus::Ok
+        );
+    }
+}
@@ -23,8 +23,9 @@ use crate::utilities::{parse_boolean_field, parse_hex_field, parse_number_field}
 /// - y: Cryptographic hash of the reflog checksum
 /// - l: currently unused (reserved for micro catalogs)
 /// - signature: In order to provide authoritative information about a repository publisher, the
-///              repository manifest is signed by an X.509 certificate together with its private key.
-///              This field is not validated by this library.
+///   repository manifest is signed by an X.509 certificate together with its private key.
+///   The signature is verified when the scraper is configured with `verify_signatures(true)`,
+///   which requires the `signatures` feature, see `SignatureVerification`.
 ///
 /// Note that the field names are lowercase, but the field names in the manifest itself are uppercase.
 ///
@@ -123,19 +124,28 @@ impl std::str::FromStr for Manifest {
 
 impl Manifest {
     pub fn output(&self) {
-        println!("  Manifest for repository: {}", self.n);
-        println!("    Root catalog hash: {}", self.c);
-        println!("    Root catalog size: {}", self.b);
-        println!("    Fetch under alternative name: {}", self.a);
-        println!("    Root path hash: {}", self.r);
-        println!("    Signing certificate hash: {}", self.x);
-        println!("    Garbage-collectable: {}", self.g);
-        println!("    Tag history hash: {}", self.h);
-        println!("    Revision timestamp: {}", self.t);
-        println!("    Root catalog TTL: {}", self.d);
-        println!("    Revision number: {}", self.s);
-        println!("    Metadata hash: {}", self.m);
-        println!("    Reflog checksum hash: {}", self.y);
-        // println!("  Signature: {}", self.signature);
+        let mut text = String::new();
+        self.write_output(&mut text)
+            .expect("Writing to a String cannot fail");
+        print!("{}", text);
+    }
+
+    /// Write the output of `output()` to the given writer.
+    pub fn write_output(&self, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+        writeln!(out, "  Manifest for repository: {}", self.n)?;
+        writeln!(out, "    Root catalog hash: {}", self.c)?;
+        writeln!(out, "    Root catalog size: {}", self.b)?;
+        writeln!(out, "    Fetch under alternative name: {}", self.a)?;
+        writeln!(out, "    Root path hash: {}", self.r)?;
+        writeln!(out, "    Signing certificate hash: {}", self.x)?;
+        writeln!(out, "    Garbage-collectable: {}", self.g)?;
+        writeln!(out, "    Tag history hash: {}", self.h)?;
+        writeln!(out, "    Revision timestamp: {}", self.t)?;
+        writeln!(out, "    Root catalog TTL: {}", self.d)?;
+        writeln!(out, "    Revision number: {}", self.s)?;
+        writeln!(out, "    Metadata hash: {}", self.m)?;
+        writeln!(out, "    Reflog checksum hash: {}", self.y)?;
+        // writeln!(out, "  Signature: {}", self.signature)?;
+        Ok(())
     }
 }
@@ -0,0 +1,237 @@
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ManifestError;
+
+/// The whitelist of a repository (.cvmfswhitelist).
+///
+/// The whitelist lists the fingerprints of the certificates that may sign the manifest of the
+/// repository, and is re-signed by the repository master key at regular intervals. Clients refuse
+/// to mount a repository with an expired whitelist, so an expired whitelist is an outage even if
+/// the servers themselves are fine.
+///
+/// Fields:
+///
+/// - created: When the whitelist was created
+/// - expires: When the whitelist expires
+/// - repository: The name of the repository the whitelist belongs to
+/// - fingerprints: The SHA-1 fingerprints of the allowed certificates, as uppercase hex bytes
+///   separated by colons (e.g. "AB:CD:...")
+///
+/// The signature of the whitelist itself (made with the master key) is not verified.
+///
+/// See https://cvmfs.readthedocs.io/en/stable/cpt-details.html#signature-details for more
+/// information.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Whitelist {
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+    pub repository: String,
+    pub fingerprints: Vec<String>,
+}
+
+impl std::str::FromStr for Whitelist {
+    type Err = ManifestError;
+
+    /// Parse the text of a whitelist.
+    ///
+    /// The first line is the creation timestamp, followed by the expiry timestamp (prefixed with
+    /// "E"), the repository name (prefixed with "N") and one fingerprint per line, optionally
+    /// followed by a comment. Everything after the "--" separator is the signature.
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let mut lines = content.lines().take_while(|line| *line != "--");
+
+        let created = parse_timestamp(lines.next().unwrap_or_default(), 'C')?;
+        let expires = match lines.next() {
+            Some(line) if line.starts_with('E') => parse_timestamp(&line[1..], 'E')?,
+            _ => return Err(ManifestError::MissingField('E')),
+        };
+        let repository = match lines.next() {
+            Some(line) if line.starts_with('N') => line[1..].to_string(),
+            _ => return Err(ManifestError::MissingField('N')),
+        };
+
+        let mut fingerprints = Vec::new();
+        for line in lines {
+            let Some(fingerprint) = line.split_whitespace().next() else {
+                continue;
+            };
+            fingerprints.push(normalize_fingerprint(fingerprint).ok_or_else(|| {
+                ManifestError::ParseError('F', format!("Invalid fingerprint: {}", line))
+            })?);
+        }
+
+        Ok(Whitelist {
+            created,
+            expires,
+            repository,
+            fingerprints,
+        })
+    }
+}
+
+impl Whitelist {
+    /// Check if the whitelist has expired.
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(Utc::now())
+    }
+
+    /// Check if the whitelist has expired at the given time.
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires <= now
+    }
+
+    /// The time left until the whitelist expires, negative if it has already expired.
+    pub fn time_to_expiry(&self) -> TimeDelta {
+        self.time_to_expiry_at(Utc::now())
+    }
+
+    /// The time left from the given time until the whitelist expires.
+    pub fn time_to_expiry_at(&self, now: DateTime<Utc>) -> TimeDelta {
+        self.expires - now
+    }
+
+    /// The number of whole days left until the whitelist expires, negative if it has expired.
+    pub fn days_to_expiry(&self) -> i64 {
+        self.time_to_expiry().num_days()
+    }
+
+    /// Check if the whitelist expires within the given time (or has already expired).
+    pub fn expires_within(&self, within: TimeDelta) -> bool {
+        self.expires_within_at(within, Utc::now())
+    }
+
+    /// Check if the whitelist expires within the given time, counting from the given time.
+    pub fn expires_within_at(&self, within: TimeDelta, now: DateTime<Utc>) -> bool {
+        self.time_to_expiry_at(now) <= within
+    }
+
+    /// Check if a certificate fingerprint is listed in the whitelist.
+    ///
+    /// The fingerprint may be given with or without colons, in either case.
+    pub fn contains_fingerprint(&self, fingerprint: &str) -> bool {
+        normalize_fingerprint(fingerprint)
+            .is_some_and(|fingerprint| self.fingerprints.contains(&fingerprint))
+    }
+
+    pub fn output(&self) {
+        let mut text = String::new();
+        self.write_output(&mut text)
+            .expect("Writing to a String cannot fail");
+        print!("{}", text);
+    }
+
+    /// Write the output of `output()` to the given writer.
+    pub fn write_output(&self, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+        writeln!(out, "  Whitelist for repository: {}", self.repository)?;
+        writeln!(out, "    Created: {}", self.created)?;
+        writeln!(out, "    Expires: {}", self.expires)?;
+        if self.is_expired() {
+            writeln!(out, "    Expired: yes")?;
+        } else {
+            writeln!(out, "    Days to expiry: {}", self.days_to_expiry())?;
+        }
+        for fingerprint in &self.fingerprints {
+            writeln!(out, "    Fingerprint: {}", fingerprint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a whitelist timestamp (YYYYMMDDhhmmss, in UTC).
+fn parse_timestamp(value: &str, field: char) -> Result<DateTime<Utc>, ManifestError> {
+    NaiveDateTime::parse_from_str(value, "%Y%m%d%H%M%S")
+        .map(|naive| naive.and_utc())
+        .map_err(|e| ManifestError::ParseError(field, format!("{}: {}", value, e)))
+}
+
+/// Normalize a SHA-1 fingerprint to uppercase hex bytes separated by colons.
+pub(crate) fn normalize_fingerprint(fingerprint: &str) -> Option<String> {
+    let hex: String = fingerprint.chars().filter(|c| *c != ':').collect();
+    if hex.len() != 40 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(
+        hex.to_uppercase()
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| String::from_utf8_lossy(pair).into_owned())
+            .collect::<Vec<String>>()
+            .join(":"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yare::parameterized;
+
+    const FINGERPRINT: &str = "0A:1B:2C:3D:4E:5F:60:71:82:93:A4:B5:C6:D7:E8:F9:0A:1B:2C:3D";
+
+    fn whitelist() -> Whitelist {
+        format!(
+            "20240617000003\nE20240717000003\nNsoftware.eessi.io\n{} # software.eessi.io\n--\n\
+             5e3d1b4a8f9c2e7d6b0a1f3c4e5d6b7a8c9d0e1f\n\x01\x02binary",
+            FINGERPRINT
+        )
+        .parse()
+        .unwrap()
+    }
+
+    fn at(date: &str) -> DateTime<Utc> {
+        date.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_whitelist() {
+        let whitelist = whitelist();
+        assert_eq!(whitelist.created, at("2024-06-17T00:00:03Z"));
+        assert_eq!(whitelist.expires, at("2024-07-17T00:00:03Z"));
+        assert_eq!(whitelist.repository, "software.eessi.io");
+        assert_eq!(whitelist.fingerprints, vec![FINGERPRINT.to_string()]);
+    }
+
+    #[parameterized(
+        colons = { "0A:1B:2C:3D:4E:5F:60:71:82:93:A4:B5:C6:D7:E8:F9:0A:1B:2C:3D", true },
+        lowercase = { "0a:1b:2c:3d:4e:5f:60:71:82:93:a4:b5:c6:d7:e8:f9:0a:1b:2c:3d", true },
+        no_colons = { "0A1B2C3D4E5F60718293A4B5C6D7E8F90A1B2C3D", true },
+        other = { "FF:1B:2C:3D:4E:5F:60:71:82:93:A4:B5:C6:D7:E8:F9:0A:1B:2C:3D", false },
+        invalid = { "not a fingerprint", false },
+    )]
+    fn test_contains_fingerprint(fingerprint: &str, expected: bool) {
+        assert_eq!(whitelist().contains_fingerprint(fingerprint), expected);
+    }
+
+    #[parameterized(
+        month_before = { "2024-06-17T00:00:03Z", false, 30, false },
+        week_before = { "2024-07-10T00:00:03Z", false, 7, true },
+        at_expiry = { "2024-07-17T00:00:03Z", true, 0, true },
+        after = { "2024-07-19T00:00:03Z", true, -2, true },
+    )]
+    fn test_expiry(now: &str, expired: bool, days: i64, within_week: bool) {
+        let whitelist = whitelist();
+        let now = at(now);
+        assert_eq!(whitelist.is_expired_at(now), expired);
+        assert_eq!(whitelist.time_to_expiry_at(now).num_days(), days);
+        assert_eq!(
+            whitelist.expires_within_at(TimeDelta::days(7), now),
+            within_week
+        );
+    }
+
+    #[parameterized(
+        empty = { "", 'C' },
+        bad_created = { "2024-06-17\nE20240717000003\nNrepo\n--\n", 'C' },
+        missing_expiry = { "20240617000003\nNrepo\n--\n", 'E' },
+        missing_name = { "20240617000003\nE20240717000003\n--\n", 'N' },
+        bad_fingerprint = { "20240617000003\nE20240717000003\nNrepo\nAB:CD\n--\n", 'F' },
+    )]
+    fn test_parse_malformed_whitelist(content: &str, field: char) {
+        match content.parse::<Whitelist>() {
+            Err(ManifestError::MissingField(f)) | Err(ManifestError::ParseError(f, _)) => {
+                assert_eq!(f, field)
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 use crate::errors::{HostnameError, ManifestError, ScrapeError};
 
@@ -7,7 +8,10 @@ use crate::errors::{HostnameError, ManifestError, ScrapeError};
 ///
 /// This type is used to represent a hostname string. It is a wrapper around a `String` and
 /// provides validation for hostnames.
+///
+/// Deserializing a hostname validates it in the same way as parsing it does.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(try_from = "String")]
 pub struct Hostname(String);
 
 impl std::str::FromStr for Hostname {
@@ -71,11 +75,148 @@ impl Hostname {
         &self.0
     }
 
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         self.0.clone()
     }
 }
 
+/// The address of a server: a DNS name, an IPv4 address or an IPv6 address.
+///
+/// Addresses are parsed from strings:
+///
+/// - "stratum1.example.com": A DNS name, validated as a `Hostname`
+/// - "192.0.2.1": An IPv4 address
+/// - "[2001:db8::1]" (or "2001:db8::1"): An IPv6 address
+/// - "stratum1.example.com@192.0.2.1": A DNS name pinned to an IP address (IPv6 addresses in
+///   brackets). Requests use the name (for the Host header and TLS), but are sent to the given
+///   address instead of resolving the name. This is useful to scrape one specific node behind a
+///   round-robin name.
+///
+/// The address displays as the host part of a URL (with brackets for IPv6, and without the pin),
+/// and serializes in the same form it is parsed from.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum ServerAddress {
+    Dns(Hostname),
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+    Pinned(Hostname, IpAddr),
+}
+
+impl ServerAddress {
+    /// Pin a DNS name to the given IP address.
+    pub fn pinned(hostname: Hostname, ip: IpAddr) -> Self {
+        ServerAddress::Pinned(hostname, ip)
+    }
+
+    /// The DNS name of the address, if it has one.
+    pub fn hostname(&self) -> Option<&Hostname> {
+        match self {
+            ServerAddress::Dns(hostname) | ServerAddress::Pinned(hostname, _) => Some(hostname),
+            ServerAddress::Ipv4(_) | ServerAddress::Ipv6(_) => None,
+        }
+    }
+
+    /// The IP address requests are sent to, if it is not resolved through DNS.
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            ServerAddress::Dns(_) => None,
+            ServerAddress::Ipv4(ip) => Some(IpAddr::V4(*ip)),
+            ServerAddress::Ipv6(ip) => Some(IpAddr::V6(*ip)),
+            ServerAddress::Pinned(_, ip) => Some(*ip),
+        }
+    }
+
+    /// A key identifying the address, as it is serialized.
+    ///
+    /// Unlike `Display`, which gives the host part of URLs, the key includes the IP of a pinned
+    /// address, e.g. "stratum1.example.com@192.0.2.1", so the same name pinned to different IPs
+    /// gives different keys.
+    pub fn key(&self) -> String {
+        match self {
+            ServerAddress::Pinned(hostname, IpAddr::V4(ip)) => format!("{}@{}", hostname, ip),
+            ServerAddress::Pinned(hostname, IpAddr::V6(ip)) => format!("{}@[{}]", hostname, ip),
+            _ => self.to_string(),
+        }
+    }
+}
+
+fn parse_ip(s: &str) -> Result<IpAddr, HostnameError> {
+    let inner = s
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(s);
+    inner
+        .parse()
+        .map_err(|_| HostnameError::InvalidIpAddress(s.to_string()))
+}
+
+impl std::str::FromStr for ServerAddress {
+    type Err = HostnameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((hostname, ip)) = s.split_once('@') {
+            // Only DNS names can be pinned.
+            if hostname.parse::<Ipv4Addr>().is_ok() {
+                return Err(HostnameError::InvalidIpAddress(s.to_string()));
+            }
+            return Ok(ServerAddress::Pinned(hostname.parse()?, parse_ip(ip)?));
+        }
+        if s.starts_with('[') || s.contains(':') {
+            return match parse_ip(s)? {
+                IpAddr::V6(ip) => Ok(ServerAddress::Ipv6(ip)),
+                IpAddr::V4(_) => Err(HostnameError::InvalidIpAddress(s.to_string())),
+            };
+        }
+        if let Ok(ip) = s.parse::<Ipv4Addr>() {
+            return Ok(ServerAddress::Ipv4(ip));
+        }
+        Ok(ServerAddress::Dns(s.parse()?))
+    }
+}
+
+impl std::fmt::Display for ServerAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerAddress::Dns(hostname) | ServerAddress::Pinned(hostname, _) => {
+                write!(f, "{}", hostname)
+            }
+            ServerAddress::Ipv4(ip) => write!(f, "{}", ip),
+            ServerAddress::Ipv6(ip) => write!(f, "[{}]", ip),
+        }
+    }
+}
+
+impl From<Hostname> for ServerAddress {
+    fn from(hostname: Hostname) -> Self {
+        ServerAddress::Dns(hostname)
+    }
+}
+
+impl From<IpAddr> for ServerAddress {
+    fn from(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(ip) => ServerAddress::Ipv4(ip),
+            IpAddr::V6(ip) => ServerAddress::Ipv6(ip),
+        }
+    }
+}
+
+impl TryFrom<String> for ServerAddress {
+    type Error = HostnameError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ServerAddress> for String {
+    fn from(address: ServerAddress) -> Self {
+        address.key()
+    }
+}
+
 /// A hexadecimal string.
 ///
 /// This type is used to represent a hexadecimal string. It is a wrapper around a `String` and
@@ -90,7 +231,7 @@ pub struct HexString(String);
 
 impl HexString {
     pub fn new(s: &str) -> Result<Self, ManifestError> {
-        if s.len() % 2 == 0 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+        if s.len().is_multiple_of(2) && s.chars().all(|c| c.is_ascii_hexdigit()) {
             Ok(HexString(s.to_string().to_lowercase()))
         } else {
             Err(ManifestError::InvalidHex(s.to_string()))
@@ -130,8 +271,8 @@ impl<'de> Deserialize<'de> for HexString {
 /// with any degree of sanity.
 ///
 /// To offer both the option of a time-parsed field and the raw string, we store
-/// the string itself and provide a method (`try_into_datetime`) to attempt to
-/// parse the string into a `DateTime<Utc>`.
+/// the string itself and provide methods (`try_into_datetime` and `try_parse`) to attempt to
+/// parse the string into a `DateTime<Utc>`. See `DateTimeFormat` for the accepted formats.
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
 pub struct MaybeRfc2822DateTime(pub Option<String>);
 
@@ -145,17 +286,17 @@ impl std::fmt::Display for MaybeRfc2822DateTime {
 }
 
 impl MaybeRfc2822DateTime {
+    /// Parse the date-time, if present, into UTC.
+    ///
+    /// See `try_parse` for details on which format matched and how the timezone was found.
     pub fn try_into_datetime(&self) -> Result<Option<DateTime<Utc>>, ScrapeError> {
+        Ok(self.try_parse()?.map(|parsed| parsed.datetime))
+    }
+
+    /// Parse the date-time, if present, reporting the format that matched.
+    pub fn try_parse(&self) -> Result<Option<ParsedDateTime>, ScrapeError> {
         match &self.0 {
-            Some(date_str) => {
-                // Try parsing the date string with the format
-                let naive_dt = NaiveDateTime::parse_from_str(date_str, "%a %b %d %H:%M:%S %Z %Y")
-                    .map_err(|_| ScrapeError::ConversionError(date_str.clone()))?;
-                // Convert NaiveDateTime to DateTime<Utc>
-                Ok(Some(DateTime::<Utc>::from_naive_utc_and_offset(
-                    naive_dt, Utc,
-                )))
-            }
+            Some(date_str) => ParsedDateTime::parse(date_str).map(Some),
             None => Ok(None),
         }
     }
@@ -169,6 +310,171 @@ impl MaybeRfc2822DateTime {
     }
 }
 
+/// The format a date-time string was parsed with.
+///
+/// - Rfc2822: e.g. "Fri, 21 Jun 2024 17:40:02 +0000"
+/// - Rfc3339: e.g. "2024-06-21T17:40:02Z" or "2024-06-21T19:40:02+02:00"
+/// - Date: The default output of the `date` command, e.g. "Fri Jun 21 17:40:02 UTC 2024", with
+///   a timezone abbreviation (see `timezone_offset`) or a numeric offset such as "+0200"
+/// - Iso8601: Other ISO 8601 variants, e.g. "2024-06-21 17:40:02", "2024-06-21 17:40:02 +0200"
+///   or "2024-06-21T17:40:02+0200"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateTimeFormat {
+    Rfc2822,
+    Rfc3339,
+    Date,
+    Iso8601,
+}
+
+/// Whether the timezone of a parsed date-time was known.
+///
+/// - Known: The string had a numeric offset or a recognized timezone abbreviation
+/// - Assumed: The string had no timezone, or an unrecognized abbreviation, and UTC was assumed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimezoneStatus {
+    Known,
+    Assumed,
+}
+
+/// A date-time parsed from a string, with the format that matched.
+///
+/// Fields:
+///
+/// - datetime: The date-time, converted to UTC
+/// - format: The format that matched
+/// - timezone: Whether the timezone was known or assumed to be UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParsedDateTime {
+    pub datetime: DateTime<Utc>,
+    pub format: DateTimeFormat,
+    pub timezone: TimezoneStatus,
+}
+
+impl ParsedDateTime {
+    /// Parse a date-time string, trying each format in `DateTimeFormat` in turn.
+    pub fn parse(value: &str) -> Result<Self, ScrapeError> {
+        let value = value.trim();
+        let known = |datetime: DateTime<FixedOffset>, format| ParsedDateTime {
+            datetime: datetime.with_timezone(&Utc),
+            format,
+            timezone: TimezoneStatus::Known,
+        };
+
+        if let Ok(datetime) = DateTime::parse_from_rfc2822(value) {
+            return Ok(known(datetime, DateTimeFormat::Rfc2822));
+        }
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+            return Ok(known(datetime, DateTimeFormat::Rfc3339));
+        }
+        if let Some(parsed) = parse_date_output(value) {
+            return Ok(parsed);
+        }
+        for format in [
+            "%Y-%m-%dT%H:%M:%S%.f%z",
+            "%Y-%m-%d %H:%M:%S%.f%z",
+            "%Y-%m-%d %H:%M:%S%.f %z",
+        ] {
+            if let Ok(datetime) = DateTime::parse_from_str(value, format) {
+                return Ok(known(datetime, DateTimeFormat::Iso8601));
+            }
+        }
+        for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+            if let Ok(naive) = NaiveDateTime::parse_from_str(value, format) {
+                return Ok(ParsedDateTime {
+                    datetime: naive.and_utc(),
+                    format: DateTimeFormat::Iso8601,
+                    timezone: TimezoneStatus::Assumed,
+                });
+            }
+        }
+
+        Err(ScrapeError::ConversionError(value.to_string()))
+    }
+}
+
+/// Parse the output of `date` ("%a %b %d %H:%M:%S %Z %Y").
+///
+/// The weekday is ignored, as it may be localized. The timezone is required, and may be an
+/// abbreviation or a numeric offset.
+fn parse_date_output(value: &str) -> Option<ParsedDateTime> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let [_weekday, month, day, time, zone, year] = parts.as_slice() else {
+        return None;
+    };
+    let naive = NaiveDateTime::parse_from_str(
+        &format!("{} {} {} {}", year, month, day, time),
+        "%Y %b %d %H:%M:%S",
+    )
+    .ok()?;
+
+    let (offset, timezone) = match timezone_offset(zone) {
+        Some(offset) => (offset, TimezoneStatus::Known),
+        // Something that looks like an abbreviation, but one we do not know.
+        None if zone.chars().all(|c| c.is_ascii_alphabetic()) => {
+            (FixedOffset::east_opt(0)?, TimezoneStatus::Assumed)
+        }
+        None => return None,
+    };
+    Some(ParsedDateTime {
+        datetime: naive
+            .and_local_timezone(offset)
+            .single()?
+            .with_timezone(&Utc),
+        format: DateTimeFormat::Date,
+        timezone,
+    })
+}
+
+/// The offset of a timezone abbreviation or numeric offset ("+0200", "+02:00", "-05").
+///
+/// Only common abbreviations are recognized. Abbreviations used for several timezones, such as
+/// CST (US Central or China), IST (India, Ireland or Israel) and BST (British Summer Time or
+/// Bangladesh), are not, so they are treated as unknown and UTC is assumed.
+fn timezone_offset(zone: &str) -> Option<FixedOffset> {
+    let hours = match zone.to_uppercase().as_str() {
+        "UTC" | "GMT" | "UT" | "Z" | "WET" => 0,
+        "CET" | "WEST" => 1,
+        "CEST" | "EET" => 2,
+        "EEST" | "MSK" => 3,
+        "JST" => 9,
+        "AEST" => 10,
+        "AEDT" => 11,
+        "EDT" => -4,
+        "EST" | "CDT" => -5,
+        "MDT" => -6,
+        "MST" | "PDT" => -7,
+        "PST" => -8,
+        _ => return parse_numeric_offset(zone),
+    };
+    FixedOffset::east_opt(hours * 3600)
+}
+
+fn parse_numeric_offset(zone: &str) -> Option<FixedOffset> {
+    let sign = match zone.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = zone[1..].chars().filter(|c| *c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (
+            digits[..2].parse::<i32>().ok()?,
+            digits[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
 pub struct Rfc2822DateTime(String);
 
 impl From<&str> for Rfc2822DateTime {
@@ -271,6 +577,100 @@ mod tests {
         }
     }
 
+    #[parameterized(
+        date_utc = { "Fri Jun 21 17:40:02 UTC 2024", "2024-06-21T17:40:02Z", DateTimeFormat::Date, TimezoneStatus::Known },
+        date_cest = { "Fri Jun 21 19:40:02 CEST 2024", "2024-06-21T17:40:02Z", DateTimeFormat::Date, TimezoneStatus::Known },
+        date_cet = { "Mon Jan  1 01:00:00 CET 2024", "2024-01-01T00:00:00Z", DateTimeFormat::Date, TimezoneStatus::Known },
+        date_offset = { "Fri Jun 21 12:40:02 -0500 2024", "2024-06-21T17:40:02Z", DateTimeFormat::Date, TimezoneStatus::Known },
+        date_localized_weekday = { "Fr Jun 21 17:40:02 UTC 2024", "2024-06-21T17:40:02Z", DateTimeFormat::Date, TimezoneStatus::Known },
+        date_unknown_zone = { "Fri Jun 21 17:40:02 XYZT 2024", "2024-06-21T17:40:02Z", DateTimeFormat::Date, TimezoneStatus::Assumed },
+        date_ambiguous_cst = { "Fri Jun 21 17:40:02 CST 2024", "2024-06-21T17:40:02Z", DateTimeFormat::Date, TimezoneStatus::Assumed },
+        date_ambiguous_ist = { "Fri Jun 21 17:40:02 IST 2024", "2024-06-21T17:40:02Z", DateTimeFormat::Date, TimezoneStatus::Assumed },
+        date_ambiguous_bst = { "Fri Jun 21 17:40:02 BST 2024", "2024-06-21T17:40:02Z", DateTimeFormat::Date, TimezoneStatus::Assumed },
+        rfc2822 = { "Fri, 21 Jun 2024 19:40:02 +0200", "2024-06-21T17:40:02Z", DateTimeFormat::Rfc2822, TimezoneStatus::Known },
+        rfc3339 = { "2024-06-21T17:40:02Z", "2024-06-21T17:40:02Z", DateTimeFormat::Rfc3339, TimezoneStatus::Known },
+        rfc3339_offset = { "2024-06-21T19:40:02.5+02:00", "2024-06-21T17:40:02.5Z", DateTimeFormat::Rfc3339, TimezoneStatus::Known },
+        iso_compact_offset = { "2024-06-21T19:40:02+0200", "2024-06-21T17:40:02Z", DateTimeFormat::Iso8601, TimezoneStatus::Known },
+        iso_space_offset = { "2024-06-21 19:40:02 +0200", "2024-06-21T17:40:02Z", DateTimeFormat::Iso8601, TimezoneStatus::Known },
+        iso_naive = { "2024-06-21 17:40:02", "2024-06-21T17:40:02Z", DateTimeFormat::Iso8601, TimezoneStatus::Assumed },
+        iso_naive_t = { "2024-06-21T17:40:02", "2024-06-21T17:40:02Z", DateTimeFormat::Iso8601, TimezoneStatus::Assumed },
+    )]
+    fn test_parse_datetime(
+        date: &str,
+        expected: &str,
+        format: DateTimeFormat,
+        timezone: TimezoneStatus,
+    ) {
+        let parsed = MaybeRfc2822DateTime(Some(date.to_string()))
+            .try_parse()
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.datetime, expected.parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(parsed.format, format);
+        assert_eq!(parsed.timezone, timezone);
+    }
+
+    #[parameterized(
+        empty = { "" },
+        garbage = { "foo" },
+        date_without_zone = { "Tue Jun 18 13:40:04 2024" },
+        date_bad_offset = { "Fri Jun 21 17:40:02 +02:75 2024" },
+        date_bad_month = { "Fri Foo 21 17:40:02 UTC 2024" },
+        date_only = { "2024-06-21" },
+    )]
+    fn test_parse_invalid_datetime(date: &str) {
+        assert!(ParsedDateTime::parse(date).is_err());
+    }
+
+    #[test]
+    fn test_parse_missing_datetime() {
+        assert_eq!(MaybeRfc2822DateTime(None).try_parse().unwrap(), None);
+    }
+
+    #[parameterized(
+        dns = { "s1.example.com", "s1.example.com", "s1.example.com", None },
+        ipv4 = { "192.0.2.1", "192.0.2.1", "192.0.2.1", Some("192.0.2.1") },
+        ipv6_bracketed = { "[2001:db8::1]", "[2001:db8::1]", "[2001:db8::1]", Some("2001:db8::1") },
+        ipv6_bare = { "2001:db8::1", "[2001:db8::1]", "[2001:db8::1]", Some("2001:db8::1") },
+        pinned_ipv4 = { "s1.example.com@192.0.2.1", "s1.example.com", "s1.example.com@192.0.2.1", Some("192.0.2.1") },
+        pinned_ipv6 = { "s1.example.com@[2001:db8::1]", "s1.example.com", "s1.example.com@[2001:db8::1]", Some("2001:db8::1") },
+    )]
+    fn test_valid_server_address(address: &str, display: &str, serialized: &str, ip: Option<&str>) {
+        let parsed: ServerAddress = address.parse().unwrap();
+        assert_eq!(parsed.to_string(), display);
+        assert_eq!(parsed.key(), serialized);
+        assert_eq!(String::from(parsed.clone()), serialized);
+        assert_eq!(parsed.ip(), ip.map(|ip| ip.parse().unwrap()));
+
+        let json = serde_json::to_string(&parsed).unwrap();
+        assert_eq!(json, format!("\"{}\"", serialized));
+        assert_eq!(
+            serde_json::from_str::<ServerAddress>(&json).unwrap(),
+            parsed
+        );
+    }
+
+    #[parameterized(
+        empty = { "" },
+        invalid_hostname = { "example..com" },
+        bad_ipv6 = { "[2001:db8::g]" },
+        ipv4_in_brackets = { "[192.0.2.1]" },
+        unclosed_bracket = { "[2001:db8::1" },
+        pinned_to_hostname = { "s1.example.com@s2.example.com" },
+        pinned_ip = { "192.0.2.1@192.0.2.2" },
+        ipv4_with_port = { "192.0.2.1:80" },
+    )]
+    fn test_invalid_server_address(address: &str) {
+        assert!(address.parse::<ServerAddress>().is_err());
+    }
+
+    #[test]
+    fn test_hostname_deserialization_is_validated() {
+        let hostname: Hostname = serde_json::from_str("\"example.com\"").unwrap();
+        assert_eq!(hostname.to_str(), "example.com");
+        assert!(serde_json::from_str::<Hostname>("\"example..com\"").is_err());
+    }
+
     #[test]
     fn test_hostname_as_str() {
         let hostname = Hostname("example.com".to_string());
@@ -2,6 +2,7 @@ use log::warn;
 use serde::{Deserialize, Serialize};
 
 use crate::errors::ScrapeError;
+use crate::models::ServerAddress;
 use crate::Hostname;
 
 /// A query to the GeoAPI endpoints of the host.
@@ -11,14 +12,23 @@ use crate::Hostname;
 /// the repository structure.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct GeoapiServerQuery {
-    pub hostname: Hostname,
+    pub hostname: ServerAddress,
     pub geoapi_hosts: Vec<Hostname>,
     pub response: Vec<u32>,
 }
 
 impl GeoapiServerQuery {
     pub fn output(&self) {
-        println!(
+        let mut text = String::new();
+        self.write_output(&mut text)
+            .expect("Writing to a String cannot fail");
+        print!("{}", text);
+    }
+
+    /// Write the output of `output()` to the given writer.
+    pub fn write_output(&self, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+        writeln!(
+            out,
             "  Hosts: {} -> {:?}",
             self.geoapi_hosts
                 .iter()
@@ -27,7 +37,8 @@ impl GeoapiServerQuery {
                 .collect::<Vec<String>>()
                 .join(", "),
             self.response
-        )
+        )?;
+        Ok(())
     }
 
     pub fn check_against_expected_order_by_id(&self, expected_order: Vec<u32>) -> bool {
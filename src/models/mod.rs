@@ -1,15 +1,27 @@
 mod cvmfs_published;
 mod cvmfs_status_json;
+mod cvmfs_whitelist;
 mod generic;
 mod geoapi;
 mod meta_json;
+mod report;
 mod repositories_json;
 mod servers;
+#[cfg(feature = "signatures")]
+mod signature;
+mod verification;
 
 pub use cvmfs_published::Manifest;
-pub use generic::{HexString, Hostname, MaybeRfc2822DateTime};
+pub use cvmfs_whitelist::Whitelist;
+pub use generic::{
+    DateTimeFormat, HexString, Hostname, MaybeRfc2822DateTime, ParsedDateTime, ServerAddress,
+    TimezoneStatus,
+};
 pub use geoapi::GeoapiServerQuery;
+pub use report::{ScrapeReport, SCRAPE_REPORT_VERSION};
+pub(crate) use servers::ScrapeOptions;
 pub use servers::{
-    FailedServer, PopulatedRepositoryOrReplica, PopulatedServer, ScrapedServer, Server,
-    ServerBackendType, ServerMetadata, ServerType,
+    FailedRepositoryOrReplica, FailedServer, PopulatedRepositoryOrReplica, PopulatedServer, Scheme,
+    ScrapedServer, Server, ServerBackendType, ServerMetadata, ServerType,
 };
+pub use verification::SignatureVerification;
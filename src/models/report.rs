@@ -0,0 +1,124 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ScrapeError;
+use crate::models::ScrapedServer;
+
+/// The current version of the `ScrapeReport` format.
+///
+/// The version is bumped whenever a change to the serialized form of a scrape result is not
+/// backwards compatible, i.e. when an older reader can no longer read a newer report.
+pub const SCRAPE_REPORT_VERSION: u32 = 1;
+
+/// A versioned, serializable record of a complete scrape run.
+///
+/// Fields:
+///
+/// - version: The version of the report format, see `SCRAPE_REPORT_VERSION`
+/// - generated_at: When the report was created
+/// - servers: The scraped servers, populated or failed
+///
+/// Errors in failed servers and repositories are serialized as `ErrorReport`s, and are restored
+/// as `CVMFSScraperError::Restored` when the report is deserialized.
+///
+/// ### Example
+///
+/// ```rust
+/// use cvmfs_server_scraper::ScrapeReport;
+///
+/// let report = ScrapeReport::new(vec![]);
+/// let json = report.to_json().unwrap();
+/// let restored = ScrapeReport::from_json(&json).unwrap();
+/// assert_eq!(restored.servers.len(), 0);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrapeReport {
+    pub version: u32,
+    pub generated_at: DateTime<Utc>,
+    pub servers: Vec<ScrapedServer>,
+}
+
+impl ScrapeReport {
+    /// Create a report of the given scrape results, generated now.
+    pub fn new(servers: Vec<ScrapedServer>) -> Self {
+        ScrapeReport {
+            version: SCRAPE_REPORT_VERSION,
+            generated_at: Utc::now(),
+            servers,
+        }
+    }
+
+    /// Serialize the report to JSON.
+    pub fn to_json(&self) -> Result<String, ScrapeError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Deserialize a report from JSON.
+    ///
+    /// Fails if the report was written with a newer, unsupported, version of the format.
+    pub fn from_json(json: &str) -> Result<Self, ScrapeError> {
+        let report: ScrapeReport = serde_json::from_str(json)?;
+        if report.version > SCRAPE_REPORT_VERSION {
+            return Err(ScrapeError::InvalidJson(format!(
+                "Unsupported scrape report version {} (supported up to {})",
+                report.version, SCRAPE_REPORT_VERSION
+            )));
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorKind;
+    use crate::models::ServerType;
+    use crate::test_helpers::{failed_server, populated_server};
+
+    #[test]
+    fn test_scrape_report_roundtrip() {
+        let servers = vec![
+            populated_server(
+                "s1.example.com",
+                ServerType::Stratum1,
+                &[("software.eessi.io", 10, 1718990402)],
+            ),
+            failed_server("s2.example.com"),
+        ];
+        let report = ScrapeReport::new(servers);
+        let json = report.to_json().unwrap();
+        let restored = ScrapeReport::from_json(&json).unwrap();
+
+        assert_eq!(restored.version, SCRAPE_REPORT_VERSION);
+        assert_eq!(restored.generated_at, report.generated_at);
+        assert_eq!(restored.servers.len(), 2);
+
+        let populated = restored.servers[0].clone().get_populated_server().unwrap();
+        assert_eq!(
+            populated,
+            report.servers[0].clone().get_populated_server().unwrap()
+        );
+
+        let failed = restored.servers[1].clone().get_failed_server().unwrap();
+        let original = report.servers[1].clone().get_failed_server().unwrap();
+        assert_eq!(failed.hostname, original.hostname);
+        assert_eq!(failed.error.to_string(), original.error.to_string());
+        assert_eq!(failed.error.report().kind, ErrorKind::ServerTypeMismatch);
+    }
+
+    #[test]
+    fn test_scraped_server_is_tagged_by_status() {
+        let json = serde_json::to_value(failed_server("s2.example.com")).unwrap();
+        assert_eq!(json["status"], "failed");
+        assert_eq!(json["hostname"], "s2.example.com");
+        assert_eq!(json["error"]["kind"], "server_type_mismatch");
+    }
+
+    #[test]
+    fn test_scrape_report_rejects_newer_versions() {
+        let mut report = ScrapeReport::new(vec![]);
+        report.version = SCRAPE_REPORT_VERSION + 1;
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(ScrapeReport::from_json(&json).is_err());
+    }
+}
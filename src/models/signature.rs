@@ -0,0 +1,391 @@
+//! Verification of manifest signatures and of certificates against the whitelist.
+//!
+//! This module requires the `signatures` feature.
+
+use std::io::Read;
+
+use chrono::{DateTime, Utc};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{Pkcs1v15Sign, RsaPublicKey};
+use sha1::{Digest, Sha1};
+use x509_cert::der::{DecodePem, Encode};
+use x509_cert::Certificate;
+
+use crate::errors::ManifestError;
+use crate::models::cvmfs_whitelist::Whitelist;
+use crate::models::generic::HexString;
+
+/// The signed parts of a raw .cvmfspublished.
+///
+/// The manifest is made up of the body (the field lines), a "--" separator line, the hex hash
+/// of the body and the signature. The signature is made with the private key of the repository
+/// certificate, over the hex hash of the body, using RSA PKCS#1 v1.5 with SHA-1.
+#[derive(Debug)]
+pub(crate) struct SignedManifest<'a> {
+    body: &'a [u8],
+    hash: &'a str,
+    signature: &'a [u8],
+}
+
+impl<'a> SignedManifest<'a> {
+    pub fn parse(raw: &'a [u8]) -> Result<Self, ManifestError> {
+        let separator = b"--\n";
+        let start = if raw.starts_with(separator) {
+            0
+        } else {
+            raw.windows(separator.len() + 1)
+                .position(|w| w[0] == b'\n' && &w[1..] == separator)
+                .map(|i| i + 1)
+                .ok_or_else(|| {
+                    ManifestError::InvalidSignature("Missing signature separator".to_string())
+                })?
+        };
+
+        let body = &raw[..start];
+        let rest = &raw[start + separator.len()..];
+        let hash_end = rest
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| ManifestError::InvalidSignature("Missing manifest hash".to_string()))?;
+        let hash = std::str::from_utf8(&rest[..hash_end])
+            .map_err(|_| ManifestError::InvalidSignature("Invalid manifest hash".to_string()))?;
+
+        Ok(SignedManifest {
+            body,
+            hash,
+            signature: &rest[hash_end + 1..],
+        })
+    }
+
+    /// Check that the hash in the manifest is the hash of the body.
+    pub fn verify_hash(&self) -> Result<(), ManifestError> {
+        // Hashes other than SHA-1 are suffixed with their algorithm (e.g. "-rmd160").
+        if self.hash.contains('-') {
+            return Err(ManifestError::InvalidSignature(format!(
+                "Unsupported manifest hash algorithm: {}",
+                self.hash
+            )));
+        }
+        let actual = hex_sha1(self.body);
+        if actual != self.hash.to_lowercase() {
+            return Err(ManifestError::InvalidSignature(format!(
+                "Manifest hash mismatch: expected {}, got {}",
+                self.hash, actual
+            )));
+        }
+        Ok(())
+    }
+
+    /// The repository name (the "N" field) of the body, if present.
+    pub fn repository_name(&self) -> Option<&'a str> {
+        std::str::from_utf8(self.body)
+            .ok()?
+            .lines()
+            .find_map(|line| line.strip_prefix('N'))
+    }
+
+    /// Check the signature over the hash with the given public key.
+    pub fn verify_signature(&self, key: &RsaPublicKey) -> Result<(), ManifestError> {
+        let hashed = Sha1::digest(self.hash.as_bytes());
+        key.verify(Pkcs1v15Sign::new::<Sha1>(), &hashed, self.signature)
+            .map_err(|e| ManifestError::InvalidSignature(format!("Signature mismatch: {}", e)))
+    }
+}
+
+/// Decode a certificate object as stored in the repository.
+///
+/// The object is a zlib-compressed PEM certificate, addressed by the SHA-1 hash of the
+/// compressed content. The hash is checked against the expected hash from the manifest.
+fn decode_certificate(
+    object: &[u8],
+    expected_hash: &HexString,
+) -> Result<Certificate, ManifestError> {
+    let actual = hex_sha1(object);
+    if actual != expected_hash.to_string() {
+        return Err(ManifestError::InvalidCertificate(format!(
+            "Certificate hash mismatch: expected {}, got {}",
+            expected_hash, actual
+        )));
+    }
+
+    let mut pem = Vec::new();
+    flate2::read::ZlibDecoder::new(object)
+        .read_to_end(&mut pem)
+        .map_err(|e| ManifestError::InvalidCertificate(format!("Decompression failed: {}", e)))?;
+    Certificate::from_pem(&pem)
+        .map_err(|e| ManifestError::InvalidCertificate(format!("Invalid PEM: {}", e)))
+}
+
+fn public_key(certificate: &Certificate) -> Result<RsaPublicKey, ManifestError> {
+    let spki = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|e| ManifestError::InvalidCertificate(format!("Invalid public key: {}", e)))?;
+    RsaPublicKey::from_public_key_der(&spki)
+        .map_err(|e| ManifestError::InvalidCertificate(format!("Invalid RSA public key: {}", e)))
+}
+
+/// The SHA-1 fingerprint of a certificate, as listed in the repository whitelist.
+fn fingerprint(certificate: &Certificate) -> Result<String, ManifestError> {
+    let der = certificate
+        .to_der()
+        .map_err(|e| ManifestError::InvalidCertificate(format!("Invalid certificate: {}", e)))?;
+    Ok(Sha1::digest(der)
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<String>>()
+        .join(":"))
+}
+
+/// Check that a certificate is listed in the whitelist, and that the whitelist belongs to the
+/// given repository.
+fn check_whitelisted(
+    certificate: &Certificate,
+    whitelist: &Whitelist,
+    repository: Option<&str>,
+) -> Result<(), ManifestError> {
+    let fingerprint = fingerprint(certificate)?;
+    if !whitelist.contains_fingerprint(&fingerprint) {
+        return Err(ManifestError::InvalidCertificate(format!(
+            "Certificate {} is not in the whitelist of {}",
+            fingerprint, whitelist.repository
+        )));
+    }
+    if repository != Some(whitelist.repository.as_str()) {
+        return Err(ManifestError::InvalidCertificate(format!(
+            "Whitelist is for {}, not {}",
+            whitelist.repository,
+            repository.unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
+
+/// Verify a raw manifest against a raw certificate object.
+///
+/// If a whitelist is given, the certificate must also be listed in it, and the whitelist must
+/// belong to the repository of the manifest.
+pub(crate) fn verify_manifest(
+    manifest: &[u8],
+    certificate: &[u8],
+    certificate_hash: &HexString,
+    whitelist: Option<&Whitelist>,
+) -> Result<(), ManifestError> {
+    let signed = SignedManifest::parse(manifest)?;
+    signed.verify_hash()?;
+    let certificate = decode_certificate(certificate, certificate_hash)?;
+    signed.verify_signature(&public_key(&certificate)?)?;
+
+    if let Some(whitelist) = whitelist {
+        check_whitelisted(&certificate, whitelist, signed.repository_name())?;
+    }
+    Ok(())
+}
+
+/// Verify a raw certificate object against the whitelist of a repository.
+///
+/// Unlike `verify_manifest`, this does not need the manifest signature. The whitelist must not
+/// have expired at `now`, it must belong to the repository, and the certificate must be listed
+/// in it.
+pub(crate) fn verify_whitelist(
+    certificate: &[u8],
+    certificate_hash: &HexString,
+    whitelist: &Whitelist,
+    repository: &str,
+    now: DateTime<Utc>,
+) -> Result<(), ManifestError> {
+    if whitelist.is_expired_at(now) {
+        return Err(ManifestError::InvalidCertificate(format!(
+            "Whitelist of {} expired at {}",
+            whitelist.repository, whitelist.expires
+        )));
+    }
+    let certificate = decode_certificate(certificate, certificate_hash)?;
+    check_whitelisted(&certificate, whitelist, Some(repository))
+}
+
+fn hex_sha1(data: &[u8]) -> String {
+    Sha1::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rsa::pkcs8::DecodePrivateKey;
+    use rsa::RsaPrivateKey;
+    use std::io::Write;
+    use yare::parameterized;
+
+    pub const CERTIFICATE: &str = include_str!("../../tests/fixtures/signing.crt");
+    pub const PRIVATE_KEY: &str = include_str!("../../tests/fixtures/signing.key");
+
+    /// The certificate as stored in the repository, and its hash.
+    pub fn certificate_object() -> (Vec<u8>, HexString) {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(CERTIFICATE.as_bytes()).unwrap();
+        let object = encoder.finish().unwrap();
+        let hash = HexString::new(&hex_sha1(&object)).unwrap();
+        (object, hash)
+    }
+
+    /// A signed raw manifest for the given body.
+    pub fn sign(body: &str) -> Vec<u8> {
+        let key = RsaPrivateKey::from_pkcs8_pem(PRIVATE_KEY).unwrap();
+        let hash = hex_sha1(body.as_bytes());
+        let signature = key
+            .sign(Pkcs1v15Sign::new::<Sha1>(), &Sha1::digest(hash.as_bytes()))
+            .unwrap();
+        let mut raw = format!("{}--\n{}\n", body, hash).into_bytes();
+        raw.extend(signature);
+        raw
+    }
+
+    fn body(certificate_hash: &HexString) -> String {
+        format!(
+            "C600230b0ba7620426f2e898f1e1f43c5466efe59\nB1391\nRd41d8cd98f00b204e9800998ecf8427e\n\
+             D240\nS42\nGno\nAno\nNsoftware.eessi.io\nX{}\n\
+             H1ee5fc3a2b7a7b5c1d3c4b9e5f8e7f6a5b4c3d2e\nT1718990402\n\
+             M5a8b2e0f0e6a4b1c9d8f7e6a5b4c3d2e1f0a9b8c\nY4c3b2a1f0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b\n",
+            certificate_hash
+        )
+    }
+
+    #[test]
+    fn test_verify_valid_manifest() {
+        let (certificate, hash) = certificate_object();
+        let raw = sign(&body(&hash));
+        verify_manifest(&raw, &certificate, &hash, None).unwrap();
+
+        // The manifest itself still parses from the lossy text of the raw bytes.
+        let manifest: crate::models::Manifest = String::from_utf8_lossy(&raw).parse().unwrap();
+        assert_eq!(manifest.x, hash);
+    }
+
+    #[test]
+    fn test_verify_tampered_body() {
+        let (certificate, hash) = certificate_object();
+        let mut raw = sign(&body(&hash));
+        // Bump the revision, keeping the original hash and signature.
+        let revision = raw.windows(3).position(|w| w == b"S42").unwrap();
+        raw[revision + 2] = b'3';
+
+        match verify_manifest(&raw, &certificate, &hash, None) {
+            Err(ManifestError::InvalidSignature(reason)) => {
+                assert!(reason.contains("hash mismatch"), "{}", reason)
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify_corrupted_signature() {
+        let (certificate, hash) = certificate_object();
+        let mut raw = sign(&body(&hash));
+        let last = raw.len() - 1;
+        raw[last] ^= 0xff;
+
+        match verify_manifest(&raw, &certificate, &hash, None) {
+            Err(ManifestError::InvalidSignature(reason)) => {
+                assert!(reason.contains("Signature mismatch"), "{}", reason)
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    /// The fingerprint of the test certificate, as given by `openssl x509 -fingerprint -sha1`.
+    pub const FINGERPRINT: &str = "00:B3:F6:1E:08:0D:88:C2:EA:02:22:9C:E2:18:E9:0F:09:24:50:69";
+
+    fn whitelist(repository: &str, fingerprint: &str) -> Whitelist {
+        format!(
+            "20240617000003\nE20240717000003\nN{}\n{}\n--\n",
+            repository, fingerprint
+        )
+        .parse()
+        .unwrap()
+    }
+
+    #[parameterized(
+        listed = { "software.eessi.io", FINGERPRINT, None },
+        not_listed = { "software.eessi.io", "FF:B3:F6:1E:08:0D:88:C2:EA:02:22:9C:E2:18:E9:0F:09:24:50:69", Some("not in the whitelist") },
+        other_repository = { "dev.eessi.io", FINGERPRINT, Some("Whitelist is for dev.eessi.io") },
+    )]
+    fn test_verify_against_whitelist(repository: &str, fingerprint: &str, error: Option<&str>) {
+        let (certificate, hash) = certificate_object();
+        let raw = sign(&body(&hash));
+        let whitelist = whitelist(repository, fingerprint);
+        match (
+            verify_manifest(&raw, &certificate, &hash, Some(&whitelist)),
+            error,
+        ) {
+            (Ok(()), None) => {}
+            (Err(ManifestError::InvalidCertificate(reason)), Some(expected)) => {
+                assert!(reason.contains(expected), "{}", reason)
+            }
+            (other, _) => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[parameterized(
+        listed = { "software.eessi.io", FINGERPRINT, "20240701000000", None },
+        not_listed = { "software.eessi.io", "FF:B3:F6:1E:08:0D:88:C2:EA:02:22:9C:E2:18:E9:0F:09:24:50:69", "20240701000000", Some("not in the whitelist") },
+        other_repository = { "dev.eessi.io", FINGERPRINT, "20240701000000", Some("Whitelist is for dev.eessi.io") },
+        expired = { "software.eessi.io", FINGERPRINT, "20240801000000", Some("expired") },
+    )]
+    fn test_verify_whitelist(repository: &str, fingerprint: &str, now: &str, error: Option<&str>) {
+        let (certificate, hash) = certificate_object();
+        let whitelist = whitelist(repository, fingerprint);
+        let now = chrono::NaiveDateTime::parse_from_str(now, "%Y%m%d%H%M%S")
+            .unwrap()
+            .and_utc();
+        match (
+            verify_whitelist(&certificate, &hash, &whitelist, "software.eessi.io", now),
+            error,
+        ) {
+            (Ok(()), None) => {}
+            (Err(ManifestError::InvalidCertificate(reason)), Some(expected)) => {
+                assert!(reason.contains(expected), "{}", reason)
+            }
+            (other, _) => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_certificate_hash_mismatch() {
+        let (certificate, _) = certificate_object();
+        let other = HexString::new(&"0".repeat(40)).unwrap();
+        match decode_certificate(&certificate, &other) {
+            Err(ManifestError::InvalidCertificate(reason)) => {
+                assert!(reason.contains("hash mismatch"), "{}", reason)
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_certificate_not_compressed() {
+        let object = CERTIFICATE.as_bytes();
+        let hash = HexString::new(&hex_sha1(object)).unwrap();
+        match decode_certificate(object, &hash) {
+            Err(ManifestError::InvalidCertificate(reason)) => {
+                assert!(reason.contains("Decompression failed"), "{}", reason)
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[parameterized(
+        no_separator = { b"C00\nB1\n".as_slice() },
+        no_hash_newline = { b"C00\n--\nabc".as_slice() },
+    )]
+    fn test_parse_malformed_manifest(raw: &[u8]) {
+        assert!(matches!(
+            SignedManifest::parse(raw),
+            Err(ManifestError::InvalidSignature(_))
+        ));
+    }
+}
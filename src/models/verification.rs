@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// The result of verifying the signature of a repository manifest, or of checking its
+/// certificate against the repository whitelist.
+///
+/// - Valid: The manifest hash, the certificate hash and the signature all match.
+/// - Invalid: The manifest or certificate failed verification, the manifest may be tampered with
+///   or corrupted.
+/// - Unverified: The certificate could not be fetched, so the signature could not be checked.
+///
+/// When serialized, the variant is given by the `status` field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SignatureVerification {
+    Valid,
+    Invalid { reason: String },
+    Unverified { reason: String },
+}
+
+impl SignatureVerification {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, SignatureVerification::Valid)
+    }
+
+    pub fn is_invalid(&self) -> bool {
+        matches!(self, SignatureVerification::Invalid { .. })
+    }
+}
+
+impl std::fmt::Display for SignatureVerification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureVerification::Valid => write!(f, "valid"),
+            SignatureVerification::Invalid { reason } => write!(f, "invalid ({})", reason),
+            SignatureVerification::Unverified { reason } => write!(f, "unverified ({})", reason),
+        }
+    }
+}
@@ -0,0 +1,511 @@
+//! Replication lag between the Stratum0 and the replicas of each repository.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{ScrapedServer, ServerAddress, ServerType};
+use crate::repository_view::{RepositoryOnServer, RepositoryView, UnavailableReason};
+
+/// The replication status of a replica, ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplicationStatus {
+    Ok,
+    Warning,
+    Critical,
+}
+
+impl std::fmt::Display for ReplicationStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplicationStatus::Ok => write!(f, "OK"),
+            ReplicationStatus::Warning => write!(f, "WARNING"),
+            ReplicationStatus::Critical => write!(f, "CRITICAL"),
+        }
+    }
+}
+
+/// Thresholds for classifying replicas by how far they are behind the reference.
+///
+/// A replica is critical if it reaches either critical threshold, a warning if it reaches either
+/// warning threshold, and OK otherwise.
+///
+/// Fields:
+///
+/// - warning_revisions: The revision gap at which a replica is a warning (default 1)
+/// - critical_revisions: The revision gap at which a replica is critical (default 5)
+/// - warning_lag: The time lag at which a replica is a warning (default 1 hour)
+/// - critical_lag: The time lag at which a replica is critical (default 6 hours)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplicationThresholds {
+    pub warning_revisions: i32,
+    pub critical_revisions: i32,
+    pub warning_lag: Duration,
+    pub critical_lag: Duration,
+}
+
+impl Default for ReplicationThresholds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplicationThresholds {
+    /// Create new thresholds with the default settings.
+    pub fn new() -> Self {
+        ReplicationThresholds {
+            warning_revisions: 1,
+            critical_revisions: 5,
+            warning_lag: Duration::from_secs(60 * 60),
+            critical_lag: Duration::from_secs(6 * 60 * 60),
+        }
+    }
+
+    /// Set the revision gap at which a replica is a warning.
+    pub fn warning_revisions(mut self, revisions: i32) -> Self {
+        self.warning_revisions = revisions;
+        self
+    }
+
+    /// Set the revision gap at which a replica is critical.
+    pub fn critical_revisions(mut self, revisions: i32) -> Self {
+        self.critical_revisions = revisions;
+        self
+    }
+
+    /// Set the time lag at which a replica is a warning.
+    pub fn warning_lag(mut self, lag: Duration) -> Self {
+        self.warning_lag = lag;
+        self
+    }
+
+    /// Set the time lag at which a replica is critical.
+    pub fn critical_lag(mut self, lag: Duration) -> Self {
+        self.critical_lag = lag;
+        self
+    }
+
+    /// Classify a replica by its revision gap and time lag.
+    pub fn classify(&self, revision_gap: i32, time_lag: Duration) -> ReplicationStatus {
+        if revision_gap >= self.critical_revisions || time_lag >= self.critical_lag {
+            ReplicationStatus::Critical
+        } else if revision_gap >= self.warning_revisions || time_lag >= self.warning_lag {
+            ReplicationStatus::Warning
+        } else {
+            ReplicationStatus::Ok
+        }
+    }
+}
+
+/// The server a repository's replicas are compared against.
+///
+/// Fields:
+///
+/// - server: The key of the reference server, see `ScrapedServer::key`
+/// - hostname: The address of the reference server
+/// - server_type: The type of the reference server
+/// - revision: The revision of the repository on the reference server
+/// - timestamp: The timestamp of that revision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplicationReference {
+    pub server: String,
+    pub hostname: ServerAddress,
+    pub server_type: ServerType,
+    pub revision: i32,
+    pub timestamp: i64,
+}
+
+/// The replication state of a repository on a single Stratum1 or SyncServer.
+///
+/// Fields:
+///
+/// - server: The key of the replica, see `ScrapedServer::key`
+/// - hostname: The address of the replica
+/// - server_type: The type of the replica (Stratum1 or SyncServer)
+/// - revision: The revision of the repository on the replica
+/// - timestamp: The timestamp of that revision
+/// - revision_gap: How many revisions the replica is behind the reference (zero if not behind)
+/// - time_lag: How much older the replica's revision is than the reference's (zero if not behind)
+/// - status: The classification of the gap and lag
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplicaLag {
+    pub server: String,
+    pub hostname: ServerAddress,
+    pub server_type: ServerType,
+    pub revision: i32,
+    pub timestamp: i64,
+    pub revision_gap: i32,
+    pub time_lag: Duration,
+    pub status: ReplicationStatus,
+}
+
+/// The replication state of a single repository across the fleet.
+///
+/// Fields:
+///
+/// - repository: The name of the repository
+/// - reference: The server the replicas are compared against, `None` if no server serves the
+///   repository (it failed everywhere it was listed)
+/// - replicas: Every Stratum1 and SyncServer that serves the repository
+/// - unavailable: The keys of the Stratum1s and SyncServers where scraping the repository failed,
+///   or where scraping the server failed altogether
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryReplication {
+    pub repository: String,
+    pub reference: Option<ReplicationReference>,
+    pub replicas: Vec<ReplicaLag>,
+    pub unavailable: Vec<String>,
+}
+
+impl RepositoryReplication {
+    /// The worst status of the replicas, OK if there are none.
+    ///
+    /// A repository that no server serves, or that is unavailable on any replica, is critical.
+    pub fn status(&self) -> ReplicationStatus {
+        if self.reference.is_none() || !self.unavailable.is_empty() {
+            return ReplicationStatus::Critical;
+        }
+        self.replicas
+            .iter()
+            .map(|r| r.status)
+            .max()
+            .unwrap_or(ReplicationStatus::Ok)
+    }
+}
+
+/// Replication lag for every repository in a scrape run.
+///
+/// For each repository, the reference is the Stratum0 serving it. If no Stratum0 in the run
+/// serves the repository, the server with the highest revision is used instead. Every Stratum1
+/// and SyncServer serving the repository is then compared to the reference.
+///
+/// A repository that failed on every server listing it is still included, without a reference
+/// and with every such Stratum1 and SyncServer unavailable. Stratum1s and SyncServers that failed
+/// to scrape altogether are listed as unavailable for every repository.
+///
+/// Repositories are sorted by name, replicas by key. The report is built on the
+/// `RepositoryView` of the run, see `from_view`.
+///
+/// ### Example
+///
+/// ```rust,no_run
+/// use cvmfs_server_scraper::{ReplicationReport, ReplicationThresholds, Scraper};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let scraper = Scraper::new().with_servers(vec![]).validate()?;
+///     let servers = scraper.scrape().await;
+///     let report = ReplicationReport::new(&servers, &ReplicationThresholds::default());
+///     report.output();
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplicationReport {
+    pub repositories: Vec<RepositoryReplication>,
+}
+
+impl ReplicationReport {
+    pub fn new(servers: &[ScrapedServer], thresholds: &ReplicationThresholds) -> Self {
+        Self::from_view(&RepositoryView::new(servers), thresholds)
+    }
+
+    /// Build the report from the scrape run pivoted by repository.
+    ///
+    /// Use this to build both from the same run without pivoting it twice.
+    pub fn from_view(view: &RepositoryView, thresholds: &ReplicationThresholds) -> Self {
+        let repositories = view
+            .repositories
+            .iter()
+            .map(|repo| {
+                let unavailable = repo
+                    .unavailable
+                    .iter()
+                    .filter(|u| {
+                        u.server_type != ServerType::Stratum0
+                            && !matches!(u.reason, UnavailableReason::Missing)
+                    })
+                    .map(|u| u.server.clone())
+                    .collect::<Vec<_>>();
+                // Prefer the Stratum0, then the highest revision. Ties go to the lowest key.
+                let reference = repo.servers.iter().reduce(|best, candidate| {
+                    let key = |s: &RepositoryOnServer| {
+                        (s.server_type == ServerType::Stratum0, s.revision)
+                    };
+                    if key(candidate) > key(best) {
+                        candidate
+                    } else {
+                        best
+                    }
+                });
+
+                let Some(reference) = reference else {
+                    return RepositoryReplication {
+                        repository: repo.name.clone(),
+                        reference: None,
+                        replicas: Vec::new(),
+                        unavailable,
+                    };
+                };
+
+                let replicas = repo
+                    .servers
+                    .iter()
+                    .filter(|s| s.server_type != ServerType::Stratum0)
+                    .map(|s| {
+                        let revision_gap = (reference.revision - s.revision).max(0);
+                        let time_lag =
+                            Duration::from_secs((reference.timestamp - s.timestamp).max(0) as u64);
+                        ReplicaLag {
+                            server: s.server.clone(),
+                            hostname: s.hostname.clone(),
+                            server_type: s.server_type,
+                            revision: s.revision,
+                            timestamp: s.timestamp,
+                            revision_gap,
+                            time_lag,
+                            status: thresholds.classify(revision_gap, time_lag),
+                        }
+                    })
+                    .collect();
+
+                RepositoryReplication {
+                    repository: repo.name.clone(),
+                    reference: Some(ReplicationReference {
+                        server: reference.server.clone(),
+                        hostname: reference.hostname.clone(),
+                        server_type: reference.server_type,
+                        revision: reference.revision,
+                        timestamp: reference.timestamp,
+                    }),
+                    replicas,
+                    unavailable,
+                }
+            })
+            .collect();
+
+        ReplicationReport { repositories }
+    }
+
+    /// The worst status across all repositories, OK if there are none.
+    pub fn status(&self) -> ReplicationStatus {
+        self.repositories
+            .iter()
+            .map(|r| r.status())
+            .max()
+            .unwrap_or(ReplicationStatus::Ok)
+    }
+
+    /// The replication state of the given repository, if it was seen.
+    pub fn repository(&self, name: &str) -> Option<&RepositoryReplication> {
+        self.repositories.iter().find(|r| r.repository == name)
+    }
+
+    pub fn output(&self) {
+        for repo in &self.repositories {
+            println!("Repository: {} [{}]", repo.repository, repo.status());
+            match &repo.reference {
+                Some(reference) => println!(
+                    "  Reference: {} ({:?}) revision {} at {}",
+                    reference.server,
+                    reference.server_type,
+                    reference.revision,
+                    reference.timestamp
+                ),
+                None => println!("  Reference: none, no server serves the repository"),
+            }
+            for replica in &repo.replicas {
+                println!(
+                    "  {} {}: revision {} (-{}), lag {}s",
+                    replica.status,
+                    replica.server,
+                    replica.revision,
+                    replica.revision_gap,
+                    replica.time_lag.as_secs()
+                );
+            }
+            for server in &repo.unavailable {
+                println!("  UNAVAILABLE {}", server);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{failed_server, populated_server};
+    use yare::parameterized;
+
+    const REPO: &str = "software.eessi.io";
+    const HOUR: i64 = 60 * 60;
+
+    #[parameterized(
+        in_sync = { 0, 0, ReplicationStatus::Ok },
+        one_revision = { 1, 60, ReplicationStatus::Warning },
+        many_revisions = { 5, 60, ReplicationStatus::Critical },
+        old_revision = { 1, 2 * HOUR, ReplicationStatus::Warning },
+        very_old_revision = { 1, 7 * HOUR, ReplicationStatus::Critical },
+    )]
+    fn test_classify(gap: i32, lag: i64, expected: ReplicationStatus) {
+        let thresholds = ReplicationThresholds::default();
+        assert_eq!(
+            thresholds.classify(gap, Duration::from_secs(lag as u64)),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_report_uses_stratum0_as_reference() {
+        let t = 1718990402;
+        let servers = vec![
+            populated_server("s0.example.com", ServerType::Stratum0, &[(REPO, 10, t)]),
+            populated_server(
+                "s1-b.example.com",
+                ServerType::Stratum1,
+                &[(REPO, 8, t - 2 * HOUR)],
+            ),
+            populated_server("s1-a.example.com", ServerType::Stratum1, &[(REPO, 10, t)]),
+            populated_server(
+                "sync.example.com",
+                ServerType::SyncServer,
+                &[(REPO, 12, t + HOUR)],
+            ),
+            failed_server("s1-c.example.com"),
+        ];
+
+        let report = ReplicationReport::new(&servers, &ReplicationThresholds::default());
+        let repo = report.repository(REPO).unwrap();
+        assert_eq!(
+            repo.reference.as_ref().unwrap().hostname.to_string(),
+            "s0.example.com"
+        );
+        assert_eq!(repo.reference.as_ref().unwrap().revision, 10);
+
+        let replicas = repo
+            .replicas
+            .iter()
+            .map(|r| {
+                (
+                    r.hostname.to_string(),
+                    r.revision_gap,
+                    r.time_lag.as_secs() as i64,
+                    r.status,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            replicas,
+            vec![
+                ("s1-a.example.com".to_string(), 0, 0, ReplicationStatus::Ok),
+                (
+                    "s1-b.example.com".to_string(),
+                    2,
+                    2 * HOUR,
+                    ReplicationStatus::Warning
+                ),
+                ("sync.example.com".to_string(), 0, 0, ReplicationStatus::Ok),
+            ]
+        );
+        // s1-c failed to scrape, so the repository is unavailable there.
+        assert_eq!(repo.unavailable, vec!["s1-c.example.com".to_string()]);
+        assert_eq!(report.status(), ReplicationStatus::Critical);
+    }
+
+    #[test]
+    fn test_report_without_stratum0_uses_highest_revision() {
+        let t = 1718990402;
+        let servers = vec![
+            populated_server(
+                "s1-a.example.com",
+                ServerType::Stratum1,
+                &[(REPO, 4, t - 7 * HOUR)],
+            ),
+            populated_server("s1-b.example.com", ServerType::Stratum1, &[(REPO, 9, t)]),
+        ];
+
+        let report = ReplicationReport::new(&servers, &ReplicationThresholds::default());
+        let repo = report.repository(REPO).unwrap();
+        assert_eq!(
+            repo.reference.as_ref().unwrap().hostname.to_string(),
+            "s1-b.example.com"
+        );
+        assert_eq!(repo.replicas[0].revision_gap, 5);
+        assert_eq!(repo.replicas[0].status, ReplicationStatus::Critical);
+        assert_eq!(repo.replicas[1].status, ReplicationStatus::Ok);
+        assert_eq!(report.status(), ReplicationStatus::Critical);
+    }
+
+    #[test]
+    fn test_report_lists_unavailable_replicas() {
+        let mut servers = vec![
+            populated_server("s0.example.com", ServerType::Stratum0, &[(REPO, 1, 0)]),
+            populated_server("s1.example.com", ServerType::Stratum1, &[]),
+        ];
+        if let ScrapedServer::Populated(populated) = &mut servers[1] {
+            populated
+                .failed_repositories
+                .push(crate::models::FailedRepositoryOrReplica {
+                    name: REPO.to_string(),
+                    error: crate::errors::ScrapeError::ConversionError("test".to_string()).into(),
+                    fetches: Vec::new(),
+                });
+        }
+
+        let report = ReplicationReport::new(&servers, &ReplicationThresholds::default());
+        let repo = report.repository(REPO).unwrap();
+        assert!(repo.replicas.is_empty());
+        assert_eq!(repo.unavailable, vec!["s1.example.com".to_string()]);
+        assert_eq!(repo.status(), ReplicationStatus::Critical);
+    }
+
+    #[test]
+    fn test_report_lists_repositories_failed_on_every_replica() {
+        let mut servers = vec![
+            populated_server("s1-a.example.com", ServerType::Stratum1, &[]),
+            populated_server("s1-b.example.com", ServerType::Stratum1, &[]),
+        ];
+        for server in &mut servers {
+            if let ScrapedServer::Populated(populated) = server {
+                populated
+                    .failed_repositories
+                    .push(crate::models::FailedRepositoryOrReplica {
+                        name: REPO.to_string(),
+                        error: crate::errors::ScrapeError::ConversionError("test".to_string())
+                            .into(),
+                        fetches: Vec::new(),
+                    });
+            }
+        }
+
+        let report = ReplicationReport::new(&servers, &ReplicationThresholds::default());
+        let repo = report.repository(REPO).unwrap();
+        assert_eq!(repo.reference, None);
+        assert!(repo.replicas.is_empty());
+        assert_eq!(
+            repo.unavailable,
+            vec![
+                "s1-a.example.com".to_string(),
+                "s1-b.example.com".to_string()
+            ]
+        );
+        assert_eq!(repo.status(), ReplicationStatus::Critical);
+        assert_eq!(report.status(), ReplicationStatus::Critical);
+    }
+
+    #[test]
+    fn test_report_from_view() {
+        let servers = vec![
+            populated_server("s0.example.com", ServerType::Stratum0, &[(REPO, 3, 0)]),
+            populated_server("s1.example.com", ServerType::Stratum1, &[(REPO, 2, 0)]),
+            failed_server("s1-b.example.com"),
+        ];
+        let thresholds = ReplicationThresholds::default();
+        let view = RepositoryView::new(&servers);
+        assert_eq!(
+            ReplicationReport::from_view(&view, &thresholds),
+            ReplicationReport::new(&servers, &thresholds)
+        );
+    }
+}
@@ -0,0 +1,442 @@
+//! A repository-centric view of a scrape run.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ErrorReport;
+use crate::models::{MaybeRfc2822DateTime, ScrapedServer, ServerAddress, ServerType};
+
+/// A repository as served by a single server.
+///
+/// Fields:
+///
+/// - server: The key of the server, see `ScrapedServer::key`
+/// - hostname: The address of the server
+/// - server_type: The type of the server
+/// - revision: The revision of the repository on the server (the `s` field of the manifest)
+/// - root_hash: The hash of the root catalog (the `c` field of the manifest)
+/// - timestamp: The timestamp of the revision (the `t` field of the manifest)
+/// - last_snapshot: The last snapshot of the repository, if known
+/// - last_gc: The last garbage collection of the repository, if known
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryOnServer {
+    pub server: String,
+    pub hostname: ServerAddress,
+    pub server_type: ServerType,
+    pub revision: i32,
+    pub root_hash: String,
+    pub timestamp: i64,
+    pub last_snapshot: Option<MaybeRfc2822DateTime>,
+    pub last_gc: Option<MaybeRfc2822DateTime>,
+}
+
+/// Why a repository is not available on a server.
+///
+/// Missing: The server was scraped, but does not carry the repository.
+/// Failed: The server lists the repository, but scraping it failed.
+/// ServerFailed: Scraping the server failed, so it is unknown whether it carries the repository.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum UnavailableReason {
+    Missing,
+    Failed { error: ErrorReport },
+    ServerFailed { error: ErrorReport },
+}
+
+/// A server where a repository is not available.
+///
+/// Fields:
+///
+/// - server: The key of the server, see `ScrapedServer::key`
+/// - hostname: The address of the server
+/// - server_type: The type of the server
+/// - reason: Why the repository is not available, see `UnavailableReason`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryUnavailable {
+    pub server: String,
+    pub hostname: ServerAddress,
+    pub server_type: ServerType,
+    #[serde(flatten)]
+    pub reason: UnavailableReason,
+}
+
+/// A single repository across every server in a scrape run.
+///
+/// Fields:
+///
+/// - name: The name of the repository
+/// - servers: Every server that serves the repository, sorted by key
+/// - unavailable: Every other server in the run, sorted by key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryServers {
+    pub name: String,
+    pub servers: Vec<RepositoryOnServer>,
+    pub unavailable: Vec<RepositoryUnavailable>,
+}
+
+impl RepositoryServers {
+    /// The lowest revision served, if any server serves the repository.
+    pub fn min_revision(&self) -> Option<i32> {
+        self.servers.iter().map(|s| s.revision).min()
+    }
+
+    /// The highest revision served, if any server serves the repository.
+    pub fn max_revision(&self) -> Option<i32> {
+        self.servers.iter().map(|s| s.revision).max()
+    }
+
+    /// The revision served by the most servers, if any server serves the repository.
+    ///
+    /// Ties go to the highest revision.
+    pub fn modal_revision(&self) -> Option<i32> {
+        let mut counts: HashMap<i32, usize> = HashMap::new();
+        for server in &self.servers {
+            *counts.entry(server.revision).or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(revision, count)| (*count, *revision))
+            .map(|(revision, _)| revision)
+    }
+
+    /// The servers serving the given revision.
+    pub fn at_revision(&self, revision: i32) -> Vec<&RepositoryOnServer> {
+        self.servers
+            .iter()
+            .filter(|s| s.revision == revision)
+            .collect()
+    }
+
+    /// The servers not serving the given revision, including those where the repository is
+    /// unavailable, by key and sorted.
+    pub fn not_at_revision(&self, revision: i32) -> Vec<&str> {
+        let mut servers = self
+            .servers
+            .iter()
+            .filter(|s| s.revision != revision)
+            .map(|s| s.server.as_str())
+            .chain(self.unavailable.iter().map(|u| u.server.as_str()))
+            .collect::<Vec<_>>();
+        servers.sort();
+        servers
+    }
+}
+
+/// Scrape results pivoted by repository.
+///
+/// Every repository served or listed by any server in the run is included. For each, the servers
+/// serving it are listed with the state of the repository, and every other server in the run is
+/// listed as unavailable, with the reason.
+///
+/// Repositories are sorted by name.
+///
+/// ### Example
+///
+/// ```rust,no_run
+/// use cvmfs_server_scraper::{RepositoryView, Scraper};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let scraper = Scraper::new().with_servers(vec![]).validate()?;
+///     let servers = scraper.scrape().await;
+///     let view = RepositoryView::new(&servers);
+///     if let Some(repo) = view.repository("software.eessi.io") {
+///         println!("Not at revision 1234: {:?}", repo.not_at_revision(1234));
+///     }
+///     view.output();
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryView {
+    pub repositories: Vec<RepositoryServers>,
+}
+
+impl RepositoryView {
+    pub fn new(servers: &[ScrapedServer]) -> Self {
+        let mut repositories: BTreeMap<&str, RepositoryServers> = BTreeMap::new();
+        for server in servers {
+            let ScrapedServer::Populated(populated) = server else {
+                continue;
+            };
+            for repo in &populated.repositories {
+                repositories
+                    .entry(repo.name.as_str())
+                    .or_insert_with(|| empty(&repo.name))
+                    .servers
+                    .push(RepositoryOnServer {
+                        server: populated.key(),
+                        hostname: populated.hostname.clone(),
+                        server_type: populated.server_type,
+                        revision: repo.manifest.s,
+                        root_hash: repo.manifest.c.to_string(),
+                        timestamp: repo.manifest.t,
+                        last_snapshot: repo.last_snapshot.clone(),
+                        last_gc: repo.last_gc.clone(),
+                    });
+            }
+            for repo in &populated.failed_repositories {
+                repositories
+                    .entry(repo.name.as_str())
+                    .or_insert_with(|| empty(&repo.name))
+                    .unavailable
+                    .push(RepositoryUnavailable {
+                        server: populated.key(),
+                        hostname: populated.hostname.clone(),
+                        server_type: populated.server_type,
+                        reason: UnavailableReason::Failed {
+                            error: ErrorReport::from(&repo.error),
+                        },
+                    });
+            }
+        }
+
+        // Every other server in the run is either missing the repository or failed entirely.
+        for (name, repository) in repositories.iter_mut() {
+            for server in servers {
+                let reason = match server {
+                    ScrapedServer::Populated(populated)
+                        if populated.has_repository(name)
+                            || populated.has_failed_repository(name) =>
+                    {
+                        continue
+                    }
+                    ScrapedServer::Populated(_) => UnavailableReason::Missing,
+                    ScrapedServer::Failed(failed) => UnavailableReason::ServerFailed {
+                        error: ErrorReport::from(&failed.error),
+                    },
+                };
+                let server_type = match server {
+                    ScrapedServer::Populated(populated) => populated.server_type,
+                    ScrapedServer::Failed(failed) => failed.server_type,
+                };
+                repository.unavailable.push(RepositoryUnavailable {
+                    server: server.key(),
+                    hostname: server.hostname().clone(),
+                    server_type,
+                    reason,
+                });
+            }
+            repository.servers.sort_by(|a, b| a.server.cmp(&b.server));
+            repository
+                .unavailable
+                .sort_by(|a, b| a.server.cmp(&b.server));
+        }
+
+        RepositoryView {
+            repositories: repositories.into_values().collect(),
+        }
+    }
+
+    /// The view of the given repository, if it was seen.
+    pub fn repository(&self, name: &str) -> Option<&RepositoryServers> {
+        self.repositories.iter().find(|r| r.name == name)
+    }
+
+    pub fn output(&self) {
+        for repo in &self.repositories {
+            println!("Repository: {}", repo.name);
+            if let (Some(min), Some(max), Some(modal)) = (
+                repo.min_revision(),
+                repo.max_revision(),
+                repo.modal_revision(),
+            ) {
+                println!("  Revisions: min {}, max {}, modal {}", min, max, modal);
+            }
+            for server in &repo.servers {
+                println!(
+                    "  {} ({:?}): revision {} at {}, root {}",
+                    server.server,
+                    server.server_type,
+                    server.revision,
+                    server.timestamp,
+                    server.root_hash
+                );
+                if let Some(last_snapshot) = &server.last_snapshot {
+                    println!("    Last Snapshot: {}", last_snapshot);
+                }
+                if let Some(last_gc) = &server.last_gc {
+                    println!("    Last GC: {}", last_gc);
+                }
+            }
+            for unavailable in &repo.unavailable {
+                match &unavailable.reason {
+                    UnavailableReason::Missing => {
+                        println!("  MISSING {}", unavailable.server)
+                    }
+                    UnavailableReason::Failed { error } => {
+                        println!("  FAILED {}: {}", unavailable.server, error.message)
+                    }
+                    UnavailableReason::ServerFailed { error } => {
+                        println!("  SERVER FAILED {}: {}", unavailable.server, error.message)
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn empty(name: &str) -> RepositoryServers {
+    RepositoryServers {
+        name: name.to_string(),
+        servers: Vec::new(),
+        unavailable: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{CVMFSScraperError, ManifestError};
+    use crate::models::FailedRepositoryOrReplica;
+    use crate::test_helpers::{failed_server, populated_server};
+    use yare::parameterized;
+
+    const REPO: &str = "software.eessi.io";
+    const OTHER: &str = "dev.eessi.io";
+
+    fn servers() -> Vec<ScrapedServer> {
+        let mut servers = vec![
+            populated_server(
+                "s0.example.com",
+                ServerType::Stratum0,
+                &[(REPO, 12, 300), (OTHER, 3, 100)],
+            ),
+            populated_server("s1-b.example.com", ServerType::Stratum1, &[(REPO, 10, 200)]),
+            populated_server("s1-a.example.com", ServerType::Stratum1, &[(REPO, 10, 200)]),
+            populated_server("s1-c.example.com", ServerType::Stratum1, &[(OTHER, 3, 100)]),
+            failed_server("s1-d.example.com"),
+        ];
+        if let ScrapedServer::Populated(populated) = &mut servers[3] {
+            populated
+                .failed_repositories
+                .push(FailedRepositoryOrReplica {
+                    name: REPO.to_string(),
+                    error: CVMFSScraperError::from(ManifestError::MissingField('s')),
+                    fetches: Vec::new(),
+                });
+        }
+        servers
+    }
+
+    #[test]
+    fn test_view_lists_servers_per_repository() {
+        let view = RepositoryView::new(&servers());
+        let names = view
+            .repositories
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![OTHER, REPO]);
+
+        let repo = view.repository(REPO).unwrap();
+        let hostnames = repo
+            .servers
+            .iter()
+            .map(|s| (s.hostname.to_string(), s.revision))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hostnames,
+            vec![
+                ("s0.example.com".to_string(), 12),
+                ("s1-a.example.com".to_string(), 10),
+                ("s1-b.example.com".to_string(), 10),
+            ]
+        );
+        let first = &repo.servers[0];
+        assert_eq!(first.server_type, ServerType::Stratum0);
+        assert_eq!(first.timestamp, 300);
+        assert_eq!(first.root_hash, "600230b0ba7620426f2e898f1e1f43c5466efe59");
+        assert!(first.last_snapshot.is_some());
+        assert_eq!(first.last_gc, None);
+    }
+
+    #[test]
+    fn test_view_lists_unavailable_servers_with_reason() {
+        let view = RepositoryView::new(&servers());
+        let unavailable = view
+            .repository(REPO)
+            .unwrap()
+            .unavailable
+            .iter()
+            .map(|u| (u.hostname.to_string(), u.reason.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(unavailable.len(), 2);
+        assert_eq!(unavailable[0].0, "s1-c.example.com");
+        assert!(matches!(
+            &unavailable[0].1,
+            UnavailableReason::Failed { error } if error.message.contains("Missing field s")
+        ));
+        assert_eq!(unavailable[1].0, "s1-d.example.com");
+        assert!(matches!(
+            unavailable[1].1,
+            UnavailableReason::ServerFailed { .. }
+        ));
+
+        let other = view.repository(OTHER).unwrap();
+        let missing = other
+            .unavailable
+            .iter()
+            .filter(|u| u.reason == UnavailableReason::Missing)
+            .map(|u| u.hostname.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(missing, vec!["s1-a.example.com", "s1-b.example.com"]);
+    }
+
+    #[parameterized(
+        min = { RepositoryServers::min_revision, Some(10) },
+        max = { RepositoryServers::max_revision, Some(12) },
+        modal = { RepositoryServers::modal_revision, Some(10) },
+    )]
+    fn test_revision_helpers(helper: fn(&RepositoryServers) -> Option<i32>, expected: Option<i32>) {
+        let view = RepositoryView::new(&servers());
+        assert_eq!(helper(view.repository(REPO).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_revision_helpers_without_servers() {
+        let repo = empty(REPO);
+        assert_eq!(repo.min_revision(), None);
+        assert_eq!(repo.max_revision(), None);
+        assert_eq!(repo.modal_revision(), None);
+    }
+
+    #[test]
+    fn test_modal_revision_ties_go_to_highest() {
+        let view = RepositoryView::new(&[
+            populated_server("a.example.com", ServerType::Stratum1, &[(REPO, 1, 0)]),
+            populated_server("b.example.com", ServerType::Stratum1, &[(REPO, 2, 0)]),
+        ]);
+        assert_eq!(view.repository(REPO).unwrap().modal_revision(), Some(2));
+    }
+
+    #[test]
+    fn test_at_and_not_at_revision() {
+        let view = RepositoryView::new(&servers());
+        let repo = view.repository(REPO).unwrap();
+        let at = repo
+            .at_revision(10)
+            .iter()
+            .map(|s| s.hostname.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(at, vec!["s1-a.example.com", "s1-b.example.com"]);
+        let not_at = repo
+            .not_at_revision(10)
+            .iter()
+            .map(|h| h.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            not_at,
+            vec!["s0.example.com", "s1-c.example.com", "s1-d.example.com"]
+        );
+    }
+
+    #[test]
+    fn test_view_roundtrips_through_json() {
+        let view = RepositoryView::new(&servers());
+        let json = serde_json::to_string(&view).unwrap();
+        let restored: RepositoryView = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, view);
+    }
+}
@@ -3,11 +3,15 @@ use std::convert::TryFrom;
 use std::{fmt::Debug, time::Instant};
 
 use futures::future::join_all;
+use futures::stream::{FuturesUnordered, Stream};
 use std::marker::PhantomData;
+use std::sync::Arc;
 
-use crate::constants::DEFAULT_GEOAPI_SERVERS;
+use crate::client::{ClientConfig, HttpClient, RetryPolicy};
+use crate::constants::{DEFAULT_GEOAPI_SERVERS, DEFAULT_MAX_CONCURRENT_REQUESTS_PER_SERVER};
 use crate::errors::{HostnameError, ScrapeError};
-use crate::models::{Hostname, ScrapedServer, Server, ServerBackendType};
+use crate::fetcher::{Fetcher, ReqwestFetcher};
+use crate::models::{Hostname, ScrapeOptions, ScrapedServer, Server, ServerBackendType};
 
 pub struct WithoutServers;
 pub struct WithServers;
@@ -35,7 +39,9 @@ pub struct ValidatedAndReady;
 /// ### Example
 ///
 /// ```rust
-/// use cvmfs_server_scraper::{Scraper, ScraperCommon, Hostname, Server, ServerType, ServerBackendType};
+/// use std::time::Duration;
+/// use cvmfs_server_scraper::{Scraper, ScraperCommon, ClientConfig, Hostname, RetryPolicy, Server,
+///     ServerType, ServerBackendType};
 ///
 /// #[tokio::main]
 /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -57,6 +63,10 @@ pub struct ValidatedAndReady;
 ///        .with_servers(servers)
 ///        .only_scrape_forced_repositories(false) // this is the default
 ///        .ignored_repositories(vec!["repo3", "repo4"])
+///        .client_config(ClientConfig::new().timeout(Duration::from_secs(20)))
+///        .retry_policy(RetryPolicy::new().max_attempts(5))
+///        .max_concurrent_requests(Some(16))
+///        .max_concurrent_requests_per_server(Some(4))
 ///        .geoapi_servers(vec!["cvmfs-stratum-one.cern.ch", "cvmfs-stratum-one.ihep.ac.cn"])?;
 ///    
 ///     let server_results = scraper.validate()?.scrape().await;
@@ -69,6 +79,15 @@ pub struct Scraper<State = WithoutServers> {
     only_scrape_forced_repos: bool,
     ignored_repos: Vec<String>,
     geoapi_servers: Vec<Hostname>,
+    client_config: ClientConfig,
+    retry_policy: RetryPolicy,
+    max_concurrent_requests: Option<usize>,
+    max_concurrent_requests_per_server: Option<usize>,
+    fetch_whitelists: bool,
+    #[cfg(feature = "signatures")]
+    verify_signatures: bool,
+    fetcher: Option<Arc<dyn Fetcher>>,
+    client: Option<HttpClient>,
     _state: PhantomData<State>,
 }
 
@@ -91,6 +110,15 @@ impl Scraper<WithoutServers> {
             only_scrape_forced_repos: false,
             ignored_repos: Vec::new(),
             geoapi_servers: DEFAULT_GEOAPI_SERVERS.clone(),
+            client_config: ClientConfig::default(),
+            retry_policy: RetryPolicy::default(),
+            max_concurrent_requests: None,
+            max_concurrent_requests_per_server: Some(DEFAULT_MAX_CONCURRENT_REQUESTS_PER_SERVER),
+            fetch_whitelists: false,
+            #[cfg(feature = "signatures")]
+            verify_signatures: false,
+            fetcher: None,
+            client: None,
             _state: PhantomData,
         }
     }
@@ -106,6 +134,15 @@ impl Scraper<WithoutServers> {
             only_scrape_forced_repos: self.only_scrape_forced_repos,
             ignored_repos: self.ignored_repos,
             geoapi_servers: self.geoapi_servers,
+            client_config: self.client_config,
+            retry_policy: self.retry_policy,
+            max_concurrent_requests: self.max_concurrent_requests,
+            max_concurrent_requests_per_server: self.max_concurrent_requests_per_server,
+            fetch_whitelists: self.fetch_whitelists,
+            #[cfg(feature = "signatures")]
+            verify_signatures: self.verify_signatures,
+            fetcher: self.fetcher,
+            client: self.client,
             _state: PhantomData,
         }
     }
@@ -185,6 +222,75 @@ pub trait ScraperCommon {
         Hostname: TryFrom<S>,
         <Hostname as TryFrom<S>>::Error: Into<HostnameError>,
         Self: Sized;
+
+    /// Set the configuration for the HTTP client used by the scraper.
+    ///
+    /// The client is built when the scraper is validated and is shared by every fetch
+    /// performed during a scrape. Defaults to `ClientConfig::default()`.
+    fn client_config(self, config: ClientConfig) -> Self
+    where
+        Self: Sized;
+
+    /// Set the retry policy for fetches.
+    ///
+    /// Transient failures (by default connect errors, timeouts and 5xx responses) are retried
+    /// with exponential backoff. Every attempt is recorded in the `fetches` field of the scraped
+    /// servers and repositories. Defaults to `RetryPolicy::default()`.
+    fn retry_policy(self, policy: RetryPolicy) -> Self
+    where
+        Self: Sized;
+
+    /// Limit the number of requests in flight across all servers.
+    ///
+    /// The limit is shared by every scrape performed by the validated scraper. None (the default)
+    /// means no global limit. A limit of zero is treated as one.
+    fn max_concurrent_requests(self, limit: Option<usize>) -> Self
+    where
+        Self: Sized;
+
+    /// Limit the number of requests in flight against a single server.
+    ///
+    /// Repositories on a server are scraped concurrently, this bounds how many requests a single
+    /// server sees at once. Defaults to `DEFAULT_MAX_CONCURRENT_REQUESTS_PER_SERVER`, None means
+    /// no limit. A limit of zero is treated as one.
+    fn max_concurrent_requests_per_server(self, limit: Option<usize>) -> Self
+    where
+        Self: Sized;
+
+    /// Set whether to fetch the whitelist of each repository.
+    ///
+    /// If set to true, .cvmfswhitelist is fetched for every repository and stored in the
+    /// `whitelist` field of the populated repositories. With the `signatures` feature, the
+    /// certificate of the manifest is also fetched and checked against the whitelist, see the
+    /// `whitelist_verification` field. A missing or malformed whitelist does not fail the
+    /// repository. Defaults to false.
+    fn fetch_whitelists(self, fetch: bool) -> Self
+    where
+        Self: Sized;
+
+    /// Set whether to verify the signatures of repository manifests.
+    ///
+    /// If set to true, the signing certificate of each repository is fetched from the data area
+    /// of the server, and the manifest hash, certificate hash and signature are verified. The
+    /// result is stored in the `signature` field of the populated repositories, a failed
+    /// verification does not fail the repository. Defaults to false.
+    ///
+    /// This method requires the `signatures` feature.
+    #[cfg(feature = "signatures")]
+    fn verify_signatures(self, verify: bool) -> Self
+    where
+        Self: Sized;
+
+    /// Set the fetcher used to fetch files from the servers.
+    ///
+    /// Defaults to a `ReqwestFetcher` built from the client configuration. Use a
+    /// `DirectoryFetcher` to scrape a local mirror of the servers, e.g. in CI or against a
+    /// captured snapshot. The client configuration is ignored when a fetcher is set, the retry
+    /// policy and the limits on in-flight requests still apply.
+    fn fetcher<F>(self, fetcher: F) -> Self
+    where
+        F: Fetcher + 'static,
+        Self: Sized;
 }
 
 // Implement common functionality for WithoutServers state
@@ -232,6 +338,45 @@ impl ScraperCommon for Scraper<WithoutServers> {
             .collect::<Result<Vec<_>, _>>()?;
         Ok(self)
     }
+
+    fn client_config(mut self, config: ClientConfig) -> Self {
+        self.client_config = config;
+        self
+    }
+
+    fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    fn max_concurrent_requests(mut self, limit: Option<usize>) -> Self {
+        self.max_concurrent_requests = limit;
+        self
+    }
+
+    fn max_concurrent_requests_per_server(mut self, limit: Option<usize>) -> Self {
+        self.max_concurrent_requests_per_server = limit;
+        self
+    }
+
+    fn fetch_whitelists(mut self, fetch: bool) -> Self {
+        self.fetch_whitelists = fetch;
+        self
+    }
+
+    #[cfg(feature = "signatures")]
+    fn verify_signatures(mut self, verify: bool) -> Self {
+        self.verify_signatures = verify;
+        self
+    }
+
+    fn fetcher<F>(mut self, fetcher: F) -> Self
+    where
+        F: Fetcher + 'static,
+    {
+        self.fetcher = Some(Arc::new(fetcher));
+        self
+    }
 }
 
 // Implement common functionality for WithServers state
@@ -279,6 +424,45 @@ impl ScraperCommon for Scraper<WithServers> {
             .collect::<Result<Vec<_>, _>>()?;
         Ok(self)
     }
+
+    fn client_config(mut self, config: ClientConfig) -> Self {
+        self.client_config = config;
+        self
+    }
+
+    fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    fn max_concurrent_requests(mut self, limit: Option<usize>) -> Self {
+        self.max_concurrent_requests = limit;
+        self
+    }
+
+    fn max_concurrent_requests_per_server(mut self, limit: Option<usize>) -> Self {
+        self.max_concurrent_requests_per_server = limit;
+        self
+    }
+
+    fn fetch_whitelists(mut self, fetch: bool) -> Self {
+        self.fetch_whitelists = fetch;
+        self
+    }
+
+    #[cfg(feature = "signatures")]
+    fn verify_signatures(mut self, verify: bool) -> Self {
+        self.verify_signatures = verify;
+        self
+    }
+
+    fn fetcher<F>(mut self, fetcher: F) -> Self
+    where
+        F: Fetcher + 'static,
+    {
+        self.fetcher = Some(Arc::new(fetcher));
+        self
+    }
 }
 
 // Implementation for WithServers state
@@ -291,6 +475,8 @@ impl Scraper<WithServers> {
     ///
     /// The checks performed are:
     /// - If any servers use the S3 backend, the forced repositories list cannot be empty.
+    /// - The HTTP client configuration must be valid (e.g. a valid proxy URL and headers), unless
+    ///   a fetcher has been set.
     pub fn validate(self) -> Result<Scraper<ValidatedAndReady>, ScrapeError> {
         if self
             .servers
@@ -305,12 +491,29 @@ impl Scraper<WithServers> {
                     .to_string(),
             ));
         }
+        let client = match &self.fetcher {
+            Some(fetcher) => HttpClient::new(fetcher.clone(), self.retry_policy.clone()),
+            None => HttpClient::from_config(&self.client_config, self.retry_policy.clone())?,
+        }
+        .with_limits(
+            self.max_concurrent_requests,
+            self.max_concurrent_requests_per_server,
+        );
         Ok(Scraper {
             servers: self.servers,
             forced_repos: self.forced_repos,
             only_scrape_forced_repos: self.only_scrape_forced_repos,
             ignored_repos: self.ignored_repos,
             geoapi_servers: self.geoapi_servers,
+            client_config: self.client_config,
+            retry_policy: self.retry_policy,
+            max_concurrent_requests: self.max_concurrent_requests,
+            max_concurrent_requests_per_server: self.max_concurrent_requests_per_server,
+            fetch_whitelists: self.fetch_whitelists,
+            #[cfg(feature = "signatures")]
+            verify_signatures: self.verify_signatures,
+            fetcher: self.fetcher,
+            client: Some(client),
             _state: PhantomData,
         })
     }
@@ -327,6 +530,8 @@ impl Scraper<ValidatedAndReady> {
     pub async fn scrape(&self) -> Vec<ScrapedServer> {
         let servers = self.servers.as_ref().unwrap();
         scrape_servers(
+            self.client.as_ref().unwrap(),
+            &self.scrape_options(),
             servers.clone(),
             self.forced_repos.clone(),
             self.ignored_repos.clone(),
@@ -335,12 +540,129 @@ impl Scraper<ValidatedAndReady> {
         )
         .await
     }
+
+    /// Scrape the servers, yielding each result as soon as it is available.
+    ///
+    /// Unlike `scrape`, which returns once every server has been scraped, this method returns a
+    /// stream that yields a ScrapedServer for each server as soon as its scrape completes. The
+    /// results are yielded in completion order, not in the order the servers were added, so a
+    /// slow or timing out server does not hold back the results of the others.
+    ///
+    /// ### Example
+    ///
+    /// ```no_run
+    /// use futures::StreamExt;
+    /// use cvmfs_server_scraper::{Scraper, ScraperCommon, Hostname, Server, ServerType,
+    ///     ServerBackendType};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let scraper = Scraper::new()
+    ///         .with_servers(vec![Server::new(
+    ///             ServerType::Stratum1,
+    ///             ServerBackendType::CVMFS,
+    ///             Hostname::try_from("aws-eu-central-s1.eessi.science")?,
+    ///         )])
+    ///         .validate()?;
+    ///
+    ///     let mut results = scraper.scrape_stream();
+    ///     while let Some(server) = results.next().await {
+    ///         println!("{} scraped, ok: {}", server.hostname(), server.is_ok());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn scrape_stream(&self) -> impl Stream<Item = ScrapedServer> + '_ {
+        self.servers
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|server| self.scrape_server(server))
+            .collect::<FuturesUnordered<_>>()
+    }
+
+    /// The servers to scrape.
+    #[cfg(feature = "watch")]
+    pub(crate) fn servers(&self) -> &[Server] {
+        self.servers.as_ref().unwrap()
+    }
+
+    /// Scrape a single server with the settings of the scraper.
+    pub(crate) async fn scrape_server(&self, server: &Server) -> ScrapedServer {
+        let scraped = server
+            .scrape_with_client(
+                self.client.as_ref().unwrap(),
+                &self.scrape_options(),
+                self.forced_repos.clone(),
+                self.ignored_repos.clone(),
+                self.only_scrape_forced_repos,
+                Some(geoapi_servers_or_default(self.geoapi_servers.clone())),
+            )
+            .await;
+        log_scraped_server(&scraped);
+        scraped
+    }
+}
+
+impl<State> Scraper<State> {
+    /// The HTTP fetcher built from the client configuration of the scraper.
+    ///
+    /// This is the fetcher the scraper uses if no other fetcher is set. Use it to wrap the
+    /// scraper's own requests, e.g. in a `RecordingFetcher`, without losing the timeouts, proxy
+    /// and headers that were configured. Fails if the client configuration is invalid.
+    pub fn http_fetcher(&self) -> Result<ReqwestFetcher, ScrapeError> {
+        ReqwestFetcher::new(&self.client_config)
+    }
+
+    fn scrape_options(&self) -> ScrapeOptions {
+        ScrapeOptions {
+            fetch_whitelists: self.fetch_whitelists,
+            #[cfg(feature = "signatures")]
+            verify_signatures: self.verify_signatures,
+            #[cfg(feature = "signatures")]
+            scrape_time: self
+                .fetcher
+                .as_ref()
+                .and_then(|fetcher| fetcher.scrape_time())
+                .unwrap_or_else(chrono::Utc::now),
+        }
+    }
+}
+
+fn geoapi_servers_or_default(geoapi_hosts: Vec<Hostname>) -> Vec<Hostname> {
+    if geoapi_hosts.is_empty() {
+        debug!("No geoapi servers provided to scrape_server, using default servers");
+        DEFAULT_GEOAPI_SERVERS.clone()
+    } else {
+        geoapi_hosts
+    }
+}
+
+fn log_scraped_server(server: &ScrapedServer) {
+    match server {
+        ScrapedServer::Populated(popserver) => {
+            info!(
+                "Scraped server: {} with {} repositories ({} failed)",
+                popserver.hostname,
+                popserver.repositories.len(),
+                popserver.failed_repositories.len()
+            );
+        }
+        ScrapedServer::Failed(failedserver) => {
+            warn!(
+                "Scraping failed for server: {} with error: {}",
+                failedserver.hostname, failedserver.error
+            );
+        }
+    }
 }
 
 /// Scrape a list of servers in parallel.
 ///
 /// This function scrapes a list of servers in parallel and returns a list of ScrapedServer objects,
 async fn scrape_servers<R>(
+    client: &HttpClient,
+    options: &ScrapeOptions,
     servers: Vec<Server>,
     scrape_repos: Vec<R>,
     ignored_repos: Vec<R>,
@@ -350,12 +672,7 @@ async fn scrape_servers<R>(
 where
     R: AsRef<str> + Debug + std::fmt::Display + Clone,
 {
-    let geoapi_servers = if geoapi_hosts.is_empty() {
-        debug!("No geoapi servers provided to scrape_server, using default servers");
-        DEFAULT_GEOAPI_SERVERS.clone()
-    } else {
-        geoapi_hosts
-    };
+    let geoapi_servers = geoapi_servers_or_default(geoapi_hosts);
 
     let start = Instant::now();
     let scrapes_attempted = servers.len();
@@ -373,7 +690,9 @@ where
         let geoapi_servers = geoapi_servers.clone();
         async move {
             server
-                .scrape(
+                .scrape_with_client(
+                    client,
+                    options,
                     repolist.clone(),
                     ignore.clone(),
                     only_scrape_forced_repos,
@@ -386,21 +705,7 @@ where
     let scraped_servers = join_all(futures).await;
 
     for server in scraped_servers.iter() {
-        match server {
-            ScrapedServer::Populated(popserver) => {
-                info!(
-                    "Scraped server: {} with {} repositories",
-                    popserver.hostname,
-                    popserver.repositories.len()
-                );
-            }
-            ScrapedServer::Failed(failedserver) => {
-                warn!(
-                    "Scraping failed for server: {} with error: {}",
-                    failedserver.hostname, failedserver.error
-                );
-            }
-        }
+        log_scraped_server(server);
     }
 
     info!(
@@ -419,7 +724,8 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::{Hostname, Server, ServerBackendType, ServerType};
+    use crate::mock::{MockServer, MockServerConfig};
+    use crate::models::{Hostname, Server, ServerAddress, ServerBackendType, ServerType};
 
     #[tokio::test]
     async fn test_online_cvmfs_servers_using_scrape_servers() {
@@ -442,7 +748,18 @@ mod tests {
         ];
 
         let repolist = vec!["software.eessi.io", "dev.eessi.io", "riscv.eessi.io"];
-        let results = scrape_servers(servers, repolist.clone(), vec![], false, vec![]).await;
+        let client =
+            HttpClient::from_config(&ClientConfig::default(), RetryPolicy::default()).unwrap();
+        let results = scrape_servers(
+            &client,
+            &ScrapeOptions::default(),
+            servers,
+            repolist.clone(),
+            vec![],
+            false,
+            vec![],
+        )
+        .await;
 
         for result in results {
             match result {
@@ -457,4 +774,234 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn test_offline_cvmfs_servers_using_scrape_servers() {
+        let repolist = vec!["software.eessi.io", "dev.eessi.io", "riscv.eessi.io"];
+        let stratum1 = MockServer::start(
+            MockServerConfig::new(ServerType::Stratum1).repositories(repolist.clone()),
+        )
+        .await
+        .unwrap();
+        let sync_server = MockServer::start(
+            MockServerConfig::new(ServerType::SyncServer)
+                .backend_type(ServerBackendType::S3)
+                .repositories(repolist.clone()),
+        )
+        .await
+        .unwrap();
+        let servers = vec![
+            stratum1.server(ServerType::Stratum1, ServerBackendType::CVMFS),
+            sync_server.server(ServerType::SyncServer, ServerBackendType::S3),
+        ];
+
+        let client =
+            HttpClient::from_config(&ClientConfig::default(), RetryPolicy::default()).unwrap();
+        let results = scrape_servers(
+            &client,
+            &ScrapeOptions::default(),
+            servers,
+            repolist.clone(),
+            vec![],
+            false,
+            vec![],
+        )
+        .await;
+
+        assert_eq!(results.len(), 2);
+        for result in results {
+            let popserver = result.get_populated_server().unwrap();
+            for repo in repolist.clone() {
+                assert!(popserver.has_repository(repo));
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_with_invalid_client_config() {
+        let result = Scraper::new()
+            .client_config(ClientConfig::new().proxy("not a proxy url"))
+            .with_servers(vec![Server::new(
+                ServerType::Stratum1,
+                ServerBackendType::CVMFS,
+                Hostname::try_from("aws-eu-central-s1.eessi.science").unwrap(),
+            )])
+            .validate();
+        assert!(matches!(result, Err(ScrapeError::ClientConfigError(_))));
+    }
+
+    #[test]
+    fn test_http_fetcher_uses_client_config() {
+        let scraper = Scraper::new().client_config(ClientConfig::new().proxy("not a proxy url"));
+        assert!(matches!(
+            scraper.http_fetcher(),
+            Err(ScrapeError::ClientConfigError(_))
+        ));
+        assert!(Scraper::new().http_fetcher().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_stream_yields_every_server() {
+        use futures::StreamExt;
+
+        // One server is the mock, the other a port that was bound and released again, so it
+        // fails quickly. Each must still be yielded by the stream.
+        let mock = MockServer::start(
+            MockServerConfig::new(ServerType::Stratum1).repositories(["software.eessi.io"]),
+        )
+        .await
+        .unwrap();
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let scraper = Scraper::new()
+            .retry_policy(RetryPolicy::no_retries())
+            .with_servers(vec![
+                mock.server(ServerType::Stratum1, ServerBackendType::CVMFS),
+                Server::from_address(
+                    ServerType::Stratum0,
+                    ServerBackendType::CVMFS,
+                    ServerAddress::from(closed.ip()),
+                )
+                .with_port(closed.port()),
+            ])
+            .validate()
+            .unwrap();
+
+        let results = scraper.scrape_stream().collect::<Vec<_>>().await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results.iter().filter(|s| s.is_ok()).count(), 1);
+        assert_eq!(results.iter().filter(|s| s.is_failed()).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_whitelists_are_only_fetched_when_enabled() {
+        for fetch_whitelists in [false, true] {
+            let mock = MockServer::start(
+                MockServerConfig::new(ServerType::Stratum1).repositories(["software.eessi.io"]),
+            )
+            .await
+            .unwrap();
+            let server = Scraper::new()
+                .fetch_whitelists(fetch_whitelists)
+                .with_servers(vec![
+                    mock.server(ServerType::Stratum1, ServerBackendType::CVMFS)
+                ])
+                .validate()
+                .unwrap()
+                .scrape()
+                .await
+                .remove(0)
+                .get_populated_server()
+                .unwrap();
+            let requests = mock.requests();
+            assert_eq!(server.repositories[0].whitelist.is_some(), fetch_whitelists);
+            assert_eq!(
+                requests.contains(&"/cvmfs/software.eessi.io/.cvmfswhitelist".to_string()),
+                fetch_whitelists
+            );
+            if !fetch_whitelists {
+                // Without a whitelist or signature verification, no certificate is fetched.
+                assert!(!requests.iter().any(|request| request.contains("/data/")));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scrape_directory_mirror() {
+        use crate::fetcher::DirectoryFetcher;
+
+        let mirror = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/mirror");
+        let scraper = Scraper::new()
+            .fetcher(DirectoryFetcher::new(mirror).per_host(true))
+            .fetch_whitelists(true);
+        #[cfg(feature = "signatures")]
+        let scraper = scraper.verify_signatures(true);
+        let scraper = scraper
+            .with_servers(vec![Server::new(
+                ServerType::Stratum1,
+                ServerBackendType::CVMFS,
+                Hostname::try_from("stratum1.example.org").unwrap(),
+            )])
+            .validate()
+            .unwrap();
+
+        let server = scraper
+            .scrape()
+            .await
+            .remove(0)
+            .get_populated_server()
+            .unwrap();
+        assert_eq!(server.repositories.len(), 2);
+        assert!(server.failed_repositories.is_empty());
+        assert_eq!(server.metadata.organisation, Some("EESSI".to_string()));
+        assert_eq!(server.geoapi.response, vec![2, 1, 3]);
+
+        let repository = |name: &str| {
+            server
+                .repositories
+                .iter()
+                .find(|repo| repo.name == name)
+                .unwrap()
+        };
+        let software = repository("software.eessi.io");
+        assert_eq!(software.revision(), 42);
+        #[cfg(feature = "signatures")]
+        assert!(software.signature.as_ref().unwrap().is_valid());
+        assert!(software.snapshot_marker.is_some());
+        assert_eq!(software.master_replica, Some(false));
+        let dev = repository("dev.eessi.io");
+        assert_eq!(dev.revision(), 17);
+        #[cfg(feature = "signatures")]
+        assert!(!dev.signature.as_ref().unwrap().is_valid());
+        assert_eq!(dev.whitelist.as_ref().unwrap().repository, "dev.eessi.io");
+    }
+
+    #[cfg(feature = "signatures")]
+    #[tokio::test]
+    async fn test_whitelist_is_checked_without_signature_verification() {
+        use crate::fetcher::DirectoryFetcher;
+        use crate::models::SignatureVerification;
+
+        let mirror = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/mirror");
+        let scraper = Scraper::new()
+            .fetcher(DirectoryFetcher::new(mirror).per_host(true))
+            .fetch_whitelists(true)
+            .with_servers(vec![Server::new(
+                ServerType::Stratum1,
+                ServerBackendType::CVMFS,
+                Hostname::try_from("stratum1.example.org").unwrap(),
+            )])
+            .validate()
+            .unwrap();
+
+        let server = scraper
+            .scrape()
+            .await
+            .remove(0)
+            .get_populated_server()
+            .unwrap();
+        let verification = |name: &str| {
+            let repository = server
+                .repositories
+                .iter()
+                .find(|repo| repo.name == name)
+                .unwrap();
+            assert_eq!(repository.signature, None);
+            repository.whitelist_verification.clone().unwrap()
+        };
+        // The fixture whitelists expired in 2024.
+        match verification("software.eessi.io") {
+            SignatureVerification::Invalid { reason } => {
+                assert!(reason.contains("expired"), "{}", reason)
+            }
+            other => panic!("Unexpected verification {:?}", other),
+        }
+        // The mirror has no certificate for dev.eessi.io.
+        assert!(matches!(
+            verification("dev.eessi.io"),
+            SignatureVerification::Unverified { .. }
+        ));
+    }
 }
@@ -0,0 +1,412 @@
+//! An HTTP status API and dashboard for continuously scraped servers.
+//!
+//! `StatusApi` serves the latest results of a `Watcher` as JSON and as a server-rendered HTML
+//! dashboard, while the watcher re-scrapes the servers in the background.
+//!
+//! This module requires the `http-api` feature.
+
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use crate::models::ScrapedServer;
+use crate::repository_view::RepositoryView;
+use crate::watch::Watcher;
+
+/// An HTTP server exposing the latest scrape results of a `Watcher`.
+///
+/// Endpoints:
+///
+/// - `/`: An HTML dashboard with a summary of every server, and the same details as
+///   `PopulatedServer::output` and `FailedServer::output` for each of them.
+/// - `/servers`: The latest result of every server, as a JSON list of `ScrapedServer` with the
+///   key of each server (see `Server::key`) added as `key`.
+/// - `/servers/{key}`: The latest result of the server with the given key, percent-encoded, in
+///   the same form.
+/// - `/repositories/{name}`: The repository across the latest results, as a `RepositoryServers`
+///   of the `RepositoryView`: every server serving it, and every other server as unavailable
+///   with the reason (missing, failed or server_failed).
+/// - `/health`: The number of configured, scraped and failed servers. Always returns 200 while
+///   the API is running.
+///
+/// Servers are serialized the same way in every endpoint, with the address as the `hostname`
+/// (including a pinned IP address, see `ServerAddress`). Servers that have not been scraped yet
+/// are not listed, and unknown servers and repositories return 404 with a JSON body of the form
+/// `{"error": "..."}`.
+///
+/// ### Example
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use cvmfs_server_scraper::{CancellationToken, Scraper, StatusApi, Watcher};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let scraper = Scraper::new().with_servers(vec![]).validate()?;
+///     let watcher = Watcher::new(scraper)?.interval(Duration::from_secs(300))?;
+///     let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
+///     StatusApi::new(Arc::new(watcher))
+///         .serve(listener, CancellationToken::new())
+///         .await?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct StatusApi {
+    watcher: Arc<Watcher>,
+}
+
+/// A scraped server with its key, so clients can look it up again.
+#[derive(Debug, Serialize)]
+struct KeyedServer<'a> {
+    key: String,
+    #[serde(flatten)]
+    server: &'a ScrapedServer,
+}
+
+impl<'a> From<&'a ScrapedServer> for KeyedServer<'a> {
+    fn from(server: &'a ScrapedServer) -> Self {
+        KeyedServer {
+            key: server.key(),
+            server,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Health {
+    status: &'static str,
+    servers: usize,
+    scraped: usize,
+    failed: usize,
+}
+
+impl StatusApi {
+    pub fn new(watcher: Arc<Watcher>) -> Self {
+        StatusApi { watcher }
+    }
+
+    /// An axum router serving the API, for embedding into an existing application.
+    ///
+    /// The router only serves the results, the watcher must be run separately.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/", get(dashboard))
+            .route("/servers", get(servers))
+            .route("/servers/{key}", get(server))
+            .route("/repositories/{name}", get(repository))
+            .route("/health", get(health))
+            .with_state(self.watcher.clone())
+    }
+
+    /// Run the watcher and serve the API on the given listener until the token is cancelled.
+    pub async fn serve(
+        self,
+        listener: tokio::net::TcpListener,
+        token: CancellationToken,
+    ) -> std::io::Result<()> {
+        let server = axum::serve(listener, self.router())
+            .with_graceful_shutdown(token.clone().cancelled_owned());
+        let (_, served) = tokio::join!(self.watcher.run(token), server);
+        served
+    }
+}
+
+fn json<T: Serialize>(status: StatusCode, value: &T) -> Response {
+    match serde_json::to_string(value) {
+        Ok(body) => (status, [(header::CONTENT_TYPE, "application/json")], body).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+fn not_found(message: String) -> Response {
+    json(
+        StatusCode::NOT_FOUND,
+        &serde_json::json!({ "error": message }),
+    )
+}
+
+async fn servers(State(watcher): State<Arc<Watcher>>) -> Response {
+    let latest = watcher.latest();
+    json(
+        StatusCode::OK,
+        &latest.iter().map(KeyedServer::from).collect::<Vec<_>>(),
+    )
+}
+
+async fn server(State(watcher): State<Arc<Watcher>>, Path(key): Path<String>) -> Response {
+    match watcher.latest_for(&key) {
+        Some(server) => json(StatusCode::OK, &KeyedServer::from(&server)),
+        None => not_found(format!("Server {} not found", key)),
+    }
+}
+
+async fn repository(State(watcher): State<Arc<Watcher>>, Path(name): Path<String>) -> Response {
+    let view = RepositoryView::new(&watcher.latest());
+    match view.repository(&name) {
+        Some(repository) => json(StatusCode::OK, repository),
+        None => not_found(format!("Repository {} not found", name)),
+    }
+}
+
+async fn health(State(watcher): State<Arc<Watcher>>) -> Response {
+    let latest = watcher.latest();
+    json(
+        StatusCode::OK,
+        &Health {
+            status: "ok",
+            servers: watcher.server_count(),
+            scraped: latest.len(),
+            failed: latest.iter().filter(|server| server.is_failed()).count(),
+        },
+    )
+}
+
+async fn dashboard(State(watcher): State<Arc<Watcher>>) -> Html<String> {
+    Html(render_dashboard(&watcher.latest()))
+}
+
+/// Render the dashboard for the given results.
+fn render_dashboard(servers: &[ScrapedServer]) -> String {
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+        "<meta http-equiv=\"refresh\" content=\"60\">\n",
+        "<title>CVMFS servers</title>\n",
+        "<style>body { font-family: sans-serif; } table { border-collapse: collapse; } ",
+        "td, th { border: 1px solid #ccc; padding: 2px 8px; text-align: left; } ",
+        ".failed { color: #b00; }</style>\n",
+        "</head>\n<body>\n<h1>CVMFS servers</h1>\n",
+    ));
+    if servers.is_empty() {
+        html.push_str("<p>No servers have been scraped yet.</p>\n");
+    } else {
+        html.push_str(concat!(
+            "<table>\n<tr><th>Server</th><th>Type</th><th>Status</th>",
+            "<th>Repositories</th><th>Failed repositories</th></tr>\n",
+        ));
+        for server in servers {
+            let (server_type, status, repositories, failed) = match server {
+                ScrapedServer::Populated(server) => (
+                    server.server_type,
+                    "ok".to_string(),
+                    server.repositories.len(),
+                    server.failed_repositories.len(),
+                ),
+                ScrapedServer::Failed(server) => (
+                    server.server_type,
+                    format!("failed: {}", server.error),
+                    0,
+                    0,
+                ),
+            };
+            html.push_str(&format!(
+                "<tr{}><td><a href=\"#{host}\">{host}</a></td><td>{:?}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                if server.is_failed() || failed > 0 { " class=\"failed\"" } else { "" },
+                server_type,
+                escape_html(&status),
+                repositories,
+                failed,
+                host = escape_html(&server.key()),
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+    for server in servers {
+        let mut details = String::new();
+        server
+            .write_output(&mut details)
+            .expect("Writing to a String cannot fail");
+        let host = escape_html(&server.key());
+        html.push_str(&format!(
+            "<h2 id=\"{host}\">{host}</h2>\n<pre>{}</pre>\n",
+            escape_html(&details),
+        ));
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockRepository, MockServer, MockServerConfig};
+    use crate::models::{Hostname, Server, ServerAddress, ServerBackendType, ServerType};
+    use crate::scraper::Scraper;
+    use std::time::Duration;
+
+    const REPO: &str = "software.eessi.io";
+
+    struct Running {
+        base_url: String,
+        key: String,
+        token: CancellationToken,
+        _mock: MockServer,
+    }
+
+    impl Drop for Running {
+        fn drop(&mut self) {
+            self.token.cancel();
+        }
+    }
+
+    /// Serve the API for a mock Stratum1 named s1.example.org, once it has been scraped.
+    async fn running() -> Running {
+        let mock = MockServer::start(
+            MockServerConfig::new(ServerType::Stratum1)
+                .repository(MockRepository::new(REPO).revision(42)),
+        )
+        .await
+        .unwrap();
+        let address = ServerAddress::pinned(
+            Hostname::try_from("s1.example.org").unwrap(),
+            mock.address().ip(),
+        );
+        let server = Server::from_address(ServerType::Stratum1, ServerBackendType::CVMFS, address)
+            .with_port(mock.address().port());
+        let key = server.key();
+        let scraper = Scraper::new()
+            .with_servers(vec![server])
+            .validate()
+            .unwrap();
+        let watcher = Arc::new(Watcher::new(scraper).unwrap());
+        let mut events = watcher.subscribe();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let token = CancellationToken::new();
+        tokio::spawn(StatusApi::new(watcher).serve(listener, token.clone()));
+        tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("The server was not scraped")
+            .unwrap();
+        Running {
+            base_url,
+            key,
+            token,
+            _mock: mock,
+        }
+    }
+
+    impl Running {
+        /// The URL of the given path segments, percent-encoded.
+        fn url(&self, segments: &[&str]) -> String {
+            let mut url = reqwest::Url::parse(&self.base_url).unwrap();
+            url.path_segments_mut().unwrap().clear().extend(segments);
+            url.to_string()
+        }
+    }
+
+    async fn get_json(url: String) -> (u16, serde_json::Value) {
+        let response = reqwest::get(url).await.unwrap();
+        let status = response.status().as_u16();
+        (status, response.json().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_servers() {
+        let api = running().await;
+        let (status, body) = get_json(format!("{}/servers", api.base_url)).await;
+        assert_eq!(status, 200);
+        // The mock is pinned to a local address, which is part of the serialized hostname.
+        assert_eq!(body[0]["hostname"], "s1.example.org@127.0.0.1");
+        assert_eq!(body[0]["key"], api.key.as_str());
+        assert_eq!(body[0]["status"], "populated");
+    }
+
+    #[tokio::test]
+    async fn test_servers_round_trip() {
+        let api = running().await;
+        let (_, list) = get_json(format!("{}/servers", api.base_url)).await;
+        let list = list.as_array().unwrap();
+        assert_eq!(list.len(), 1);
+        for listed in list {
+            let key = listed["key"].as_str().unwrap();
+            let (status, body) = get_json(api.url(&["servers", key])).await;
+            assert_eq!(status, 200, "Looking up {}", key);
+            assert_eq!(&body, listed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server() {
+        let api = running().await;
+        let (status, body) = get_json(api.url(&["servers", &api.key])).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["repositories"][0]["name"], REPO);
+
+        let (status, body) = get_json(api.url(&["servers", "s2.example.org"])).await;
+        assert_eq!(status, 404);
+        assert_eq!(body["error"], "Server s2.example.org not found");
+    }
+
+    #[tokio::test]
+    async fn test_repository() {
+        let api = running().await;
+        let (status, body) = get_json(format!("{}/repositories/{}", api.base_url, REPO)).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["name"], REPO);
+        assert_eq!(body["servers"][0]["hostname"], "s1.example.org@127.0.0.1");
+        assert_eq!(body["servers"][0]["server"], api.key.as_str());
+        assert_eq!(body["servers"][0]["revision"], 42);
+        assert_eq!(body["unavailable"], serde_json::json!([]));
+
+        let (status, _) = get_json(format!("{}/repositories/unknown.eessi.io", api.base_url)).await;
+        assert_eq!(status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let api = running().await;
+        let (status, body) = get_json(format!("{}/health", api.base_url)).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body,
+            serde_json::json!({ "status": "ok", "servers": 1, "scraped": 1, "failed": 0 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dashboard() {
+        let api = running().await;
+        let response = reqwest::get(format!("{}/", api.base_url)).await.unwrap();
+        assert!(response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        let body = response.text().await.unwrap();
+        let key = escape_html(&api.key);
+        assert!(body.contains(&format!("<h2 id=\"{key}\">{key}</h2>")));
+        assert!(body.contains("Manifest for repository: software.eessi.io"));
+        assert!(body.contains("Revision number: 42"));
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<a href=\"x\">&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Helpers for constructing scrape results in tests without scraping.
+
+use crate::constants::DEFAULT_BASE_PATH;
+use crate::errors::ScrapeError;
+use crate::models::{
+    GeoapiServerQuery, Manifest, MaybeRfc2822DateTime, PopulatedRepositoryOrReplica,
+    PopulatedServer, Scheme, ScrapedServer, Server, ServerBackendType, ServerMetadata, ServerType,
+    Whitelist,
+};
+
+/// A manifest for the given repository, revision and timestamp.
+pub fn manifest(name: &str, revision: i32, timestamp: i64) -> Manifest {
+    format!(
+        "C600230b0ba7620426f2e898f1e1f43c5466efe59\n\
+         B1391\n\
+         Rd41d8cd98f00b204e9800998ecf8427e\n\
+         D240\n\
+         S{}\n\
+         Gno\n\
+         Ano\n\
+         N{}\n\
+         X7f4a1a8b0bc0f3e9e8b0f8e4fb9c5e0cb0d7c0a1\n\
+         H1ee5fc3a2b7a7b5c1d3c4b9e5f8e7f6a5b4c3d2e\n\
+         T{}\n\
+         M5a8b2e0f0e6a4b1c9d8f7e6a5b4c3d2e1f0a9b8c\n\
+         Y4c3b2a1f0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b\n\
+         --\n",
+        revision, name, timestamp
+    )
+    .parse()
+    .unwrap()
+}
+
+/// A whitelist for the given repository, created 2024-06-17 and expiring 2024-07-17.
+pub fn whitelist(name: &str) -> Whitelist {
+    format!(
+        "20240617000003\n\
+         E20240717000003\n\
+         N{}\n\
+         00:B3:F6:1E:08:0D:88:C2:EA:02:22:9C:E2:18:E9:0F:09:24:50:69\n\
+         --\n",
+        name
+    )
+    .parse()
+    .unwrap()
+}
+
+/// A populated repository with the given revision and timestamp.
+pub fn repository(name: &str, revision: i32, timestamp: i64) -> PopulatedRepositoryOrReplica {
+    PopulatedRepositoryOrReplica {
+        name: name.to_string(),
+        manifest: manifest(name, revision, timestamp),
+        last_snapshot: Some(MaybeRfc2822DateTime(Some(
+            "Fri Jun 21 17:40:02 UTC 2024".to_string(),
+        ))),
+        last_gc: None,
+        signature: None,
+        whitelist: Some(whitelist(name)),
+        whitelist_verification: None,
+        snapshot_marker: None,
+        snapshot_in_progress: Some(false),
+        master_replica: Some(true),
+        fetches: Vec::new(),
+    }
+}
+
+/// A populated server with repositories given as (name, revision, timestamp).
+pub fn populated_server(
+    hostname: &str,
+    server_type: ServerType,
+    repositories: &[(&str, i32, i64)],
+) -> ScrapedServer {
+    ScrapedServer::Populated(PopulatedServer {
+        server_type,
+        backend_type: ServerBackendType::CVMFS,
+        backend_detected: ServerBackendType::CVMFS,
+        hostname: hostname.parse().unwrap(),
+        scheme: Scheme::default(),
+        port: None,
+        base_path: DEFAULT_BASE_PATH.to_string(),
+        repositories: repositories
+            .iter()
+            .map(|(name, revision, timestamp)| repository(name, *revision, *timestamp))
+            .collect(),
+        failed_repositories: Vec::new(),
+        metadata: ServerMetadata {
+            schema_version: Some(1),
+            cvmfs_version: Some(semver::Version::new(2, 11, 3)),
+            last_geodb_update: MaybeRfc2822DateTime(None),
+            os_version_id: None,
+            os_pretty_name: None,
+            os_id: None,
+            administrator: Some("admin".to_string()),
+            email: Some("admin@example.com".to_string()),
+            organisation: None,
+            custom: None,
+        },
+        meta_json_error: None,
+        geoapi: GeoapiServerQuery {
+            hostname: hostname.parse().unwrap(),
+            geoapi_hosts: Vec::new(),
+            response: Vec::new(),
+        },
+        fetches: Vec::new(),
+        scrape_duration: std::time::Duration::from_millis(250),
+    })
+}
+
+/// A server that failed with a server type mismatch.
+pub fn failed_server(hostname: &str) -> ScrapedServer {
+    let server = Server::new(
+        ServerType::Stratum1,
+        ServerBackendType::CVMFS,
+        hostname.parse().unwrap(),
+    );
+    ScrapedServer::Failed(server.to_failed_server(
+        ScrapeError::ServerTypeMismatch(format!("{} is not a Stratum1", hostname)).into(),
+    ))
+}
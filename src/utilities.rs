@@ -1,7 +1,6 @@
 use log::trace;
 use rand::distr::Alphanumeric;
 use rand::{rng, Rng};
-use reqwest::Client;
 use serde::de::DeserializeOwned;
 use std::collections::HashMap;
 use std::fmt::Display;
@@ -9,6 +8,7 @@ use std::num::ParseIntError;
 
 use chrono::{DateTime, Utc};
 
+use crate::client::HttpClient;
 use crate::errors::{ManifestError, ScrapeError};
 use crate::models::HexString;
 
@@ -63,34 +63,37 @@ pub fn parse_timestamp_field(
         })
 }
 
-pub async fn fetch_text<T>(client: &Client, url: T) -> Result<String, ScrapeError>
+pub async fn fetch_text<T>(client: &HttpClient, url: T) -> Result<String, ScrapeError>
 where
     T: Display,
 {
     trace!("Fetching text from {}", url);
-    let response = client
-        .get(url.to_string())
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
-    Ok(response)
+    Ok(client.get_text(&url.to_string()).await?)
 }
 
-pub async fn fetch_json<T, U>(client: &Client, url: T) -> Result<U, ScrapeError>
+/// Fetch an optional text file, returning None if the server responds with 404 Not Found.
+pub async fn fetch_optional_text<T>(
+    client: &HttpClient,
+    url: T,
+) -> Result<Option<String>, ScrapeError>
+where
+    T: Display,
+{
+    trace!("Fetching optional text from {}", url);
+    match client.get_text(&url.to_string()).await {
+        Ok(text) => Ok(Some(text)),
+        Err(error) if error.is_not_found() => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+pub async fn fetch_json<T, U>(client: &HttpClient, url: T) -> Result<U, ScrapeError>
 where
     T: Display,
     U: DeserializeOwned,
 {
     trace!("Fetching JSON from {}", url);
-    let response = client
-        .get(url.to_string())
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
+    let response = client.get_text(&url.to_string()).await?;
 
     let json: U = serde_json::from_str(&response)?;
     Ok(json)
@@ -0,0 +1,522 @@
+//! Continuous scraping on a schedule.
+//!
+//! `Watcher` re-scrapes every server of a validated `Scraper` on an interval, keeps the latest
+//! result per server, and publishes the results and the changes between them to subscribers.
+//!
+//! This module requires the `watch` feature.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use futures::future::join_all;
+use log::{debug, info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use crate::diff::{ChangeEvent, ScrapeDiff};
+use crate::errors::WatchError;
+use crate::models::{ScrapedServer, Server};
+use crate::scraper::{Scraper, ValidatedAndReady};
+
+/// The default interval between two scrapes of a server.
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The default number of events a subscriber may fall behind before it misses events.
+pub const DEFAULT_WATCH_CHANNEL_CAPACITY: usize = 1024;
+
+/// An event published by a `Watcher`.
+///
+/// Scraped: A server was scraped, with the result.
+/// Changed: Something changed on a server since its previous scrape, see `ChangeEvent`.
+///
+/// The events of a scrape are published in order: first `Scraped`, then any `Changed` events.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum WatchEvent {
+    Scraped(ScrapedServer),
+    Changed(ChangeEvent),
+}
+
+/// A long-running scraper that re-scrapes its servers on a schedule.
+///
+/// Every server is scraped on its own schedule, the default `interval()` or the one given with
+/// `server_interval()`, with a random delay of up to `jitter()` added before each scrape so that
+/// the requests of many servers (or many watchers) are spread out. If a scrape takes longer than
+/// the interval, the missed scrapes are skipped rather than run back to back, and a server is
+/// never scraped twice at the same time.
+///
+/// The latest result of every server is kept in memory (`latest()`), and every result and every
+/// change since the previous result of the server is published to the subscribers
+/// (`subscribe()`). The first scrape of a server publishes no changes. Subscribers that fall
+/// more than `channel_capacity()` events behind miss the oldest events, see
+/// `tokio::sync::broadcast`.
+///
+/// Results are kept and published per server key (see `Server::key`), so building a watcher for
+/// servers with duplicate keys fails. Intervals and the channel capacity must be greater than
+/// zero, and per-server intervals must name a watched server, the setters return a `WatchError`
+/// otherwise.
+///
+/// `run()` returns once the cancellation token is cancelled. Scrapes in flight are abandoned.
+///
+/// ### Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use cvmfs_server_scraper::{CancellationToken, Scraper, WatchEvent, Watcher};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let scraper = Scraper::new().with_servers(vec![]).validate()?;
+///     let watcher = Arc::new(
+///         Watcher::new(scraper)?
+///             .interval(Duration::from_secs(300))?
+///             .jitter(Duration::from_secs(30)),
+///     );
+///
+///     let mut events = watcher.subscribe();
+///     tokio::spawn(async move {
+///         while let Ok(event) = events.recv().await {
+///             if let WatchEvent::Changed(change) = event {
+///                 println!("{}", change);
+///             }
+///         }
+///     });
+///
+///     let token = CancellationToken::new();
+///     let handle = tokio::spawn({
+///         let watcher = watcher.clone();
+///         let token = token.clone();
+///         async move { watcher.run(token).await }
+///     });
+///     token.cancel();
+///     handle.await?;
+///     Ok(())
+/// }
+/// ```
+pub struct Watcher {
+    scraper: Scraper<ValidatedAndReady>,
+    interval: Duration,
+    jitter: Duration,
+    server_intervals: HashMap<String, Duration>,
+    latest: RwLock<BTreeMap<String, ScrapedServer>>,
+    sender: broadcast::Sender<WatchEvent>,
+}
+
+impl Watcher {
+    /// Create a watcher for the servers of the given scraper.
+    ///
+    /// By default every server is scraped every `DEFAULT_WATCH_INTERVAL`, without jitter.
+    ///
+    /// Fails if two servers of the scraper have the same key.
+    pub fn new(scraper: Scraper<ValidatedAndReady>) -> Result<Self, WatchError> {
+        let mut keys = HashSet::new();
+        for server in scraper.servers() {
+            let key = server.key();
+            if !keys.insert(key.clone()) {
+                return Err(WatchError::DuplicateServer(key));
+            }
+        }
+        let (sender, _) = broadcast::channel(DEFAULT_WATCH_CHANNEL_CAPACITY);
+        Ok(Watcher {
+            scraper,
+            interval: DEFAULT_WATCH_INTERVAL,
+            jitter: Duration::ZERO,
+            server_intervals: HashMap::new(),
+            latest: RwLock::new(BTreeMap::new()),
+            sender,
+        })
+    }
+
+    /// Set the interval between two scrapes of a server.
+    ///
+    /// Fails if the interval is zero.
+    pub fn interval(mut self, interval: Duration) -> Result<Self, WatchError> {
+        if interval.is_zero() {
+            return Err(WatchError::ZeroInterval);
+        }
+        self.interval = interval;
+        Ok(self)
+    }
+
+    /// Set the maximum random delay added before each scrape.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the interval for the server with the given key (see `Server::key`), overriding
+    /// `interval()`.
+    ///
+    /// Fails if no watched server has the key, or if the interval is zero.
+    pub fn server_interval<S: ToString>(
+        mut self,
+        key: S,
+        interval: Duration,
+    ) -> Result<Self, WatchError> {
+        let key = key.to_string();
+        if !self
+            .scraper
+            .servers()
+            .iter()
+            .any(|server| server.key() == key)
+        {
+            return Err(WatchError::UnknownServer(key));
+        }
+        if interval.is_zero() {
+            return Err(WatchError::ZeroInterval);
+        }
+        self.server_intervals.insert(key, interval);
+        Ok(self)
+    }
+
+    /// Set the number of events a subscriber may fall behind before it misses events.
+    ///
+    /// Subscribers created before this call are disconnected. Fails if the capacity is zero.
+    pub fn channel_capacity(mut self, capacity: usize) -> Result<Self, WatchError> {
+        if capacity == 0 {
+            return Err(WatchError::ZeroChannelCapacity);
+        }
+        self.sender = broadcast::channel(capacity).0;
+        Ok(self)
+    }
+
+    /// Subscribe to the results and changes published by the watcher.
+    pub fn subscribe(&self) -> broadcast::Receiver<WatchEvent> {
+        self.sender.subscribe()
+    }
+
+    /// The number of servers watched.
+    pub fn server_count(&self) -> usize {
+        self.scraper.servers().len()
+    }
+
+    /// The latest result of every server scraped so far, sorted by key.
+    pub fn latest(&self) -> Vec<ScrapedServer> {
+        self.latest.read().unwrap().values().cloned().collect()
+    }
+
+    /// The latest result of the server with the given key (see `Server::key`), if it has been
+    /// scraped.
+    pub fn latest_for<S: ToString>(&self, key: S) -> Option<ScrapedServer> {
+        self.latest.read().unwrap().get(&key.to_string()).cloned()
+    }
+
+    /// Scrape the servers on their schedules until the token is cancelled.
+    ///
+    /// The first scrape of every server starts right away (after the jitter).
+    pub async fn run(&self, token: CancellationToken) {
+        info!(
+            "Watching {} servers (interval {:?}, jitter {:?})",
+            self.scraper.servers().len(),
+            self.interval,
+            self.jitter
+        );
+        join_all(
+            self.scraper
+                .servers()
+                .iter()
+                .map(|server| self.watch_server(server, &token)),
+        )
+        .await;
+        info!("Stopped watching");
+    }
+
+    async fn watch_server(&self, server: &Server, token: &CancellationToken) {
+        let key = server.key();
+        let period = self
+            .server_intervals
+            .get(&key)
+            .copied()
+            .unwrap_or(self.interval);
+        let mut ticks = tokio::time::interval(period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            let scheduled = tokio::select! {
+                _ = token.cancelled() => break,
+                scheduled = ticks.tick() => scheduled,
+            };
+            let jitter = self.random_jitter();
+            let scraped = tokio::select! {
+                _ = token.cancelled() => break,
+                scraped = async {
+                    tokio::time::sleep(jitter).await;
+                    self.scraper.scrape_server(server).await
+                } => scraped,
+            };
+            let elapsed = scheduled.elapsed();
+            if elapsed > period {
+                warn!(
+                    "Scraping {} took {:?}, longer than its interval of {:?}, skipping missed scrapes",
+                    key, elapsed, period
+                );
+            }
+            self.publish(key.clone(), scraped);
+        }
+        debug!("Stopped watching {}", key);
+    }
+
+    fn random_jitter(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        rand::rng().random_range(Duration::ZERO..=self.jitter)
+    }
+
+    fn publish(&self, key: String, scraped: ScrapedServer) {
+        let previous = self.latest.write().unwrap().insert(key, scraped.clone());
+        let changes = match &previous {
+            Some(previous) => {
+                ScrapeDiff::new(
+                    std::slice::from_ref(previous),
+                    std::slice::from_ref(&scraped),
+                )
+                .events
+            }
+            None => Vec::new(),
+        };
+        // Sending only fails if there are no subscribers, which is fine.
+        let _ = self.sender.send(WatchEvent::Scraped(scraped));
+        for change in changes {
+            let _ = self.sender.send(WatchEvent::Changed(change));
+        }
+    }
+}
+
+impl std::fmt::Debug for Watcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Watcher")
+            .field("interval", &self.interval)
+            .field("jitter", &self.jitter)
+            .field("server_intervals", &self.server_intervals)
+            .field("subscribers", &self.sender.receiver_count())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{
+        MockFault, MockFetcher, MockFile, MockRepository, MockServer, MockServerConfig,
+    };
+    use crate::models::{Hostname, ServerAddress, ServerBackendType, ServerType};
+    use crate::scraper::ScraperCommon;
+    use std::sync::Arc;
+
+    const REPO: &str = "software.eessi.io";
+
+    async fn mock(revision: i32) -> MockServer {
+        MockServer::start(
+            MockServerConfig::new(ServerType::Stratum1)
+                .repository(MockRepository::new(REPO).revision(revision)),
+        )
+        .await
+        .unwrap()
+    }
+
+    /// A server for the mock, with the given name pinned to the address of the mock.
+    fn server(name: &str, mock: &MockServer) -> Server {
+        let address = ServerAddress::pinned(Hostname::try_from(name).unwrap(), mock.address().ip());
+        Server::from_address(ServerType::Stratum1, ServerBackendType::CVMFS, address)
+            .with_port(mock.address().port())
+    }
+
+    /// A watcher for the given mocks, each with its own name.
+    fn watcher(mocks: &[(&str, &MockServer)]) -> Watcher {
+        let servers = mocks
+            .iter()
+            .map(|(name, mock)| server(name, mock))
+            .collect();
+        let scraper = Scraper::new().with_servers(servers).validate().unwrap();
+        Watcher::new(scraper)
+            .unwrap()
+            .interval(Duration::from_millis(50))
+            .unwrap()
+    }
+
+    /// A scraper for servers with the given names, all served in-process by the fetcher.
+    fn scraper(names: &[&str], fetcher: MockFetcher) -> Scraper<ValidatedAndReady> {
+        let servers = names
+            .iter()
+            .map(|name| {
+                Server::new(
+                    ServerType::Stratum1,
+                    ServerBackendType::CVMFS,
+                    Hostname::try_from(*name).unwrap(),
+                )
+            })
+            .collect();
+        Scraper::new()
+            .fetcher(fetcher)
+            .with_servers(servers)
+            .validate()
+            .unwrap()
+    }
+
+    fn repositories_json_requests(fetcher: &MockFetcher, name: &str) -> usize {
+        fetcher
+            .requests()
+            .iter()
+            .filter(|url| url == &&format!("http://{}/cvmfs/info/v1/repositories.json", name))
+            .count()
+    }
+
+    #[test]
+    fn test_watcher_rejects_invalid_settings() {
+        let fetcher = MockFetcher::new(MockServerConfig::new(ServerType::Stratum1));
+        let watcher = || Watcher::new(scraper(&["s1.example.org"], fetcher.clone())).unwrap();
+        let second = Duration::from_secs(1);
+
+        assert!(matches!(
+            watcher().interval(Duration::ZERO),
+            Err(WatchError::ZeroInterval)
+        ));
+        assert!(matches!(
+            watcher().server_interval("s1.example.org", Duration::ZERO),
+            Err(WatchError::ZeroInterval)
+        ));
+        assert!(matches!(
+            watcher().server_interval("s2.example.org", second),
+            Err(WatchError::UnknownServer(key)) if key == "s2.example.org"
+        ));
+        assert!(matches!(
+            watcher().channel_capacity(0),
+            Err(WatchError::ZeroChannelCapacity)
+        ));
+        assert!(watcher()
+            .interval(second)
+            .and_then(|watcher| watcher.server_interval("s1.example.org", second))
+            .and_then(|watcher| watcher.channel_capacity(1))
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_watcher_rejects_duplicate_servers() {
+        let mock = mock(1).await;
+        let servers = vec![
+            server("s1.example.org", &mock),
+            server("s2.example.org", &mock),
+            server("s1.example.org", &mock),
+        ];
+        let scraper = Scraper::new().with_servers(servers).validate().unwrap();
+        match Watcher::new(scraper) {
+            Err(WatchError::DuplicateServer(key)) => {
+                assert_eq!(key, server("s1.example.org", &mock).key())
+            }
+            other => panic!("Expected a duplicate server error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watcher_publishes_results_and_changes() {
+        let mock = mock(1).await;
+        let watcher = Arc::new(watcher(&[("s1.example.org", &mock)]));
+        let mut events = watcher.subscribe();
+        let token = CancellationToken::new();
+        let handle = tokio::spawn({
+            let (watcher, token) = (watcher.clone(), token.clone());
+            async move { watcher.run(token).await }
+        });
+
+        let WatchEvent::Scraped(first) = events.recv().await.unwrap() else {
+            panic!("Expected a scrape result first");
+        };
+        assert!(first.is_ok());
+        assert!(watcher
+            .latest_for(server("s1.example.org", &mock).key())
+            .is_some());
+
+        mock.update(|config| config.repository_mut(REPO).unwrap().revision = 2);
+        let change = loop {
+            if let WatchEvent::Changed(change) = events.recv().await.unwrap() {
+                break change;
+            }
+        };
+        assert_eq!(
+            change,
+            ChangeEvent::RevisionChanged {
+                server: first.key(),
+                repository: REPO.to_string(),
+                previous: 1,
+                current: 2,
+            }
+        );
+
+        token.cancel();
+        tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("The watcher did not stop")
+            .unwrap();
+        assert_eq!(watcher.latest().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_watcher_uses_per_server_intervals() {
+        let fetcher =
+            MockFetcher::new(MockServerConfig::new(ServerType::Stratum1).repositories([REPO]));
+        let watcher = Watcher::new(scraper(
+            &["fast.example.org", "slow.example.org"],
+            fetcher.clone(),
+        ))
+        .unwrap()
+        .interval(Duration::from_millis(50))
+        .unwrap()
+        .server_interval("slow.example.org", Duration::from_secs(60))
+        .unwrap()
+        .jitter(Duration::from_millis(10));
+        let token = CancellationToken::new();
+        // The clock is paused, so the scrapes take no time and the fast server is scraped at
+        // 0, 50, ..., 1500ms.
+        tokio::select! {
+            _ = watcher.run(token.clone()) => panic!("The watcher stopped on its own"),
+            _ = tokio::time::sleep(Duration::from_millis(1525)) => token.cancel(),
+        }
+        assert_eq!(repositories_json_requests(&fetcher, "fast.example.org"), 31);
+        assert_eq!(repositories_json_requests(&fetcher, "slow.example.org"), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_watcher_skips_scrapes_while_one_is_running() {
+        // Every scrape takes 200ms, four times the interval.
+        let fetcher = MockFetcher::new(
+            MockServerConfig::new(ServerType::Stratum1)
+                .repositories([REPO])
+                .fault(
+                    MockFile::RepositoriesJson,
+                    MockFault::Delay(Duration::from_millis(200)),
+                ),
+        );
+        let watcher = Watcher::new(scraper(&["s1.example.org"], fetcher.clone()))
+            .unwrap()
+            .interval(Duration::from_millis(50))
+            .unwrap();
+        let token = CancellationToken::new();
+        // Scrapes start at 0, 200, 400 and 600ms, the missed ticks in between are skipped.
+        tokio::select! {
+            _ = watcher.run(token.clone()) => panic!("The watcher stopped on its own"),
+            _ = tokio::time::sleep(Duration::from_millis(700)) => token.cancel(),
+        }
+        assert_eq!(repositories_json_requests(&fetcher, "s1.example.org"), 4);
+    }
+
+    #[test]
+    fn test_watch_events_serialize_with_a_tag() {
+        let event = WatchEvent::Changed(ChangeEvent::ServerRecovered {
+            server: "s1.example.org".to_string(),
+        });
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "type": "changed",
+                "data": { "event": "server_recovered", "server": "s1.example.org" },
+            })
+        );
+    }
+}